@@ -0,0 +1,1560 @@
+//! The full-screen app: the task list, the plan and the selected task side by side,
+//! with the journal and the timer as screens of their own.
+
+mod calendar;
+mod form;
+mod gantt;
+mod timer;
+
+use crate::Setup;
+use calendar::Week;
+use chrono::{DateTime, Duration as TimeDelta, Local, NaiveDate, Timelike, Utc};
+use crossterm::{
+	event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+	execute,
+	terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use form::{FormAction, TaskForm};
+use gantt::Gantt;
+use itertools::Itertools;
+use pomeranian::{
+	db::{CTask, Db, EnergyCurve, PlanDiff, ShuffleBudget, ShuffleRecord, TaskFilter},
+	length,
+	notify::Notifications,
+	pomodoro::Pomodoro,
+	scheduler::{Fix, Task},
+	storage::Shared,
+	strategy::{self, Context, Strategy},
+};
+use rand::{thread_rng, Rng};
+use ratatui::{
+	backend::CrosstermBackend,
+	layout::{Constraint, Layout, Rect},
+	style::{Color, Modifier, Style},
+	text::{Line, Span},
+	widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Tabs, Wrap},
+	Frame, Terminal,
+};
+use std::{
+	io::{self, stdout, BufReader, Stdout},
+	sync::Arc,
+	time::Duration,
+};
+
+/// The terminal that the app draws on.
+pub type Term = Terminal<CrosstermBackend<Stdout>>;
+
+const HELP: [&str; 20] = [
+	"1 to 5       switch between the plan, the week, the tasks' timelines, the log and the timer",
+	"Tab          move between the task list and the plan",
+	"↑/↓, j/k     move up and down",
+	"←/→, h/l     show earlier or later days",
+	"t            go back to today in the week and timelines",
+	"↑/↓, j/k     scroll the timelines",
+	"d, w         show a day or a week of the plan",
+	"a            add a task",
+	"e, Enter     edit the selected task",
+	"x, Delete    remove the selected task",
+	"/            filter tasks by project:<name> and tags",
+	"p, P, f      in the plan, pin or unpin a slot, pin the selected task to it, or keep it free",
+	"s            shuffle for a strategy",
+	"r            reschedule everything from scratch, except pinned slots",
+	"u, U         undo and redo",
+	"n            change the energy curve",
+	"c            change settings",
+	"i            import tasks from a calendar link",
+	"m            deal with slots that went by without being worked",
+	"q            quit",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Screen {
+	Plan,
+	Week,
+	Gantt,
+	Log,
+	Timer,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Focus {
+	Tasks,
+	Plan,
+}
+
+/// Something shown over the screen, which takes the keyboard until it's closed.
+enum Popup {
+	/// Adding a task, or editing the one with this ID.
+	Form(Box<TaskForm>, Option<String>),
+	/// Asking before doing something that's hard to take back.
+	Confirm(String, Confirmed),
+	Strategies(ListState),
+	Energy {
+		curve: EnergyCurve,
+		hour: usize,
+	},
+	Settings {
+		selected: ListState,
+		/// The new value of the selected setting, while it's being typed.
+		editing: Option<String>,
+		profile_only: bool,
+	},
+	/// Deciding what to do about the slots of each task that went by without being worked.
+	Missed(ListState),
+	/// Typing a line of text.
+	Prompt(&'static str, String, Prompted),
+	/// Text to read, like the help or the metrics of a shuffle.
+	Message(String, Vec<String>),
+}
+
+enum Confirmed {
+	Remove(String),
+	Reschedule,
+	/// Saving a task from the form, which is opened again if the user says no.
+	Save {
+		form: Box<TaskForm>,
+		task: Box<CTask>,
+		id: Option<String>,
+	},
+}
+
+enum Prompted {
+	Filter,
+	Import,
+	/// How much of the missed slots of the task with this ID were done after all.
+	PartlyDone(String),
+}
+
+/// One line of the plan.
+enum Row {
+	Day(NaiveDate),
+	Slot(DateTime<Utc>, Option<String>),
+	Break(DateTime<Utc>, Pomodoro),
+}
+
+struct App<'a> {
+	storage: &'a mut Shared,
+	db: &'a mut Db,
+	setup: &'a mut Setup,
+	screen: Screen,
+	focus: Focus,
+	filter: String,
+	tasks: ListState,
+	plan: ListState,
+	/// The first day of the plan that's shown.
+	day: NaiveDate,
+	week: bool,
+	/// How many tasks are scrolled past in the timelines.
+	scroll: usize,
+	popup: Option<Popup>,
+	status: String,
+	notifications: Notifications,
+	quit: bool,
+}
+
+/// Run the app until the user quits, saving after every change.
+pub fn run(storage: &mut Shared, db: &mut Db, setup: &mut Setup) -> io::Result<()> {
+	enable_raw_mode()?;
+	execute!(stdout(), EnterAlternateScreen)?;
+	let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+	let mut app = App {
+		storage,
+		db,
+		setup,
+		screen: Screen::Plan,
+		focus: Focus::Tasks,
+		filter: String::new(),
+		tasks: ListState::default().with_selected(Some(0)),
+		plan: ListState::default().with_selected(Some(0)),
+		day: Local::now().date_naive(),
+		week: false,
+		scroll: 0,
+		popup: None,
+		status: "Press ? for help".into(),
+		notifications: Notifications::desktop(),
+		quit: false,
+	};
+	let result = app.main_loop(&mut terminal);
+	disable_raw_mode()?;
+	execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+	terminal.show_cursor()?;
+	result
+}
+
+impl App<'_> {
+	fn main_loop(&mut self, terminal: &mut Term) -> io::Result<()> {
+		self.changed();
+		if !self.db.missed.is_empty() {
+			self.popup = Some(Popup::Missed(ListState::default().with_selected(Some(0))));
+		}
+		while !self.quit {
+			if let Some(e) = self.notifications.errors.try_iter().last() {
+				self.status = format!("Couldn't show a notification: {e}");
+			}
+			terminal.draw(|frame| self.draw(frame))?;
+			// Redraw every so often anyway, to keep the plan up to date
+			if !event::poll(Duration::from_secs(30))? {
+				continue;
+			}
+			if let Event::Key(key) = event::read()? {
+				if key.kind == KeyEventKind::Press {
+					self.handle(key, terminal)?;
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/// Tidy up, save and plan notifications after the database has changed.
+	fn changed(&mut self) {
+		self.db.housekeeping();
+		match self.storage.save(self.db) {
+			Ok(true) => self.status = "Merged in changes from another pomeranian".into(),
+			Ok(false) => {}
+			Err(e) => self.status = format!("Couldn't save: {e}"),
+		}
+		self.notifications
+			.outbox
+			.plan(timer::notices(self.db, &self.setup.settings, Utc::now()));
+	}
+
+	/// The tasks that pass the filter, by name.
+	fn visible_tasks(&self) -> Vec<(&String, &Arc<CTask>)> {
+		let filter: TaskFilter = self.filter.parse().expect("Infallible");
+		self.db
+			.tasks
+			.iter()
+			.filter(|(_, task)| filter.matches(task))
+			.sorted_by_key(|(id, task)| (&task.name, *id))
+			.collect()
+	}
+
+	fn selected_task(&self) -> Option<(String, Arc<CTask>)> {
+		self.visible_tasks()
+			.get(self.tasks.selected()?)
+			.map(|(id, task)| ((*id).clone(), (*task).clone()))
+	}
+
+	/// The slots and breaks on the days being shown, with a heading for each day.
+	fn plan_rows(&self) -> Vec<Row> {
+		let days = if self.week { 7 } else { 1 };
+		let day = |time: DateTime<Utc>| time.with_timezone(&Local).date_naive();
+		let shown = |time: &DateTime<Utc>| {
+			(self.day..self.day + TimeDelta::days(days)).contains(&day(*time))
+		};
+		let slots = self
+			.db
+			.slots
+			.iter()
+			.filter(|(time, _)| shown(time))
+			.map(|(time, id)| Row::Slot(*time, id.clone()));
+		let breaks = self
+			.db
+			.pomodoro_states
+			.iter()
+			.filter(|(time, state)| !matches!(state, Pomodoro::Work(_)) && shown(&time.start))
+			.map(|(time, state)| Row::Break(time.start, *state));
+		let mut rows = vec![];
+		for row in slots.merge_by(breaks, |a, b| row_time(a) <= row_time(b)) {
+			let date = day(row_time(&row));
+			if self.week && rows.last().is_none_or(|last| day(row_time(last)) != date) {
+				rows.push(Row::Day(date));
+			}
+			rows.push(row);
+		}
+		rows
+	}
+
+	fn handle(&mut self, key: KeyEvent, terminal: &mut Term) -> io::Result<()> {
+		if let Some(popup) = self.popup.take() {
+			self.handle_popup(popup, key, terminal)?;
+			return Ok(());
+		}
+		let control = key.modifiers.contains(KeyModifiers::CONTROL);
+		match key.code {
+			KeyCode::Char('q') => self.quit = true,
+			KeyCode::Char('c') if control => self.quit = true,
+			KeyCode::Char('1') => self.screen = Screen::Plan,
+			KeyCode::Char('2') => self.screen = Screen::Week,
+			KeyCode::Char('3') => self.screen = Screen::Gantt,
+			KeyCode::Char('4') => self.screen = Screen::Log,
+			KeyCode::Char('5') => self.screen = Screen::Timer,
+			KeyCode::Char('?') => {
+				self.popup = Some(Popup::Message(
+					"Keys".into(),
+					HELP.iter().map(ToString::to_string).collect(),
+				));
+			}
+			KeyCode::Char('u') => {
+				self.status = match self.db.undo() {
+					Some(operation) => format!("Undid {operation}"),
+					None => "Nothing to undo".into(),
+				};
+				self.changed();
+			}
+			KeyCode::Char('U' | 'r') if control || key.code == KeyCode::Char('U') => {
+				self.status = match self.db.redo() {
+					Some(operation) => format!("Redid {operation}"),
+					None => "Nothing to redo".into(),
+				};
+				self.changed();
+			}
+			_ => match self.screen {
+				Screen::Plan => self.handle_plan(key),
+				Screen::Week | Screen::Gantt => self.handle_chart(key),
+				Screen::Log => {}
+				Screen::Timer => {
+					if key.code == KeyCode::Enter {
+						self.run_timer(terminal)?;
+					}
+				}
+			},
+		}
+		Ok(())
+	}
+
+	fn handle_plan(&mut self, key: KeyEvent) {
+		let step = TimeDelta::days(if self.week { 7 } else { 1 });
+		match key.code {
+			KeyCode::Tab | KeyCode::BackTab => {
+				self.focus = match self.focus {
+					Focus::Tasks => Focus::Plan,
+					Focus::Plan => Focus::Tasks,
+				};
+			}
+			KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+			KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+			KeyCode::PageDown => self.move_selection(10),
+			KeyCode::PageUp => self.move_selection(-10),
+			KeyCode::Left | KeyCode::Char('h') => {
+				self.day -= step;
+				self.plan.select(Some(0));
+			}
+			KeyCode::Right | KeyCode::Char('l') => {
+				self.day += step;
+				self.plan.select(Some(0));
+			}
+			KeyCode::Char('d') => self.week = false,
+			KeyCode::Char('w') => self.week = true,
+			KeyCode::Char('a') => {
+				self.popup = Some(Popup::Form(
+					Box::new(TaskForm::new(None, self.db.timeslice_length)),
+					None,
+				));
+			}
+			KeyCode::Enter if self.focus == Focus::Plan => self.select_slot_task(),
+			KeyCode::Char(key @ ('p' | 'P' | 'f')) => self.pin(key),
+			KeyCode::Char('e') | KeyCode::Enter => {
+				if let Some((id, task)) = self.selected_task() {
+					self.popup = Some(Popup::Form(
+						Box::new(TaskForm::new(Some(&task), self.db.timeslice_length)),
+						Some(id),
+					));
+				}
+			}
+			KeyCode::Char('x') | KeyCode::Delete => {
+				if let Some((id, task)) = self.selected_task() {
+					self.popup = Some(Popup::Confirm(
+						format!("Remove {}?", task.name),
+						Confirmed::Remove(id),
+					));
+				}
+			}
+			KeyCode::Char('r') => {
+				self.popup = Some(Popup::Confirm(
+					"Throw away the plan and the last shuffle, and schedule everything from scratch around the pinned slots?".into(),
+					Confirmed::Reschedule,
+				));
+			}
+			KeyCode::Char('/') => {
+				self.popup = Some(Popup::Prompt(
+					"Filter (project:<name> and tags, or empty for everything)",
+					self.filter.clone(),
+					Prompted::Filter,
+				));
+			}
+			KeyCode::Char('i') => {
+				self.popup = Some(Popup::Prompt(
+					"Calendar link",
+					String::new(),
+					Prompted::Import,
+				));
+			}
+			KeyCode::Char('s') => {
+				self.popup = Some(Popup::Strategies(
+					ListState::default().with_selected(Some(0)),
+				));
+			}
+			KeyCode::Char('n') => {
+				self.popup = Some(Popup::Energy {
+					curve: self.db.energy_curve,
+					hour: self.db.active_period.start.hour() as usize,
+				});
+			}
+			KeyCode::Char('c') => {
+				self.popup = Some(Popup::Settings {
+					selected: ListState::default().with_selected(Some(0)),
+					editing: None,
+					profile_only: false,
+				});
+			}
+			KeyCode::Char('m') if self.db.missed.is_empty() => {
+				self.status = "No slots have gone by without being worked".into();
+			}
+			KeyCode::Char('m') => {
+				self.popup = Some(Popup::Missed(ListState::default().with_selected(Some(0))));
+			}
+			_ => {}
+		}
+	}
+
+	/// Move the week or the timelines around.
+	fn handle_chart(&mut self, key: KeyEvent) {
+		let step = TimeDelta::days(if self.screen == Screen::Week { 7 } else { 1 });
+		match key.code {
+			KeyCode::Left | KeyCode::Char('h') => self.day -= step,
+			KeyCode::Right | KeyCode::Char('l') => self.day += step,
+			KeyCode::Char('t') => self.day = Local::now().date_naive(),
+			KeyCode::Down | KeyCode::Char('j') => {
+				self.scroll = (self.scroll + 1).min(self.db.tasks.len().saturating_sub(1));
+			}
+			KeyCode::Up | KeyCode::Char('k') => self.scroll = self.scroll.saturating_sub(1),
+			_ => {}
+		}
+	}
+
+	fn move_selection(&mut self, by: isize) {
+		let (state, length) = match self.focus {
+			Focus::Tasks => {
+				let length = self.visible_tasks().len();
+				(&mut self.tasks, length)
+			}
+			Focus::Plan => {
+				let length = self.plan_rows().len();
+				(&mut self.plan, length)
+			}
+		};
+		let selected = state
+			.selected()
+			.unwrap_or(0)
+			.saturating_add_signed(by)
+			.min(length.saturating_sub(1));
+		state.select(Some(selected));
+	}
+
+	/// Select the task in the highlighted slot of the plan.
+	fn select_slot_task(&mut self) {
+		let rows = self.plan_rows();
+		let Some(Row::Slot(_, Some(id))) = self.plan.selected().and_then(|index| rows.get(index))
+		else {
+			return;
+		};
+		if let Some(index) = self
+			.visible_tasks()
+			.iter()
+			.position(|(other, _)| *other == id)
+		{
+			self.tasks.select(Some(index));
+			self.focus = Focus::Tasks;
+		}
+	}
+
+	/// Pin or unpin the highlighted slot of the plan, as it is, with the selected task in it, or kept free.
+	fn pin(&mut self, key: char) {
+		let rows = self.plan_rows();
+		let selected = self.plan.selected().and_then(|index| rows.get(index));
+		let (Focus::Plan, Some(Row::Slot(time, current))) = (self.focus, selected) else {
+			self.status = "Pick a slot in the plan to pin it, after Tab to move to the plan".into();
+			return;
+		};
+		let time = *time;
+		let when = time.with_timezone(&Local).format("%a %H:%M");
+		let pin = match key {
+			'p' if self.db.is_pinned(time) => None,
+			'p' => Some(current.clone()),
+			'P' => {
+				let Some((id, task)) = self.selected_task() else {
+					self.status = "Select a task in the task list to pin it here".into();
+					return;
+				};
+				if !task.allowed_period().contains(&time) {
+					self.status = format!("{} can't be worked on at {when}", task.name);
+					return;
+				}
+				Some(Some(id))
+			}
+			_ => Some(None),
+		};
+		if let Some(id) = pin {
+			let name = id
+				.as_ref()
+				.and_then(|id| self.db.tasks.get(id))
+				.map_or("free time".into(), |task| task.name.clone());
+			self.db.record(format!("Pin {name} at {when}"));
+			self.change_plan(format!("Pinned {name} at {when}"), |db| {
+				db.pin_slot(time, id);
+			});
+		} else {
+			self.db.record(format!("Unpin {when}"));
+			self.change_plan(format!("Unpinned {when}"), |db| {
+				db.unpin_slot(time);
+			});
+		}
+		self.changed();
+	}
+
+	fn handle_popup(&mut self, popup: Popup, key: KeyEvent, terminal: &mut Term) -> io::Result<()> {
+		match popup {
+			Popup::Form(mut form, id) => match form.handle(key) {
+				FormAction::Continue => self.popup = Some(Popup::Form(form, id)),
+				FormAction::Cancel => {}
+				FormAction::Submit => match form.task() {
+					Ok(task) => self.confirm_save(form, task, id),
+					Err(error) => {
+						form.error = Some(error);
+						self.popup = Some(Popup::Form(form, id));
+					}
+				},
+			},
+			Popup::Confirm(question, confirmed) => match key.code {
+				KeyCode::Char('y') => self.confirmed(confirmed),
+				KeyCode::Char('n') | KeyCode::Esc => {
+					if let Confirmed::Save { form, id, .. } = confirmed {
+						self.popup = Some(Popup::Form(form, id));
+					}
+				}
+				_ => self.popup = Some(Popup::Confirm(question, confirmed)),
+			},
+			Popup::Strategies(mut selected) => {
+				let strategies = self.setup.config.all_strategies();
+				match key.code {
+					KeyCode::Down | KeyCode::Char('j') => {
+						selected.select(
+							selected
+								.selected()
+								.map(|i| (i + 1).min(strategies.len() - 1)),
+						);
+					}
+					KeyCode::Up | KeyCode::Char('k') => {
+						selected.select(selected.selected().map(|i| i.saturating_sub(1)));
+					}
+					KeyCode::Esc => return Ok(()),
+					KeyCode::Enter => {
+						if let Some(strategy) = selected.selected().and_then(|i| strategies.get(i))
+						{
+							self.status = format!("Shuffling for {}...", strategy.name);
+							terminal.draw(|frame| self.draw(frame))?;
+							self.shuffle(strategy);
+						}
+						return Ok(());
+					}
+					_ => {}
+				}
+				self.popup = Some(Popup::Strategies(selected));
+			}
+			Popup::Energy {
+				mut curve,
+				mut hour,
+			} => {
+				let hours = self.db.active_period.start.hour() as usize
+					..=self.db.active_period.end.hour() as usize;
+				match key.code {
+					KeyCode::Down | KeyCode::Char('j') => hour = (hour + 1).min(*hours.end()),
+					KeyCode::Up | KeyCode::Char('k') => {
+						hour = hour.saturating_sub(1).max(*hours.start());
+					}
+					KeyCode::Right | KeyCode::Char('l') => {
+						curve.0[hour] = (curve.0[hour] + 1).min(10);
+					}
+					KeyCode::Left | KeyCode::Char('h') => {
+						curve.0[hour] = curve.0[hour].saturating_sub(1);
+					}
+					KeyCode::Esc => return Ok(()),
+					KeyCode::Enter => {
+						self.db.energy_curve = curve;
+						self.db.record("Move tasks to match the energy curve");
+						let swaps = self.db.place_by_energy();
+						self.status = format!("Moved tasks around {swaps} times to match");
+						self.changed();
+						return Ok(());
+					}
+					_ => {}
+				}
+				self.popup = Some(Popup::Energy { curve, hour });
+			}
+			Popup::Settings {
+				selected,
+				editing,
+				profile_only,
+			} => self.handle_settings(key, selected, editing, profile_only),
+			Popup::Missed(selected) => self.handle_missed(key, selected),
+			Popup::Prompt(title, mut text, prompted) => match key.code {
+				KeyCode::Esc => {}
+				KeyCode::Enter => self.prompted(text, prompted, terminal)?,
+				KeyCode::Backspace => {
+					text.pop();
+					self.popup = Some(Popup::Prompt(title, text, prompted));
+				}
+				KeyCode::Char(c) => {
+					text.push(c);
+					self.popup = Some(Popup::Prompt(title, text, prompted));
+				}
+				_ => self.popup = Some(Popup::Prompt(title, text, prompted)),
+			},
+			Popup::Message(..) => {}
+		}
+		Ok(())
+	}
+
+	fn handle_settings(
+		&mut self,
+		key: KeyEvent,
+		mut selected: ListState,
+		editing: Option<String>,
+		mut profile_only: bool,
+	) {
+		let entries = self.setup.settings.entries();
+		let editing = match (editing, key.code) {
+			(None, KeyCode::Esc) => return,
+			(None, KeyCode::Down | KeyCode::Char('j')) => {
+				selected.select(selected.selected().map(|i| (i + 1).min(entries.len() - 1)));
+				None
+			}
+			(None, KeyCode::Up | KeyCode::Char('k')) => {
+				selected.select(selected.selected().map(|i| i.saturating_sub(1)));
+				None
+			}
+			(None, KeyCode::Char('p')) => {
+				profile_only = !profile_only && self.setup.profile.is_some();
+				None
+			}
+			(None, KeyCode::Enter) => selected
+				.selected()
+				.and_then(|i| entries.get(i))
+				.map(|(_, value)| value.clone()),
+			(None, _) | (Some(_), KeyCode::Esc) => None,
+			(Some(mut value), KeyCode::Backspace) => {
+				value.pop();
+				Some(value)
+			}
+			(Some(mut value), KeyCode::Char(c)) => {
+				value.push(c);
+				Some(value)
+			}
+			(Some(value), KeyCode::Enter) => {
+				if let Some((name, _)) = selected.selected().and_then(|i| entries.get(i)) {
+					match self
+						.setup
+						.change_setting(self.db, name, &value, profile_only)
+					{
+						Ok(notes) if notes.is_empty() => self.status = format!("Changed {name}"),
+						Ok(notes) => self.status = notes.join(". "),
+						Err(e) => self.status = e,
+					}
+					self.changed();
+				}
+				None
+			}
+			(editing, _) => editing,
+		};
+		self.popup = Some(Popup::Settings {
+			selected,
+			editing,
+			profile_only,
+		});
+	}
+
+	/// Put a task's missed slots back in the plan, or count them as done, all or in part.
+	fn handle_missed(&mut self, key: KeyEvent, mut selected: ListState) {
+		let totals = self.db.missed_totals();
+		let Some(id) = selected
+			.selected()
+			.and_then(|i| totals.keys().nth(i))
+			.cloned()
+		else {
+			return;
+		};
+		let name = self.db.tasks[&id].name.clone();
+		match key.code {
+			KeyCode::Down | KeyCode::Char('j') => {
+				selected.select(selected.selected().map(|i| (i + 1).min(totals.len() - 1)));
+			}
+			KeyCode::Up | KeyCode::Char('k') => {
+				selected.select(selected.selected().map(|i| i.saturating_sub(1)));
+			}
+			KeyCode::Esc => return,
+			KeyCode::Enter | KeyCode::Char('r') => {
+				self.db
+					.record(format!("Reschedule the missed slots of {name}"));
+				self.change_plan(format!("Put {name}'s missed work back in the plan"), |db| {
+					db.resolve_missed(&id, Duration::ZERO);
+				});
+				self.changed();
+			}
+			KeyCode::Char('d') => {
+				self.db
+					.record(format!("Count the missed slots of {name} as done"));
+				self.change_plan(format!("Counted {name}'s missed slots as done"), |db| {
+					db.resolve_missed(&id, Duration::MAX);
+				});
+				self.changed();
+			}
+			KeyCode::Char('p') => {
+				self.popup = Some(Popup::Prompt(
+					"How much of it was done? (like 30m or 1 pomodoro)",
+					String::new(),
+					Prompted::PartlyDone(id),
+				));
+				return;
+			}
+			_ => {}
+		}
+		self.missed_again(selected);
+	}
+
+	/// Keep asking about missed slots while there are any left.
+	fn missed_again(&mut self, mut selected: ListState) {
+		let left = self.db.missed_totals().len();
+		if left > 0 {
+			selected.select(selected.selected().map(|i| i.min(left - 1)));
+			self.popup = Some(Popup::Missed(selected));
+		}
+	}
+
+	/// Show how saving a task would change the plan before saving it.
+	fn confirm_save(&mut self, form: Box<TaskForm>, task: CTask, id: Option<String>) {
+		let diff = self.db.what_if(|db| match &id {
+			Some(id) => db.replace_task(id, task.clone()),
+			None => db.insert_task(db.new_id(), task.clone()),
+		});
+		let question = match id {
+			Some(_) => format!("Save the changes to {}?", task.name),
+			None => format!("Add {}?", task.name),
+		};
+		let question = std::iter::once(question)
+			.chain(std::iter::once(String::new()))
+			.chain(diff_lines(&diff))
+			.join("\n");
+		self.popup = Some(Popup::Confirm(
+			question,
+			Confirmed::Save {
+				form,
+				task: Box::new(task),
+				id,
+			},
+		));
+	}
+
+	fn save_task(&mut self, task: CTask, id: Option<String>) {
+		if let Some(id) = id {
+			self.db.record(format!("Edit {}", task.name));
+			self.change_plan(format!("Changed {}", task.name), |db| {
+				db.replace_task(&id, task);
+			});
+		} else {
+			self.db.record(format!("Add {}", task.name));
+			self.change_plan(format!("Added {}", task.name), |db| {
+				db.insert_task(db.new_id(), task);
+			});
+		}
+	}
+
+	/// Change the plan, and say what happened and how many planned slots it moved.
+	fn change_plan(&mut self, status: String, change: impl FnOnce(&mut Db)) {
+		let before = self.db.schedule.clone();
+		change(self.db);
+		let moved = PlanDiff::between(&before, &self.db.schedule).slots_moved;
+		self.status = status + &moved_note(moved);
+	}
+
+	fn confirmed(&mut self, confirmed: Confirmed) {
+		match confirmed {
+			Confirmed::Remove(id) => {
+				let name = self.db.tasks[&id].name.clone();
+				self.db.record(format!("Remove {name}"));
+				self.change_plan(format!("Removed {name}"), |db| {
+					db.remove_task(&id);
+				});
+				self.move_selection(0);
+			}
+			Confirmed::Reschedule => {
+				self.db.record("Reschedule");
+				let moved = self.db.reschedule();
+				self.status = format!("Rescheduled everything{}", moved_note(moved));
+			}
+			Confirmed::Save { task, id, .. } => self.save_task(*task, id),
+		}
+		self.changed();
+	}
+
+	fn prompted(
+		&mut self,
+		text: String,
+		prompted: Prompted,
+		terminal: &mut Term,
+	) -> io::Result<()> {
+		match prompted {
+			Prompted::Filter => {
+				self.filter = text;
+				self.tasks.select(Some(0));
+			}
+			Prompted::Import => {
+				self.status = "Downloading...".into();
+				terminal.draw(|frame| self.draw(frame))?;
+				match reqwest::blocking::get(text) {
+					Ok(calendar) => {
+						self.db.record("Import from Blackboard");
+						let added = self.db.import_calendar(
+							BufReader::new(calendar),
+							self.setup.settings.import_estimate(),
+						);
+						self.status = format!("Imported {added} tasks");
+						self.changed();
+					}
+					Err(e) => self.status = format!("Couldn't download the calendar: {e}"),
+				}
+			}
+			Prompted::PartlyDone(id) => {
+				match length::parse(&text, self.db.timeslice_length) {
+					Ok(done) => {
+						let name = self.db.tasks[&id].name.clone();
+						self.db
+							.record(format!("Count some missed slots of {name} as done"));
+						let before = self.db.schedule.clone();
+						let credited = self.db.resolve_missed(&id, done);
+						let moved = PlanDiff::between(&before, &self.db.schedule).slots_moved;
+						self.status = format!(
+							"Counted {} of {name}'s missed slots as done{}",
+							length::format(credited),
+							moved_note(moved)
+						);
+						self.changed();
+					}
+					Err(e) => self.status = e.to_string(),
+				}
+				self.missed_again(ListState::default().with_selected(Some(0)));
+			}
+		}
+		Ok(())
+	}
+
+	fn shuffle(&mut self, strategy: &Strategy) {
+		self.db.record(format!("Shuffle for {}", strategy.name));
+		let context = Context::from(&*self.db);
+		let ShuffleRecord {
+			seed,
+			iterations,
+			score,
+			..
+		} = self.db.shuffle_seeded(
+			&strategy.name,
+			|s| strategy.score(s, &context),
+			ShuffleBudget::Time(self.setup.settings.shuffle_time()),
+			thread_rng().gen(),
+		);
+		self.status = format!("Shuffled for {}", strategy.name);
+		let mut lines = vec![format!(
+			"Scored {score} after trying {iterations} times (seed {seed})"
+		)];
+		lines.extend(metric_lines(self.db, strategy));
+		self.popup = Some(Popup::Message(strategy.name.clone(), lines));
+		self.changed();
+	}
+
+	fn run_timer(&mut self, terminal: &mut Term) -> io::Result<()> {
+		let finished = timer::timer(self.db, &self.setup.settings, terminal, &self.notifications)?;
+		terminal.clear()?;
+		self.status = if finished {
+			"Done working today!".into()
+		} else {
+			"Stopped the timer".into()
+		};
+		self.changed();
+		Ok(())
+	}
+
+	fn draw(&mut self, frame: &mut Frame) {
+		let [tabs, body, status] = Layout::vertical([
+			Constraint::Length(1),
+			Constraint::Min(1),
+			Constraint::Length(1),
+		])
+		.areas(frame.size());
+
+		let title = match &self.setup.profile {
+			Some(profile) => format!("pomeranian ({profile})"),
+			None => "pomeranian".into(),
+		};
+		frame.render_widget(
+			Tabs::new(["1 Plan", "2 Week", "3 Timelines", "4 Log", "5 Timer"])
+				.select(self.screen as usize)
+				.highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+				.block(Block::default().title(title)),
+			tabs,
+		);
+		match self.screen {
+			Screen::Plan => self.draw_plan_screen(frame, body),
+			Screen::Week => {
+				let block = Block::default()
+					.borders(Borders::ALL)
+					.title(format!("Week from {}", self.day.format("%a %d %b")));
+				frame.render_widget(
+					Week {
+						db: self.db,
+						first_day: self.day,
+					},
+					block.inner(body),
+				);
+				frame.render_widget(block, body);
+			}
+			Screen::Gantt => {
+				let block = Block::default().borders(Borders::ALL).title(format!(
+					"Tasks from {}, by due date (┃ marks the due date)",
+					self.day.format("%a %d %b")
+				));
+				frame.render_widget(
+					Gantt {
+						db: self.db,
+						first_day: self.day,
+						scroll: self.scroll,
+					},
+					block.inner(body),
+				);
+				frame.render_widget(block, body);
+			}
+			Screen::Log => {
+				let lines = log_lines(self.db);
+				let lines = if lines.is_empty() {
+					vec!["Nothing has been done yet".into()]
+				} else {
+					lines
+				};
+				frame.render_widget(
+					List::new(lines).block(Block::default().borders(Borders::ALL).title("Log")),
+					body,
+				);
+			}
+			Screen::Timer => self.draw_timer_screen(frame, body),
+		}
+		frame.render_widget(
+			Paragraph::new(self.status.as_str()).style(Style::new().fg(Color::DarkGray)),
+			status,
+		);
+		self.draw_popup(frame);
+	}
+
+	fn draw_plan_screen(&mut self, frame: &mut Frame, area: Rect) {
+		let [tasks, right] =
+			Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)])
+				.areas(area);
+		let [plan, detail] =
+			Layout::vertical([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(right);
+		let focused = |focus| {
+			let block = Block::default().borders(Borders::ALL);
+			if self.focus == focus {
+				block.border_style(Style::new().fg(Color::Yellow))
+			} else {
+				block
+			}
+		};
+
+		let items = self
+			.visible_tasks()
+			.into_iter()
+			.map(|(id, task)| {
+				let late = self.db.unsatisfied_tasks().contains(id.as_str());
+				let line = Line::from(vec![
+					Span::styled(
+						format!("{} ", self.db.short_id(id)),
+						Style::new().fg(Color::DarkGray),
+					),
+					Span::raw(task.name.clone()),
+					Span::styled(
+						format!("  {}", length::remaining(task, self.db.timeslice_length)),
+						Style::new().fg(Color::DarkGray),
+					),
+				]);
+				ListItem::new(line).style(if late {
+					Style::new().fg(Color::Red)
+				} else {
+					Style::new()
+				})
+			})
+			.collect_vec();
+		let title = if self.filter.is_empty() {
+			"Tasks".to_string()
+		} else {
+			format!("Tasks ({})", self.filter)
+		};
+		frame.render_stateful_widget(
+			List::new(items)
+				.block(focused(Focus::Tasks).title(title))
+				.highlight_style(Style::new().add_modifier(Modifier::REVERSED)),
+			tasks,
+			&mut self.tasks,
+		);
+
+		let selected = self.selected_task().map(|(id, _)| id);
+		let items = self
+			.plan_rows()
+			.into_iter()
+			.map(|row| self.plan_item(&row, selected.as_deref()))
+			.collect_vec();
+		let title = if self.week {
+			format!("Week from {}", self.day.format("%a %d %b"))
+		} else {
+			self.day.format("%A %d %B").to_string()
+		};
+		frame.render_stateful_widget(
+			List::new(items)
+				.block(focused(Focus::Plan).title(title))
+				.highlight_style(Style::new().add_modifier(Modifier::REVERSED)),
+			plan,
+			&mut self.plan,
+		);
+
+		frame.render_widget(
+			Paragraph::new(self.detail_lines())
+				.wrap(Wrap { trim: false })
+				.block(Block::default().borders(Borders::ALL).title("Task")),
+			detail,
+		);
+	}
+
+	fn plan_item(&self, row: &Row, selected: Option<&str>) -> ListItem<'static> {
+		let time = |time: &DateTime<Utc>| time.with_timezone(&Local).format("%H:%M").to_string();
+		match row {
+			Row::Day(date) => ListItem::new(date.format("%A %d %B").to_string())
+				.style(Style::new().add_modifier(Modifier::BOLD)),
+			Row::Break(start, state) => ListItem::new(format!(
+				"{}  {}",
+				time(start),
+				if *state == Pomodoro::LongBreak {
+					"Long break"
+				} else {
+					"Break"
+				}
+			))
+			.style(Style::new().fg(Color::DarkGray)),
+			Row::Slot(start, id) => {
+				let energy = self.db.energy_curve.at(*start);
+				let task = id.as_ref().and_then(|id| self.db.tasks.get(id));
+				let text = match task {
+					Some(task) if task.energy.is_some_and(|needed| needed > energy) => format!(
+						"{} (needs {} energy, has {energy})",
+						task.name,
+						task.energy.unwrap_or_default()
+					),
+					Some(task) => task.name.clone(),
+					None => "Free".into(),
+				};
+				let swatch = match id {
+					Some(id) => Span::styled("█ ", Style::new().fg(task_color(id))),
+					None => Span::raw("  "),
+				};
+				let style = if id.is_some() && id.as_deref() == selected {
+					Style::new().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+				} else if task.is_none() {
+					Style::new().fg(Color::DarkGray)
+				} else {
+					Style::new()
+				};
+				let mut spans = vec![
+					Span::raw(format!("{}  ", time(start))),
+					swatch,
+					Span::raw(text),
+				];
+				if self.db.is_pinned(*start) {
+					spans.push(Span::styled("  pinned", Style::new().fg(Color::Cyan)));
+				}
+				ListItem::new(Line::from(spans)).style(style)
+			}
+		}
+	}
+
+	/// Everything about the selected task, and how well it's scheduled.
+	fn detail_lines(&self) -> Vec<Line<'static>> {
+		let Some((id, task)) = self.selected_task() else {
+			return vec![Line::raw("No task selected. Press a to add one.")];
+		};
+		let time = |time: DateTime<Utc>| {
+			time.with_timezone(&Local)
+				.format("%a %d %b %H:%M")
+				.to_string()
+		};
+		let mut lines = vec![
+			Line::styled(task.name.clone(), Style::new().add_modifier(Modifier::BOLD)),
+			Line::raw(format!("ID {}", self.db.short_id(&id))),
+			Line::raw(format!(
+				"From {} to {}",
+				time(task.working_period.start),
+				time(task.working_period.end)
+			)),
+			Line::raw(format!(
+				"{}, priority {}",
+				length::progress(&task, self.db.timeslice_length),
+				task.priority
+			)),
+		];
+		if task.project.is_some() || !task.tags.is_empty() {
+			lines.push(Line::raw(format!(
+				"{} {}",
+				task.project
+					.as_deref()
+					.map_or(String::new(), |project| format!("project:{project}")),
+				task.tags.iter().join(" ")
+			)));
+		}
+		if let Some(energy) = task.energy {
+			lines.push(Line::raw(format!("Needs {energy} energy")));
+		}
+		let warning = Style::new().fg(Color::Red);
+		for (index, line) in explanation_lines(self.db, &id).into_iter().enumerate() {
+			lines.push(if index == 0 {
+				Line::raw(line)
+			} else {
+				Line::styled(line, warning)
+			});
+		}
+		if let Some(outcome) = self.db.outcomes().get(&id) {
+			if outcome.late_slots > 0 {
+				lines.push(Line::styled(
+					format!(
+						"{} slots after the due date, finishing {} late",
+						outcome.late_slots,
+						length::format(outcome.lateness)
+					),
+					warning,
+				));
+			}
+		}
+		lines
+	}
+
+	fn draw_timer_screen(&self, frame: &mut Frame, area: Rect) {
+		let time = |time: DateTime<Utc>| time.with_timezone(&Local).format("%a %H:%M").to_string();
+		let mut lines = vec![
+			Line::raw("Press Enter to start the timer, and q in the timer to stop it."),
+			Line::default(),
+		];
+		for (period, state) in self.db.pomodoro_states.iter().take(20) {
+			let what = match state {
+				Pomodoro::Work(_) => self
+					.db
+					.slots
+					.get(&period.start)
+					.and_then(|id| self.db.tasks.get(id.as_ref()?))
+					.map_or("Free".to_string(), |task| task.name.clone()),
+				Pomodoro::Break(_) => "Break".into(),
+				Pomodoro::LongBreak => "Long break".into(),
+			};
+			lines.push(Line::raw(format!(
+				"{} to {}  {what}",
+				time(period.start),
+				time(period.end)
+			)));
+		}
+		frame.render_widget(
+			Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Timer")),
+			area,
+		);
+	}
+
+	fn draw_popup(&self, frame: &mut Frame) {
+		let Some(popup) = &self.popup else {
+			return;
+		};
+		let area = match popup {
+			Popup::Confirm(..) | Popup::Prompt(..) => centered(frame.size(), 60, 20),
+			_ => centered(frame.size(), 80, 80),
+		};
+		let block = |title: &str| {
+			Block::default()
+				.borders(Borders::ALL)
+				.title(title.to_string())
+		};
+		frame.render_widget(Clear, area);
+		match popup {
+			Popup::Form(form, id) => {
+				let title = match id {
+					Some(id) => format!("Edit {}", self.db.tasks[id].name),
+					None => "New task".into(),
+				};
+				form.draw(frame, area, &title);
+			}
+			Popup::Confirm(question, _) => {
+				frame.render_widget(
+					Paragraph::new(
+						question
+							.lines()
+							.map(|line| Line::raw(line.to_string()))
+							.chain([Line::default(), hint("y or n")])
+							.collect_vec(),
+					)
+					.wrap(Wrap { trim: false })
+					.block(block("Are you sure?")),
+					area,
+				);
+			}
+			Popup::Strategies(selected) => {
+				let items = self
+					.setup
+					.config
+					.all_strategies()
+					.into_iter()
+					.map(|s| format!("{} ({})", s.name, s.description))
+					.collect_vec();
+				frame.render_stateful_widget(
+					List::new(items)
+						.block(block("Which strategy?"))
+						.highlight_style(Style::new().add_modifier(Modifier::REVERSED)),
+					area,
+					&mut selected.clone(),
+				);
+			}
+			Popup::Energy { curve, hour } => {
+				let lines = self.energy_lines(curve, *hour);
+				frame.render_widget(Paragraph::new(lines).block(block("Energy")), area);
+			}
+			Popup::Settings {
+				selected,
+				editing,
+				profile_only,
+			} => {
+				let lines =
+					self.settings_lines(selected.selected(), editing.as_deref(), *profile_only);
+				frame.render_widget(Paragraph::new(lines).block(block("Settings")), area);
+			}
+			Popup::Missed(selected) => self.draw_missed(frame, area, selected),
+			Popup::Prompt(title, text, _) => {
+				frame.render_widget(
+					Paragraph::new(vec![
+						Line::from(vec![
+							Span::raw(text.clone()),
+							Span::styled("▏", Style::new().fg(Color::Yellow)),
+						]),
+						Line::default(),
+						hint("Enter to accept, Esc to cancel"),
+					])
+					.wrap(Wrap { trim: false })
+					.block(block(title)),
+					area,
+				);
+			}
+			Popup::Message(title, lines) => {
+				let mut lines = lines.iter().cloned().map(Line::raw).collect_vec();
+				lines.push(Line::default());
+				lines.push(hint("Press any key to close"));
+				frame.render_widget(
+					Paragraph::new(lines)
+						.wrap(Wrap { trim: false })
+						.block(block(title)),
+					area,
+				);
+			}
+		}
+	}
+
+	/// The tasks with missed slots, and what can be done about them.
+	fn draw_missed(&self, frame: &mut Frame, area: Rect, selected: &ListState) {
+		let lines = missed_lines(self.db);
+		let [list, keys] =
+			Layout::vertical([Constraint::Min(1), Constraint::Length(3)]).areas(area);
+		frame.render_stateful_widget(
+			List::new(lines)
+				.block(Block::default().borders(Borders::ALL).title("Missed slots"))
+				.highlight_style(Style::new().add_modifier(Modifier::REVERSED)),
+			list,
+			&mut selected.clone(),
+		);
+		frame.render_widget(
+			Paragraph::new(hint(
+				"Enter or r to reschedule it, d if it was done after all, p if some of it was, Esc to decide later",
+			))
+			.wrap(Wrap { trim: false })
+			.block(Block::default().borders(Borders::ALL)),
+			keys,
+		);
+	}
+
+	/// A bar for each hour of the day, with the one being changed highlighted.
+	fn energy_lines(&self, curve: &EnergyCurve, hour: usize) -> Vec<Line<'static>> {
+		let start = self.db.active_period.start.hour() as usize;
+		let end = self.db.active_period.end.hour() as usize;
+		let mut lines = (start..=end)
+			.map(|h| {
+				let level = usize::from(curve.0[h]);
+				let text = format!(
+					"{h:02}:00  {}{}  {level}",
+					"█".repeat(level),
+					"░".repeat(10 - level)
+				);
+				if h == hour {
+					Line::styled(text, Style::new().add_modifier(Modifier::REVERSED))
+				} else {
+					Line::raw(text)
+				}
+			})
+			.collect_vec();
+		lines.push(Line::default());
+		lines.push(hint(
+			"←/→ to change, Enter to move tasks to match, Esc to cancel",
+		));
+		lines
+	}
+
+	/// Every setting, with the selected one highlighted, or its new value if it's being typed.
+	fn settings_lines(
+		&self,
+		selected: Option<usize>,
+		editing: Option<&str>,
+		profile_only: bool,
+	) -> Vec<Line<'static>> {
+		let mut lines = self
+			.setup
+			.settings
+			.entries()
+			.into_iter()
+			.enumerate()
+			.map(|(index, (name, value))| {
+				if Some(index) != selected {
+					return Line::raw(format!("{name} = {value}"));
+				}
+				match editing {
+					Some(value) => Line::from(vec![
+						Span::styled(
+							format!("{name} = "),
+							Style::new().add_modifier(Modifier::BOLD),
+						),
+						Span::raw(value.to_string()),
+						Span::styled("▏", Style::new().fg(Color::Yellow)),
+					]),
+					None => Line::styled(
+						format!("{name} = {value}"),
+						Style::new().add_modifier(Modifier::REVERSED),
+					),
+				}
+			})
+			.collect_vec();
+		lines.push(Line::default());
+		lines.push(Line::raw(match (&self.setup.profile, profile_only) {
+			(Some(profile), true) => {
+				format!("Changes are only for the {profile} profile (p to change)")
+			}
+			(Some(_), false) => "Changes are for every profile (p to change)".into(),
+			(None, _) => "Changes are for every profile".into(),
+		}));
+		lines.push(hint("Enter to change the selected setting, Esc to close"));
+		lines
+	}
+}
+
+/// A line of dimmed text, saying which keys do what.
+fn hint(text: &'static str) -> Line<'static> {
+	Line::styled(text, Style::new().fg(Color::DarkGray))
+}
+
+/// A color for each task, picked from its ID so that it stays the same between screens and runs.
+fn task_color(id: &str) -> Color {
+	const COLORS: [Color; 10] = [
+		Color::Cyan,
+		Color::Green,
+		Color::Yellow,
+		Color::Blue,
+		Color::Magenta,
+		Color::LightCyan,
+		Color::LightGreen,
+		Color::LightYellow,
+		Color::LightBlue,
+		Color::LightMagenta,
+	];
+	let hash = id.bytes().fold(0usize, |hash, byte| {
+		hash.wrapping_mul(31).wrapping_add(byte.into())
+	});
+	COLORS[hash % COLORS.len()]
+}
+
+fn row_time(row: &Row) -> DateTime<Utc> {
+	match row {
+		Row::Day(date) => date
+			.and_hms_opt(0, 0, 0)
+			.and_then(|time| time.and_local_timezone(Local).earliest())
+			.map_or_else(Utc::now, |time| time.with_timezone(&Utc)),
+		Row::Slot(time, _) | Row::Break(time, _) => *time,
+	}
+}
+
+/// A rectangle in the middle of another, taking up a percentage of its width and height.
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+	let [_, area, _] = Layout::vertical([
+		Constraint::Percentage((100 - height) / 2),
+		Constraint::Percentage(height),
+		Constraint::Percentage((100 - height) / 2),
+	])
+	.areas(area);
+	let [_, area, _] = Layout::horizontal([
+		Constraint::Percentage((100 - width) / 2),
+		Constraint::Percentage(width),
+		Constraint::Percentage((100 - width) / 2),
+	])
+	.areas(area);
+	area
+}
+
+/// The value of every metric for the current schedule, marking the ones the strategy cares about.
+pub fn metric_lines(db: &Db, strategy: &Strategy) -> Vec<String> {
+	strategy::measure_all(&db.schedule, &Context::from(db))
+		.into_iter()
+		.map(|(name, value)| match strategy.weights.get(name) {
+			Some(weight) => format!("{name}: {value:.2} (weight {weight})"),
+			None => format!("{name}: {value:.2}"),
+		})
+		.collect()
+}
+
+/// How many slots a task has out of those it could have, and if it's missing any, why and what would fix it.
+/// Only the first line is there when the task has everything it needs.
+pub fn explanation_lines(db: &Db, id: &str) -> Vec<String> {
+	let Some(explanation) = db.explain(id) else {
+		return vec![];
+	};
+	let name = |id: &str| {
+		db.tasks
+			.get(id)
+			.map_or(id.to_string(), |task| task.name.clone())
+	};
+	let pinned = match explanation.pinned {
+		0 => String::new(),
+		pinned => format!(", and {pinned} pinned for something else"),
+	};
+	let mut lines = vec![format!(
+		"Has {} of the {} slots it needs, with {} slots in its window and {} of them free{pinned}",
+		explanation.has, explanation.wants, explanation.available, explanation.free
+	)];
+	let Some(reason) = explanation.reason else {
+		return lines;
+	};
+	lines.push(format!(
+		"Missing {} slots, because {reason}",
+		explanation.wants - explanation.has
+	));
+	if !explanation.competitors.is_empty() {
+		lines.push(format!(
+			"Tasks it can't take slots from: {}",
+			explanation
+				.competitors
+				.iter()
+				.map(|(other, count)| format!("{} ({count})", name(other)))
+				.join(", ")
+		));
+	}
+	let fixes = explanation
+		.fixes
+		.iter()
+		.map(|fix| match fix {
+			Fix::ExtendDeadline(extension) => {
+				format!("make it due {} later", length::format(*extension))
+			}
+			Fix::LowerEstimate(by) => format!("lower its estimate by {}", length::format(*by)),
+			Fix::RaisePriority { above } => format!(
+				"raise its priority above {} ({})",
+				name(above),
+				db.tasks.get(above).map_or(0, |task| task.priority)
+			),
+		})
+		.collect_vec();
+	if let Some((last, rest)) = fixes.split_last() {
+		if rest.is_empty() {
+			lines.push(format!("To fix it, {last}"));
+		} else {
+			lines.push(format!("To fix it, {}, or {last}", rest.join(", ")));
+		}
+	}
+	lines
+}
+
+/// How a change would affect the plan, task by task, with the tasks that would end up missing slots first.
+pub fn diff_lines(diff: &PlanDiff) -> Vec<String> {
+	if diff.is_empty() {
+		return vec!["The plan stays as it is".into()];
+	}
+	let mut lines = diff
+		.tasks
+		.values()
+		.sorted_by_key(|change| (!change.becomes_unsatisfied(), &change.name))
+		.map(|change| {
+			let slots = match (change.before, change.after) {
+				(None, Some(after)) => format!("gets {after} slots"),
+				(Some(before), None) => format!("gives up its {before} slots"),
+				(Some(before), Some(after)) if before != after => {
+					format!("{before} → {after} slots")
+				}
+				(_, after) => format!("keeps its {} slots", after.unwrap_or_default()),
+			};
+			let missing = if change.after.is_none() {
+				String::new()
+			} else if change.becomes_unsatisfied() {
+				format!(", now missing {}", change.missing_after)
+			} else if change.missing_before > 0 && change.missing_after == 0 {
+				", now has all it needs".into()
+			} else if change.missing_after > 0 {
+				format!(", missing {}", change.missing_after)
+			} else {
+				String::new()
+			};
+			format!("{}: {slots}{missing}", change.name)
+		})
+		.collect_vec();
+	match diff.slots_moved {
+		0 => {}
+		1 => lines.push("1 planned slot moves".into()),
+		moved => lines.push(format!("{moved} planned slots move")),
+	}
+	lines
+}
+
+/// The end of a sentence saying how many slots that tasks had were moved, if any were.
+pub fn moved_note(moved: usize) -> String {
+	match moved {
+		0 => String::new(),
+		1 => ", moving 1 planned slot".into(),
+		moved => format!(", moving {moved} planned slots"),
+	}
+}
+
+/// A line for each task with slots that went by without being worked, saying how many and how long they were.
+pub fn missed_lines(db: &Db) -> Vec<String> {
+	db.missed_totals()
+		.into_iter()
+		.map(|(id, (count, length))| {
+			format!(
+				"You missed {count} slot{} of {} ({})",
+				if count == 1 { "" } else { "s" },
+				db.tasks.get(&id).map_or(id.as_str(), |task| &task.name),
+				length::format(length)
+			)
+		})
+		.collect()
+}
+
+/// Recent operations, newest first, including undone ones that can still be redone.
+pub fn log_lines(db: &Db) -> Vec<String> {
+	let time = |time: DateTime<Utc>| time.with_timezone(&Local).format("%a %d %b %H:%M");
+	db.journal
+		.redo
+		.iter()
+		.map(|entry| format!("{}\t{} (undone)", time(entry.time), entry.operation))
+		.chain(
+			db.pending_operation()
+				.into_iter()
+				.chain(
+					db.journal
+						.undo
+						.iter()
+						.rev()
+						.map(|entry| (entry.operation.as_str(), entry.time)),
+				)
+				.map(|(operation, at)| format!("{}\t{operation}", time(at))),
+		)
+		.collect()
+}
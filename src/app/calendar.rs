@@ -0,0 +1,142 @@
+//! A week of the plan as a grid, with a column for each day and a row for every few minutes of the active period.
+
+use super::task_color;
+use chrono::{DateTime, Duration as TimeDelta, Local, NaiveDate, NaiveTime, Utc};
+use pomeranian::{db::Db, pomodoro::Pomodoro};
+use ratatui::{
+	buffer::Buffer,
+	layout::Rect,
+	style::{Color, Modifier, Style},
+	widgets::Widget,
+};
+
+/// How many minutes a row can stand for, picking the shortest that fits the active period.
+const ROW_MINUTES: [i64; 7] = [5, 10, 15, 20, 30, 60, 120];
+/// The width of the column of times on the left.
+const TIME_WIDTH: u16 = 6;
+
+/// Seven days of the plan, starting from `first_day`.
+pub struct Week<'a> {
+	pub db: &'a Db,
+	pub first_day: NaiveDate,
+}
+
+/// What's happening at some moment in the plan.
+#[derive(PartialEq)]
+enum Cell<'a> {
+	Task(&'a str),
+	Free,
+	Break,
+	Nothing,
+}
+
+impl Week<'_> {
+	fn cell(&self, time: DateTime<Utc>) -> Cell<'_> {
+		if let Some((start, id)) = self.db.slots.range(..=time).next_back() {
+			if time < *start + self.db.timeslice_length {
+				return match id {
+					Some(id) => Cell::Task(id),
+					None => Cell::Free,
+				};
+			}
+		}
+		let on_break =
+			self.db.pomodoro_states.iter().any(|(period, state)| {
+				!matches!(state, Pomodoro::Work(_)) && period.contains(&time)
+			});
+		if on_break {
+			Cell::Break
+		} else {
+			Cell::Nothing
+		}
+	}
+}
+
+/// A local time on a day, in UTC.
+fn at(day: NaiveDate, time: NaiveTime) -> Option<DateTime<Utc>> {
+	Some(
+		day.and_time(time)
+			.and_local_timezone(Local)
+			.earliest()?
+			.with_timezone(&Utc),
+	)
+}
+
+impl Widget for Week<'_> {
+	fn render(self, area: Rect, buf: &mut Buffer) {
+		if area.width <= TIME_WIDTH || area.height < 2 {
+			return;
+		}
+		let day_width = (area.width - TIME_WIDTH) / 7;
+		let rows = i64::from(area.height - 1);
+		let period = &self.db.active_period;
+		let minutes = (period.end - period.start).num_minutes();
+		let row_minutes = ROW_MINUTES
+			.into_iter()
+			.find(|length| minutes <= length * rows)
+			.unwrap_or(ROW_MINUTES[ROW_MINUTES.len() - 1]);
+		let today = Local::now().date_naive();
+		let now = Utc::now();
+
+		for column in 0..7u16 {
+			let day = self.first_day + TimeDelta::days(column.into());
+			let x = area.x + TIME_WIDTH + column * day_width;
+			let style = if day == today {
+				Style::new().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+			} else {
+				Style::new().add_modifier(Modifier::BOLD)
+			};
+			buf.set_stringn(
+				x,
+				area.y,
+				day.format("%a %d").to_string(),
+				day_width.into(),
+				style,
+			);
+
+			let mut previous = Cell::Nothing;
+			for row in 0..area.height - 1 {
+				let time = period.start + TimeDelta::minutes(row_minutes * i64::from(row));
+				if time >= period.end || time < period.start {
+					break;
+				}
+				let y = area.y + 1 + row;
+				if column == 0 {
+					buf.set_string(
+						area.x,
+						y,
+						time.format("%H:%M").to_string(),
+						Style::new().fg(Color::DarkGray),
+					);
+				}
+				let Some(utc) = at(day, time) else {
+					continue;
+				};
+				let cell = self.cell(utc);
+				let width = usize::from(day_width.saturating_sub(1));
+				let (text, style) = match &cell {
+					Cell::Task(id) => {
+						let name = if cell == previous {
+							""
+						} else {
+							self.db.tasks.get(*id).map_or("", |task| task.name.as_str())
+						};
+						(name, Style::new().bg(task_color(id)).fg(Color::Black))
+					}
+					Cell::Free => ("·", Style::new().fg(Color::DarkGray)),
+					Cell::Break => ("", Style::new().bg(Color::DarkGray)),
+					Cell::Nothing => ("", Style::new()),
+				};
+				let text = format!("{text:width$}");
+				let now_here = (utc..utc + TimeDelta::minutes(row_minutes)).contains(&now);
+				let style = if now_here {
+					style.add_modifier(Modifier::REVERSED)
+				} else {
+					style
+				};
+				buf.set_stringn(x, y, text, width, style);
+				previous = cell;
+			}
+		}
+	}
+}
@@ -0,0 +1,291 @@
+//! The form for adding and editing a task, one line of text per field.
+
+use chrono::{DateTime, Local, Utc};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use itertools::Itertools;
+use pomeranian::{
+	db::{CTask, SoftDeadline},
+	length,
+	when::{self, Side, TIME_FORMAT},
+};
+use ratatui::{
+	layout::Rect,
+	style::{Color, Modifier, Style},
+	text::{Line, Span},
+	widgets::{Block, Borders, Clear, Paragraph},
+	Frame,
+};
+use std::{collections::BTreeSet, str::FromStr, time::Duration};
+
+/// What each line of the form is for, in order.
+const LABELS: [&str; 15] = [
+	"Name",
+	"Start (like now, tomorrow 9:00 or 2026-10-20 09:00)",
+	"Due (like friday, in 3 days or end of month 17:00)",
+	"Estimate (like 90m, 1h30m, 2.5h or 3 pomodoros)",
+	"Worked (like 45m)",
+	"Priority",
+	"Project",
+	"Tags (contexts start with @)",
+	"Energy needed (0 to 10, 0 if it's not demanding)",
+	"Grace period after the due date (like 2h, 0 for a hard deadline)",
+	"Penalty for each hour late",
+	"Fewest slots in a row (1 for no minimum)",
+	"Most slots in a row (0 for no maximum)",
+	"Most slots in a day (0 for no maximum)",
+	"Fewest days to spread it over (1 to not spread it out)",
+];
+
+/// What the user did in the form.
+pub enum FormAction {
+	/// Kept typing.
+	Continue,
+	/// Asked to save the task.
+	Submit,
+	/// Gave up on the task.
+	Cancel,
+}
+
+/// A task being added or edited, as the text of each field.
+pub struct TaskForm {
+	values: [String; LABELS.len()],
+	focus: usize,
+	/// How long a pomodoro is, for lengths given in pomodoros.
+	timeslice_length: Duration,
+	/// Why the task couldn't be saved, if it couldn't.
+	pub error: Option<String>,
+}
+
+impl TaskForm {
+	/// A form for editing a task, or for a new task due in a week if there isn't one.
+	pub fn new(task: Option<&CTask>, timeslice_length: Duration) -> Self {
+		let time = |time: DateTime<Utc>| time.with_timezone(&Local).format(TIME_FORMAT).to_string();
+		let values = match task {
+			Some(task) => [
+				task.name.clone(),
+				time(task.working_period.start),
+				time(task.working_period.end),
+				length::format(task.estimated_length),
+				length::format(task.worked_length),
+				task.priority.to_string(),
+				task.project.clone().unwrap_or_default(),
+				task.tags.iter().join(" "),
+				task.energy.unwrap_or(0).to_string(),
+				length::format(
+					task.soft_deadline
+						.map_or(Duration::ZERO, |d| d.grace_period),
+				),
+				task.soft_deadline.map_or(1, |d| d.penalty).to_string(),
+				task.min_block.unwrap_or(1).to_string(),
+				task.max_block.unwrap_or(0).to_string(),
+				task.daily_cap.unwrap_or(0).to_string(),
+				task.min_days.unwrap_or(1).to_string(),
+			],
+			None => [
+				String::new(),
+				"now".into(),
+				"in 1 week".into(),
+				"1h".into(),
+				"0m".into(),
+				"0".into(),
+				String::new(),
+				String::new(),
+				"0".into(),
+				"0".into(),
+				"1".into(),
+				"1".into(),
+				"0".into(),
+				"0".into(),
+				"1".into(),
+			],
+		};
+		Self {
+			values,
+			focus: 0,
+			timeslice_length,
+			error: None,
+		}
+	}
+
+	/// Make the task that the form describes.
+	pub fn task(&self) -> Result<CTask, String> {
+		let [name, start, due, estimate, worked, priority, project, tags, energy, grace_period, penalty, min_block, max_block, daily_cap, min_days] =
+			&self.values;
+		let name = name.trim();
+		if name.is_empty() {
+			return Err("The task needs a name".into());
+		}
+		let start = resolve("Start", start, Side::Start)?;
+		let due = resolve("Due", due, Side::End)?;
+		if due < start {
+			return Err("The task must be due after it starts".into());
+		}
+		let energy: u8 = parse("Energy", energy)?;
+		if energy > 10 {
+			return Err("Energy must be at most 10".into());
+		}
+		let grace_period = self.length("Grace period", grace_period)?;
+		let min_block: u64 = parse("Fewest slots in a row", min_block)?;
+		let max_block: u64 = parse("Most slots in a row", max_block)?;
+		if max_block != 0 && max_block < min_block {
+			return Err("Most slots in a row must be at least the fewest slots in a row".into());
+		}
+		let daily_cap: u64 = parse("Most slots in a day", daily_cap)?;
+		let min_days: u64 = parse("Fewest days", min_days)?;
+		let project = project.trim();
+
+		Ok(CTask {
+			name: name.to_string(),
+			working_period: start..due,
+			estimated_length: self.length("Estimate", estimate)?,
+			worked_length: self.length("Worked", worked)?,
+			priority: parse("Priority", priority)?,
+			remote_id: None,
+			soft_deadline: (!grace_period.is_zero())
+				.then(|| {
+					Ok::<_, String>(SoftDeadline {
+						grace_period,
+						penalty: parse("Penalty", penalty)?,
+					})
+				})
+				.transpose()?,
+			min_block: (min_block > 1).then_some(min_block),
+			max_block: (max_block > 0).then_some(max_block),
+			daily_cap: (daily_cap > 0).then_some(daily_cap),
+			min_days: (min_days > 1).then_some(min_days),
+			energy: (energy > 0).then_some(energy),
+			project: (!project.is_empty()).then(|| project.to_string()),
+			tags: tags
+				.split_whitespace()
+				.map(str::to_string)
+				.collect::<BTreeSet<_>>(),
+		})
+	}
+
+	pub fn handle(&mut self, key: KeyEvent) -> FormAction {
+		let value = &mut self.values[self.focus];
+		match key.code {
+			KeyCode::Esc => return FormAction::Cancel,
+			KeyCode::Enter => return FormAction::Submit,
+			KeyCode::Down | KeyCode::Tab => self.focus = (self.focus + 1) % LABELS.len(),
+			KeyCode::Up | KeyCode::BackTab => {
+				self.focus = (self.focus + LABELS.len() - 1) % LABELS.len();
+			}
+			KeyCode::Backspace => {
+				value.pop();
+			}
+			KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => value.clear(),
+			KeyCode::Char(c) => value.push(c),
+			_ => {}
+		}
+		FormAction::Continue
+	}
+
+	fn length(&self, label: &str, text: &str) -> Result<Duration, String> {
+		length::parse(text, self.timeslice_length).map_err(|e| format!("{label}: {e}"))
+	}
+
+	/// What a time or a length resolves to, so that mistakes are caught before saving.
+	fn preview(&self, index: usize, value: &str) -> Option<Span<'static>> {
+		let preview = match index {
+			1 | 2 => {
+				let side = if index == 1 { Side::Start } else { Side::End };
+				when::parse(value, side, &Local::now())
+					.map(|time| time.format("%a %d %b %Y %H:%M").to_string())
+					.ok()
+			}
+			3 | 4 | 9 => length::parse(value, self.timeslice_length)
+				.map(|length| {
+					let slots = length::slots(length, self.timeslice_length);
+					format!("{} ({slots} slots)", length::format(length))
+				})
+				.ok(),
+			_ => return None,
+		};
+		Some(match preview {
+			Some(preview) => Span::styled(format!("  → {preview}"), Style::new().fg(Color::Green)),
+			None => Span::styled("  → ?", Style::new().fg(Color::Red)),
+		})
+	}
+
+	pub fn draw(&self, frame: &mut Frame, area: Rect, title: &str) {
+		let mut lines = LABELS
+			.iter()
+			.zip(&self.values)
+			.enumerate()
+			.map(|(index, (label, value))| {
+				let mut spans = if index == self.focus {
+					vec![
+						Span::styled(
+							format!("{label}: "),
+							Style::new().add_modifier(Modifier::BOLD),
+						),
+						Span::raw(value),
+						Span::styled("▏", Style::new().fg(Color::Yellow)),
+					]
+				} else {
+					vec![
+						Span::styled(format!("{label}: "), Style::new().fg(Color::DarkGray)),
+						Span::raw(value),
+					]
+				};
+				spans.extend(self.preview(index, value));
+				Line::from(spans)
+			})
+			.collect_vec();
+		lines.push(Line::default());
+		match &self.error {
+			Some(error) => lines.push(Line::styled(error.clone(), Style::new().fg(Color::Red))),
+			None => lines.push(Line::styled(
+				"Enter to save, Esc to cancel, ↑/↓ to move between fields",
+				Style::new().fg(Color::DarkGray),
+			)),
+		}
+		frame.render_widget(Clear, area);
+		frame.render_widget(
+			Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)),
+			area,
+		);
+	}
+}
+
+fn parse<T: FromStr>(label: &str, text: &str) -> Result<T, String> {
+	text.trim()
+		.parse()
+		.map_err(|_| format!("{label}: {text:?} isn't valid"))
+}
+
+fn resolve(label: &str, text: &str, side: Side) -> Result<DateTime<Utc>, String> {
+	when::parse(text, side, &Local::now())
+		.map(|time| time.with_timezone(&Utc))
+		.map_err(|e| format!("{label}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::TaskForm;
+	use std::time::Duration;
+
+	#[test]
+	fn round_trip() {
+		let slot = Duration::from_mins(25);
+		let task = TaskForm::new(None, slot)
+			.task()
+			.expect_err("A new task needs a name");
+		assert_eq!(task, "The task needs a name");
+
+		let mut form = TaskForm::new(None, slot);
+		form.values[0] = "Revise".into();
+		form.values[3] = "2 pomodoros".into();
+		form.values[7] = "@library reading".into();
+		form.values[9] = "2".into();
+		let task = form.task().expect("Valid task");
+		assert_eq!(task.estimated_length, Duration::from_mins(50));
+		assert_eq!(task.tags.len(), 2);
+		assert_eq!(
+			task.soft_deadline.map(|d| d.grace_period),
+			Some(Duration::from_hours(2))
+		);
+		assert_eq!(TaskForm::new(Some(&task), slot).task(), Ok(task));
+	}
+}
@@ -0,0 +1,227 @@
+//! Every task as a bar across its working period, with its slots filled in and its due date marked,
+//! so that tasks at risk of running late stand out.
+
+use super::task_color;
+use chrono::{DateTime, Duration as TimeDelta, Local, NaiveDate, Utc};
+use itertools::Itertools;
+use pomeranian::db::Db;
+use ratatui::{
+	buffer::Buffer,
+	layout::Rect,
+	style::{Color, Modifier, Style},
+	widgets::Widget,
+};
+
+/// The widest a task's name gets before it's cut off.
+const NAME_WIDTH: u16 = 24;
+
+/// The tasks from `first_day` until the last one is due, skipping the first `scroll` of them.
+pub struct Gantt<'a> {
+	pub db: &'a Db,
+	pub first_day: NaiveDate,
+	pub scroll: usize,
+}
+
+impl Gantt<'_> {
+	/// The tasks in the order they're due.
+	fn tasks(&self) -> Vec<&str> {
+		self.db
+			.tasks
+			.iter()
+			.sorted_by_key(|(id, task)| (task.working_period.end, &task.name, *id))
+			.map(|(id, _)| id.as_str())
+			.collect()
+	}
+}
+
+/// Which column of the chart each time falls in.
+struct Axis {
+	start: DateTime<Utc>,
+	/// How long each column stands for, in seconds.
+	column_seconds: i64,
+	width: u16,
+}
+
+impl Axis {
+	fn column(&self, time: DateTime<Utc>) -> Option<u16> {
+		let offset = (time - self.start).num_seconds() / self.column_seconds;
+		u16::try_from(offset)
+			.ok()
+			.filter(|offset| *offset < self.width)
+	}
+
+	/// Mark where each day starts, labelled as space allows.
+	fn render_days(&self, first_day: NaiveDate, days: i64, x: u16, y: u16, buf: &mut Buffer) {
+		let mut free_from = 0;
+		for day in 0..days {
+			let date = first_day + TimeDelta::days(day);
+			let Some(offset) = date
+				.and_hms_opt(0, 0, 0)
+				.and_then(|time| time.and_local_timezone(Local).earliest())
+				.and_then(|time| self.column(time.with_timezone(&Utc)))
+			else {
+				continue;
+			};
+			if offset >= free_from {
+				let label = date.format("│%a %d").to_string();
+				buf.set_stringn(
+					x + offset,
+					y,
+					&label,
+					usize::from(self.width - offset),
+					Style::new().fg(Color::DarkGray),
+				);
+				free_from = offset + u16::try_from(label.chars().count()).unwrap_or(self.width) + 1;
+			}
+		}
+	}
+}
+
+impl Gantt<'_> {
+	/// A task's bar, from where it can start to when it's due, with its slots on top.
+	fn render_task(&self, id: &str, axis: &Axis, x: u16, y: u16, buf: &mut Buffer) {
+		let period = &self.db.tasks[id].working_period;
+		if period.end >= axis.start {
+			let bar_start = axis.column(period.start.max(axis.start)).unwrap_or(0);
+			let bar_end = axis.column(period.end).unwrap_or(axis.width - 1);
+			for offset in bar_start..=bar_end {
+				buf.set_string(x + offset, y, "─", Style::new().fg(Color::DarkGray));
+			}
+		}
+		for (time, _) in self
+			.db
+			.slots
+			.iter()
+			.filter(|(_, slot)| slot.as_deref() == Some(id))
+		{
+			if let Some(offset) = axis.column(*time) {
+				let color = if *time >= period.end {
+					Color::Red
+				} else {
+					task_color(id)
+				};
+				buf.set_string(x + offset, y, "█", Style::new().fg(color));
+			}
+		}
+		if let Some(offset) = axis.column(period.end) {
+			buf.set_string(x + offset, y, "┃", Style::new().fg(Color::Red));
+		}
+		if let Some(offset) = axis.column(Utc::now()) {
+			buf.get_mut(x + offset, y).set_bg(Color::DarkGray);
+		}
+	}
+}
+
+impl Widget for Gantt<'_> {
+	fn render(self, area: Rect, buf: &mut Buffer) {
+		let tasks = self.tasks();
+		let longest = tasks
+			.iter()
+			.map(|id| self.db.tasks[*id].name.chars().count())
+			.max()
+			.unwrap_or(0)
+			.min(usize::from(NAME_WIDTH));
+		let name_width = u16::try_from(longest).unwrap_or(NAME_WIDTH) + 2;
+		if area.width <= name_width || area.height < 2 {
+			return;
+		}
+		let Some(start) = self
+			.first_day
+			.and_hms_opt(0, 0, 0)
+			.and_then(|time| time.and_local_timezone(Local).earliest())
+			.map(|time| time.with_timezone(&Utc))
+		else {
+			return;
+		};
+		// Show whole days, up to the latest due date or the latest slot, whichever is later
+		let last = tasks
+			.iter()
+			.map(|id| self.db.tasks[*id].working_period.end)
+			.chain(
+				self.db
+					.slots
+					.iter()
+					.filter(|(_, id)| id.is_some())
+					.map(|(time, _)| *time),
+			)
+			.max()
+			.unwrap_or(start);
+		let days = ((last - start).num_hours() / 24 + 1).max(1);
+		let width = area.width - name_width;
+		let axis = Axis {
+			start,
+			column_seconds: (TimeDelta::days(days) / i32::from(width))
+				.num_seconds()
+				.max(1),
+			width,
+		};
+		let x = area.x + name_width;
+		axis.render_days(self.first_day, days, x, area.y, buf);
+
+		let outcomes = self.db.outcomes();
+		for (row, id) in tasks.iter().skip(self.scroll).enumerate() {
+			let Ok(row) = u16::try_from(row) else {
+				break;
+			};
+			if row + 1 >= area.height {
+				break;
+			}
+			let y = area.y + 1 + row;
+			let at_risk = outcomes
+				.get(*id)
+				.is_some_and(|outcome| outcome.missing > 0 || outcome.late_slots > 0);
+			let style = if at_risk {
+				Style::new().fg(Color::Red).add_modifier(Modifier::BOLD)
+			} else {
+				Style::new()
+			};
+			buf.set_stringn(
+				area.x,
+				y,
+				&self.db.tasks[*id].name,
+				usize::from(name_width - 2),
+				style,
+			);
+			self.render_task(id, &axis, x, y, buf);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Gantt;
+	use chrono::{Local, Utc};
+	use pomeranian::db::{CTask, Db};
+	use ratatui::{buffer::Buffer, layout::Rect, widgets::Widget};
+	use std::time::Duration;
+
+	#[test]
+	fn draw_timelines() {
+		let mut db = Db::default();
+		let task = CTask::new(
+			"Write report".into(),
+			Utc::now()..Utc::now() + Duration::from_hours(72),
+			Duration::from_hours(2),
+		);
+		db.insert_task("00000000000000aa".into(), task);
+
+		let area = Rect::new(0, 0, 80, 3);
+		let mut buf = Buffer::empty(area);
+		Gantt {
+			db: &db,
+			first_day: Local::now().date_naive(),
+			scroll: 0,
+		}
+		.render(area, &mut buf);
+		let row = |y| {
+			(0..area.width)
+				.map(|x| buf.get(x, y).symbol())
+				.collect::<String>()
+		};
+		assert!(row(0).contains('│'));
+		assert!(row(1).starts_with("Write report"));
+		assert!(row(1).contains('█'));
+		assert!(row(1).contains('┃'));
+		assert!(row(2).trim().is_empty());
+	}
+}
@@ -0,0 +1,552 @@
+//! The pomodoro timer, which counts down each work slot and break in turn.
+
+use super::Term;
+use chrono::{DateTime, Local, TimeDelta, Utc};
+use color::{color_space::Srgb, Deg, Hsv, Rgb, ToRgb};
+use crossterm::event::{Event, KeyCode, KeyEvent};
+use itertools::Itertools;
+use pomeranian::{
+	config::Settings,
+	db::{Db, Session},
+	length,
+	notify::{Action, Notice, Notifications, Outbox},
+	pomodoro::Pomodoro,
+};
+use ratatui::{
+	layout::{Constraint, Direction, Layout, Rect},
+	style::{Color, Stylize},
+	widgets::{Block, Borders, Gauge, Paragraph},
+};
+use std::{collections::HashMap, io, ops::Range, time::Duration};
+
+/// How long before the end of a work slot or break to give a heads-up.
+const HEADS_UP: TimeDelta = TimeDelta::minutes(1);
+/// How much longer snoozing keeps a work slot or break going.
+const SNOOZE: TimeDelta = TimeDelta::minutes(5);
+
+pub fn timer(
+	db: &mut Db,
+	settings: &Settings,
+	terminal: &mut Term,
+	notifications: &Notifications,
+) -> io::Result<bool> {
+	let mut sessions = vec![];
+	let mut done = vec![];
+	let mut finished_active_period = false;
+	db.pomodoro_states.sort_by_key(|(t, _)| t.start);
+	state_loop(
+		db,
+		settings,
+		&mut finished_active_period,
+		terminal,
+		(&mut sessions, &mut done),
+		notifications,
+	)?;
+
+	let mut time_spent: HashMap<String, Duration> = HashMap::new();
+	for Session { task, period } in &sessions {
+		*time_spent.entry(task.clone()).or_default() += (period.end - period.start)
+			.to_std()
+			.expect("DateTime is monotonic, so this will always be positive");
+	}
+	if !sessions.is_empty() {
+		db.record(format!(
+			"Work on {}",
+			time_spent
+				.keys()
+				.filter_map(|id| db.tasks.get(id))
+				.map(|task| task.name.as_str())
+				.sorted()
+				.join(", ")
+		));
+	}
+	db.history.extend(sessions);
+	for (id, time) in time_spent {
+		db.credit(&id, time);
+	}
+	for id in done {
+		if let Some(task) = db.tasks.get(&id) {
+			let left = task.remaining_length();
+			db.credit(&id, left);
+		}
+	}
+
+	Ok(finished_active_period)
+}
+
+/// The notices about the work slots and breaks to come, for when the timer isn't running to act on their buttons.
+pub fn notices(db: &Db, settings: &Settings, now: DateTime<Utc>) -> Vec<(DateTime<Utc>, Notice)> {
+	phases(db, now)
+		.iter()
+		.flat_map(|phase| phase.notices(settings, now, false))
+		.collect()
+}
+
+/// The work slots and breaks that haven't ended by `now`, leaving out work slots with nothing to work on,
+/// including ones whose task has been removed since they were planned.
+fn phases(db: &Db, now: DateTime<Utc>) -> Vec<Phase> {
+	db.pomodoro_states
+		.iter()
+		.filter(|(time, _)| time.end >= now)
+		.filter_map(|(time, state)| {
+			let task = db
+				.schedule
+				.slots
+				.get(&time.start)
+				.cloned()
+				.flatten()
+				.and_then(|id| {
+					let name = db.tasks.get(&id)?.name.clone();
+					Some((id, name))
+				});
+			if matches!(state, Pomodoro::Work(_)) && task.is_none() {
+				return None;
+			}
+			Some(Phase::new(*state, task, time.clone()))
+		})
+		.collect()
+}
+
+/// A work slot or break as the timer runs through it.
+struct Phase {
+	state: Pomodoro,
+	/// The ID and name of the task being worked on, if it's a work slot with one.
+	task: Option<(String, String)>,
+	/// When the phase was planned to start, which tells buttons pressed about it apart from ones about other phases.
+	planned: DateTime<Utc>,
+	period: Range<DateTime<Utc>>,
+	/// The action that ended the phase early, if one did.
+	cut_short: Option<Action>,
+}
+
+impl Phase {
+	fn new(state: Pomodoro, task: Option<(String, String)>, period: Range<DateTime<Utc>>) -> Self {
+		Self {
+			state,
+			task,
+			planned: period.start,
+			period,
+			cut_short: None,
+		}
+	}
+
+	fn is_break(&self) -> bool {
+		!matches!(self.state, Pomodoro::Work(_))
+	}
+
+	/// Whether the phase is for working on a task that's been marked done.
+	fn is_done(&self, done: &[String]) -> bool {
+		self.task.as_ref().is_some_and(|(id, _)| done.contains(id))
+	}
+
+	/// The notices about the phase that are due from `now` on: that it's started, a heads-up before the end,
+	/// and that it's over. Their buttons are left off unless the timer is running to act on them.
+	fn notices(
+		&self,
+		settings: &Settings,
+		now: DateTime<Utc>,
+		buttons: bool,
+	) -> Vec<(DateTime<Utc>, Notice)> {
+		let notice = |summary: String, actions: Vec<Action>| Notice {
+			summary,
+			actions: if buttons { actions } else { vec![] },
+			phase: self.planned,
+		};
+		let length = length::format(
+			(self.period.end - self.period.start)
+				.to_std()
+				.unwrap_or_default(),
+		);
+		let begin = match (&self.task, self.state) {
+			(Some((_, name)), _) => notice(
+				Settings::notification(&settings.start_notification, name),
+				vec![Action::MarkDone],
+			),
+			(None, Pomodoro::LongBreak) => notice(
+				format!("Take a long break ({length})"),
+				vec![Action::SkipBreak],
+			),
+			(None, _) => notice(format!("Take a {length} break"), vec![Action::SkipBreak]),
+		};
+		let heads_up = match &self.task {
+			Some((_, name)) => notice(
+				format!("1 minute left on {name}"),
+				vec![Action::Snooze, Action::MarkDone],
+			),
+			None => notice(
+				"1 minute left of the break".into(),
+				vec![Action::Snooze, Action::SkipBreak],
+			),
+		};
+		let finish = match (&self.task, self.cut_short) {
+			(Some((_, name)), Some(Action::MarkDone)) => format!("Marked {name} as done"),
+			(Some((_, name)), _) => Settings::notification(&settings.done_notification, name),
+			(None, Some(Action::SkipBreak)) => "Skipped the rest of the break".into(),
+			(None, _) => "The break's over".into(),
+		};
+		let mut notices = vec![
+			(self.period.start, begin),
+			(self.period.end, notice(finish, vec![])),
+		];
+		if self.period.end - HEADS_UP > self.period.start {
+			notices.insert(1, (self.period.end - HEADS_UP, heads_up));
+		}
+		notices.retain(|(time, _)| *time >= now);
+		notices
+	}
+
+	/// Act on a button that was pressed. Returns how much later a snooze made the end.
+	fn act(&mut self, now: DateTime<Utc>, action: Action) -> TimeDelta {
+		match action {
+			Action::Snooze => {
+				let end = self.period.end.max(now) + SNOOZE;
+				let later = end - self.period.end;
+				self.period.end = end;
+				return later;
+			}
+			Action::SkipBreak if self.is_break() => {}
+			Action::MarkDone if self.task.is_some() => {}
+			_ => return TimeDelta::zero(),
+		}
+		self.cut_short = Some(action);
+		self.period.end = now;
+		TimeDelta::zero()
+	}
+}
+
+/// Act on a button that was pressed for the first phase, pushing the ones after it back as far as a snooze pushed its end.
+fn act(phases: &mut [Phase], now: DateTime<Utc>, action: Action) {
+	let [phase, later @ ..] = phases else {
+		return;
+	};
+	let snoozed = phase.act(now, action);
+	for phase in later {
+		phase.period.start += snoozed;
+		phase.period.end += snoozed;
+	}
+}
+
+/// Send the notices about the phases to come when they're due, with buttons for the timer to act on.
+fn plan(
+	outbox: &Outbox,
+	phases: &[Phase],
+	done: &[String],
+	settings: &Settings,
+	now: DateTime<Utc>,
+) {
+	outbox.plan(
+		phases
+			.iter()
+			.filter(|phase| !phase.is_done(done))
+			.flat_map(|phase| phase.notices(settings, now, true))
+			.collect(),
+	);
+}
+
+fn state_loop(
+	db: &Db,
+	settings: &Settings,
+	finished_active_period: &mut bool,
+	terminal: &mut Term,
+	(sessions, done): (&mut Vec<Session>, &mut Vec<String>),
+	notifications: &Notifications,
+) -> Result<(), std::io::Error> {
+	let mut phases = phases(db, Utc::now());
+	// Whether the last phase was cut short, so that this one can start early
+	let mut early = false;
+	for index in 0..phases.len() {
+		let mut keep_going = true;
+		let phase = &mut phases[index];
+		// Skip if there are somehow still slots that have ended
+		if phase.period.end < Utc::now() || phase.is_done(done) {
+			continue;
+		}
+		if phase.period.start > (Utc::now() + Duration::from_secs(5)) && !early {
+			keep_going = false;
+			*finished_active_period = true;
+		}
+		// Set up task context
+		let entered_task_at = Utc::now();
+		if early {
+			phase.period.start = entered_task_at;
+		}
+		let title = match (phase.state, &phase.task) {
+			(Pomodoro::Work(n), Some((_, name))) => {
+				format!(
+					"Working on {name} in work period ({} more until long break)",
+					db.break_interval - n
+				)
+			}
+			(Pomodoro::Break(n), _) => format!(
+				"In break period ({} until long break)",
+				db.break_interval - n
+			),
+			(Pomodoro::LongBreak, _) => "Long break!".to_string(),
+			(Pomodoro::Work(_), None) => {
+				continue;
+			}
+		};
+		if keep_going {
+			plan(
+				&notifications.outbox,
+				&phases[index..],
+				done,
+				settings,
+				entered_task_at,
+			);
+		}
+		// Loop until we're done with this task
+		task_loop(
+			&mut keep_going,
+			&mut phases[index..],
+			terminal,
+			(&title, entered_task_at),
+			(settings, done),
+			notifications,
+		)?;
+		// Done with the section
+		let phase = &phases[index];
+		early = phase.cut_short.is_some();
+		if let Some((task, _)) = &phase.task {
+			if phase.cut_short == Some(Action::MarkDone) {
+				done.push(task.clone());
+			}
+			// Record the time we spent on the task
+			sessions.push(Session {
+				task: task.clone(),
+				period: entered_task_at..Utc::now(),
+			});
+		}
+		for offset in 0..=20 {
+			let offset = f64::from(offset) / 40.0;
+			terminal.draw(|frame| {
+				for x in 0..frame.size().width {
+					let hue = ((f64::from(x) / f64::from(frame.size().width)) + offset) * 360.0;
+					let hsv = Hsv::<f64, Srgb>::new(Deg(hue), 1.0, 1.0);
+					let rgb: Rgb<f64> = hsv.to_rgb();
+					#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+					let color = Color::Rgb(
+						(rgb.r * f64::from(u8::MAX)) as u8,
+						(rgb.g * f64::from(u8::MAX)) as u8,
+						(rgb.b * f64::from(u8::MAX)) as u8,
+					);
+					frame.render_widget(
+						Block::default().bg(color),
+						Rect::new(x, 0, 1, frame.size().height),
+					);
+				}
+			})?;
+			std::thread::sleep(Duration::from_millis(30));
+		}
+		if !keep_going {
+			break;
+		}
+	}
+	Ok(())
+}
+
+/// Count down the first of `phases`, acting on keys and buttons until it's over.
+fn task_loop(
+	keep_going: &mut bool,
+	phases: &mut [Phase],
+	terminal: &mut Term,
+	(title, entered_task_at): (&str, DateTime<Utc>),
+	(settings, done): (&Settings, &[String]),
+	notifications: &Notifications,
+) -> Result<(), std::io::Error> {
+	let keys = if phases[0].is_break() {
+		"(Q to stop, S to snooze 5m, B to skip the break)"
+	} else {
+		"(Q to stop, S to snooze 5m, D to mark the task done)"
+	};
+	let mut pressed = None;
+	let mut error = None;
+	while *keep_going {
+		let now = Utc::now();
+		// Buttons pressed on notices about other phases were meant for them, not this one
+		let planned = phases[0].planned;
+		let action = pressed.take().or_else(|| {
+			notifications
+				.actions
+				.try_iter()
+				.find_map(|(phase, action)| (phase == planned).then_some(action))
+		});
+		if let Some(action) = action {
+			act(phases, now, action);
+			plan(&notifications.outbox, phases, done, settings, now);
+		}
+		if let Some(e) = notifications.errors.try_iter().last() {
+			error = Some(format!("Couldn't show a notification: {e}"));
+		}
+		let phase = &phases[0];
+		if phase.cut_short.is_some() || now >= phase.period.end {
+			break;
+		}
+		let end = phase.period.end;
+		// Draw terminal
+		terminal.draw(|frame| {
+			let rows = Layout::new(
+				Direction::Vertical,
+				[Constraint::Length(5), Constraint::Min(1)],
+			)
+			.split(frame.size());
+			// Draw status message
+			let label = format!(
+				"{}s done; {}s until completion ({})\n{keys}\n{}",
+				(now - entered_task_at).num_seconds(),
+				(end - now).num_seconds(),
+				end.with_timezone(&Local),
+				error.as_deref().unwrap_or_default()
+			);
+			frame.render_widget(
+				Paragraph::new(label).block(Block::default().borders(Borders::ALL).title(title)),
+				rows[0],
+			);
+
+			// Draw progress bar
+			let completion = (now - entered_task_at)
+				.to_std()
+				.expect("Instant increases monotonically, so this is always positive")
+				.as_secs_f64() / (end - entered_task_at)
+				.to_std()
+				.expect("Entered_at is less than now, and this loop would have ended if now was greater than the end")
+				.as_secs_f64();
+			let bar = Gauge::default()
+				.ratio(completion)
+				.use_unicode(true)
+				.block(Block::default().borders(Borders::ALL));
+			frame.render_widget(bar, rows[1]);
+		})?;
+
+		if crossterm::event::poll(Duration::from_millis(100))? {
+			if let Event::Key(KeyEvent {
+				code: KeyCode::Char(key),
+				..
+			}) = crossterm::event::read()?
+			{
+				match key {
+					'q' => *keep_going = false,
+					's' => pressed = Some(Action::Snooze),
+					'b' => pressed = Some(Action::SkipBreak),
+					'd' => pressed = Some(Action::MarkDone),
+					_ => {}
+				}
+			}
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{act, phases, Phase};
+	use chrono::{TimeDelta, Utc};
+	use itertools::Itertools;
+	use pomeranian::{
+		config::Settings,
+		db::{CTask, Db},
+		notify::Action,
+		pomodoro::Pomodoro,
+	};
+	use std::time::Duration;
+
+	#[test]
+	fn phase_notices() {
+		let settings = Settings::default();
+		let start = Utc::now();
+		let at = |seconds| start + TimeDelta::seconds(seconds);
+		let summaries = |phase: &Phase, now| {
+			phase
+				.notices(&settings, now, true)
+				.into_iter()
+				.map(|(time, notice)| (time, notice.summary))
+				.collect_vec()
+		};
+		let mut phases = [
+			Phase::new(
+				Pomodoro::Work(2),
+				Some(("00000000000000aa".into(), "Essay".into())),
+				start..at(25 * 60),
+			),
+			Phase::new(Pomodoro::Break(1), None, at(25 * 60)..at(30 * 60)),
+		];
+		let notices = phases[0].notices(&settings, start, true);
+		assert_eq!(
+			notices
+				.iter()
+				.map(|(_, notice)| &notice.actions)
+				.collect_vec(),
+			[
+				&vec![Action::MarkDone],
+				&vec![Action::Snooze, Action::MarkDone],
+				&vec![]
+			]
+		);
+		assert!(notices.iter().all(|(_, notice)| notice.phase == start));
+		assert!(phases[0]
+			.notices(&settings, start, false)
+			.iter()
+			.all(|(_, notice)| notice.actions.is_empty()));
+		assert_eq!(
+			summaries(&phases[0], at(60)),
+			[
+				(at(24 * 60), "1 minute left on Essay".into()),
+				(at(25 * 60), "Done working on Essay".into()),
+			]
+		);
+
+		// Snoozing gives five more minutes, with another heads-up before they're up, and pushes the break back
+		act(&mut phases, at(24 * 60 + 10), Action::Snooze);
+		assert_eq!(
+			summaries(&phases[0], at(24 * 60 + 10)),
+			[
+				(at(29 * 60), "1 minute left on Essay".into()),
+				(at(30 * 60), "Done working on Essay".into()),
+			]
+		);
+		assert_eq!(phases[1].period, at(30 * 60)..at(35 * 60));
+		assert_eq!(phases[1].planned, at(25 * 60));
+
+		// There's no break to skip, but marking the task done ends the slot
+		act(&mut phases, at(29 * 60 + 31), Action::SkipBreak);
+		assert_eq!(phases[0].cut_short, None);
+		act(&mut phases, at(29 * 60 + 32), Action::MarkDone);
+		assert_eq!(
+			summaries(&phases[0], at(29 * 60 + 32)),
+			[(at(29 * 60 + 32), "Marked Essay as done".into())]
+		);
+		assert_eq!(phases[1].period, at(30 * 60)..at(35 * 60));
+
+		let rest = &mut phases[1..];
+		assert_eq!(
+			summaries(&rest[0], start)[0],
+			(at(30 * 60), "Take a 5m break".into())
+		);
+		act(rest, at(31 * 60), Action::SkipBreak);
+		assert_eq!(
+			summaries(&rest[0], at(31 * 60)),
+			[(at(31 * 60), "Skipped the rest of the break".into())]
+		);
+	}
+
+	#[test]
+	fn removed_tasks() {
+		let now = Utc::now();
+		let id = "00000000000000aa";
+		let mut db = Db::default();
+		db.insert_task(
+			id.into(),
+			CTask::new(
+				"Essay".into(),
+				now..now + Duration::from_hours(72),
+				Duration::from_hours(2),
+			),
+		);
+		assert!(phases(&db, now).iter().any(|phase| phase.task.is_some()));
+
+		// Slots can still point at a task that's gone, until the plan is next worked out
+		db.schedule.tasks.remove(id);
+		assert!(db.slots.values().flatten().any(|slot| slot == id));
+		assert!(phases(&db, now).iter().all(Phase::is_break));
+	}
+}
@@ -0,0 +1,494 @@
+//! Non-interactive subcommands, for scripts and for reproducing results.
+
+use crate::{app, Setup};
+use chrono::{DateTime, Local, Utc};
+use clap::{Args, Subcommand};
+use itertools::Itertools;
+use pomeranian::{
+	config::Config,
+	db::{CTask, Db, PlanDiff, ShuffleBudget, ShuffleRecord},
+	length,
+	profile::{self, Overlap, Profile, DEFAULT_PROFILE},
+	storage::{self, Backend, Shared},
+	strategy::Context,
+	when::{self, Side},
+};
+use rand::{thread_rng, Rng};
+use std::{
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+#[derive(Subcommand)]
+pub enum Command {
+	/// Shuffle the schedule for a strategy.
+	/// The same seed and iterations give the same plan again, starting from the same schedule.
+	Shuffle {
+		/// The name of the strategy to maximize
+		strategy: String,
+		/// The seed for the shuffle, random by default
+		#[arg(long)]
+		seed: Option<u64>,
+		/// How many shuffles to try; otherwise shuffle for as long as the settings say
+		#[arg(long)]
+		iterations: Option<usize>,
+	},
+	/// Add a task. Times can be written like "now", "tomorrow 17:00", "friday", "in 3 days" or "end of month".
+	Add(NewTask),
+	/// List tasks, with the short IDs that other commands accept.
+	List,
+	/// Explain how a task is placed, and if it's missing slots, why and what would fix it.
+	Explain {
+		/// The ID of the task, or enough of the start of it to be unambiguous
+		id: String,
+	},
+	/// Remove a task.
+	Remove {
+		/// The ID of the task, or enough of the start of it to be unambiguous
+		id: String,
+	},
+	/// List the slots that went by without being worked, or deal with a task's.
+	/// Without `--done` or `--partly`, the task's missed work is put back in the plan.
+	Missed {
+		/// The ID of the task, or enough of the start of it to be unambiguous
+		id: Option<String>,
+		/// Count the missed slots as done after all
+		#[arg(long, requires = "id")]
+		done: bool,
+		/// Count this much of the missed slots as done, like 30m or 1 pomodoro
+		#[arg(long, requires = "id", conflicts_with = "done")]
+		partly: Option<String>,
+	},
+	/// Undo the most recent change to tasks or the schedule.
+	Undo,
+	/// Redo the most recently undone change.
+	Redo,
+	/// List recent changes, newest first.
+	Log,
+	/// List backups of the database, or restore one of them.
+	Restore {
+		/// The number of the backup to restore, from the list
+		number: Option<usize>,
+	},
+	/// Switch to another profile, which is used from then on unless `--profile` says otherwise.
+	/// Without a profile, lists them.
+	Switch {
+		/// The name of the profile
+		profile: Option<String>,
+	},
+	/// Show the plans of every profile together, warning about any times that two of them have work planned at once.
+	Combined,
+	/// List the settings, or show or change one of them in the config file.
+	Settings {
+		/// The name of the setting
+		name: Option<String>,
+		/// Its new value
+		value: Option<String>,
+		/// Change it only for the profile in use, instead of for every profile
+		#[arg(long)]
+		profile_only: bool,
+	},
+	/// Copy the database into new storage, such as from a RON file to `SQLite`.
+	Convert {
+		/// Where to store the copy, which mustn't exist yet
+		to: PathBuf,
+		/// How to store the copy, `ron` or `sqlite`; guessed from its extension by default
+		#[arg(long)]
+		backend: Option<Backend>,
+	},
+}
+
+/// The task for `pomeranian add`.
+#[derive(Args)]
+pub struct NewTask {
+	/// The name of the task
+	name: String,
+	/// When the task is due
+	#[arg(long, value_parser = due_time)]
+	due: DateTime<Utc>,
+	/// When work on the task can start
+	#[arg(long, value_parser = start_time, default_value = "now")]
+	start: DateTime<Utc>,
+	/// How long the task is estimated to take, like 90m, 1h30m, 2.5h or 3 pomodoros
+	#[arg(long, default_value = "1h")]
+	estimate: String,
+	/// Higher priorities are more important
+	#[arg(long, default_value_t = 0)]
+	priority: u32,
+	/// The project the task belongs to
+	#[arg(long)]
+	project: Option<String>,
+	/// A tag for the task, which can be given more than once; contexts start with @
+	#[arg(long = "tag")]
+	tags: Vec<String>,
+	/// Show how adding the task would change the plan, without adding it
+	#[arg(long)]
+	dry_run: bool,
+}
+
+/// Read the time that work on a task can start, for `--start`.
+fn start_time(text: &str) -> Result<DateTime<Utc>, String> {
+	when::parse(text, Side::Start, &Local::now())
+		.map(|time| time.with_timezone(&Utc))
+		.map_err(|e| e.to_string())
+}
+
+/// Read the time that a task is due, for `--due`.
+fn due_time(text: &str) -> Result<DateTime<Utc>, String> {
+	when::parse(text, Side::End, &Local::now())
+		.map(|time| time.with_timezone(&Utc))
+		.map_err(|e| e.to_string())
+}
+
+pub fn run(db: &mut Db, setup: &mut Setup, command: Command) -> Result<(), String> {
+	match command {
+		Command::Shuffle {
+			strategy,
+			seed,
+			iterations,
+		} => shuffle(db, setup, &strategy, seed, iterations),
+		Command::Add(task) => add(db, task),
+		Command::List => {
+			for (id, task) in db
+				.tasks
+				.iter()
+				.sorted_by_key(|(id, task)| (&task.name, *id))
+			{
+				println!(
+					"{}\t{}\t{}",
+					db.short_id(id),
+					task.name,
+					length::remaining(task, db.timeslice_length)
+				);
+			}
+			Ok(())
+		}
+		Command::Explain { id } => {
+			let id = db.resolve_id(&id).map_err(|e| e.to_string())?;
+			println!("{}", db.tasks[&id].name);
+			for line in app::explanation_lines(db, &id) {
+				println!("{line}");
+			}
+			Ok(())
+		}
+		Command::Remove { id } => {
+			let id = db.resolve_id(&id).map_err(|e| e.to_string())?;
+			db.record(format!("Remove {}", db.tasks[&id].name));
+			if let Some(task) = db.remove_task(&id) {
+				println!("Removed {}", task.name);
+			}
+			Ok(())
+		}
+		Command::Missed { id, done, partly } => missed(db, id, done, partly),
+		Command::Undo => {
+			let operation = db.undo().ok_or("Nothing to undo")?;
+			println!("Undid {operation}");
+			Ok(())
+		}
+		Command::Redo => {
+			let operation = db.redo().ok_or("Nothing to redo")?;
+			println!("Redid {operation}");
+			Ok(())
+		}
+		Command::Log => {
+			for line in app::log_lines(db) {
+				println!("{line}");
+			}
+			Ok(())
+		}
+		Command::Restore { .. } | Command::Switch { .. } | Command::Combined => {
+			unreachable!("Handled before the database is loaded")
+		}
+		Command::Settings {
+			name,
+			value,
+			profile_only,
+		} => match (name, value) {
+			(None, _) => {
+				for (name, value) in setup.settings.entries() {
+					println!("{name} = {value}");
+				}
+				Ok(())
+			}
+			(Some(name), None) => {
+				let (_, value) = setup
+					.settings
+					.entries()
+					.into_iter()
+					.find(|(other, _)| *other == name)
+					.ok_or_else(|| format!("Unknown setting {name:?}"))?;
+				println!("{value}");
+				Ok(())
+			}
+			(Some(name), Some(value)) => {
+				for note in setup.change_setting(db, &name, &value, profile_only)? {
+					eprintln!("{note}");
+				}
+				Ok(())
+			}
+		},
+		Command::Convert { to, backend } => {
+			if to.exists() {
+				return Err(format!("{} already exists", to.display()));
+			}
+			storage::open(&to, backend)
+				.and_then(|mut storage| storage.save(db))
+				.map_err(|e| e.to_string())?;
+			println!("Copied to {}", to.display());
+			Ok(())
+		}
+	}
+}
+
+/// List the tasks with missed slots, or put one's back in the plan or count some or all of them as done.
+fn missed(
+	db: &mut Db,
+	id: Option<String>,
+	done: bool,
+	partly: Option<String>,
+) -> Result<(), String> {
+	let Some(id) = id else {
+		if db.missed.is_empty() {
+			println!("No slots have gone by without being worked");
+		}
+		for line in app::missed_lines(db) {
+			println!("{line}");
+		}
+		return Ok(());
+	};
+	let id = db.resolve_id(&id).map_err(|e| e.to_string())?;
+	let name = db.tasks[&id].name.clone();
+	if !db.missed_totals().contains_key(&id) {
+		return Err(format!("{name} has no missed slots"));
+	}
+	let done = match (done, partly) {
+		(true, _) => Duration::MAX,
+		(false, Some(partly)) => {
+			length::parse(&partly, db.timeslice_length).map_err(|e| e.to_string())?
+		}
+		(false, None) => Duration::ZERO,
+	};
+	let before = db.schedule.clone();
+	if done.is_zero() {
+		db.record(format!("Reschedule the missed slots of {name}"));
+		db.resolve_missed(&id, done);
+		println!("Put {name}'s missed work back in the plan");
+	} else {
+		db.record(format!("Count the missed slots of {name} as done"));
+		let credited = db.resolve_missed(&id, done);
+		println!(
+			"Counted {} of {name}'s missed slots as done",
+			length::format(credited)
+		);
+	}
+	let moved = PlanDiff::between(&before, &db.schedule).slots_moved;
+	if moved > 0 {
+		println!(
+			"Moved {moved} planned slot{}",
+			if moved == 1 { "" } else { "s" }
+		);
+	}
+	println!("{}", length::remaining(&db.tasks[&id], db.timeslice_length));
+	Ok(())
+}
+
+/// List the profiles, or make one of them the default.
+pub fn switch(
+	config: &Config,
+	current: Option<&Path>,
+	profile: Option<&str>,
+) -> Result<(), String> {
+	let Some(current) = current else {
+		return Err("There's no config directory to remember the profile in".into());
+	};
+	let Some(profile) = profile else {
+		let current = profile::current(current)
+			.map_err(|e| e.to_string())?
+			.unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+		for (name, Profile { db_path, .. }) in config.all_profiles() {
+			let marker = if name == current { "*" } else { " " };
+			println!("{marker} {name}\t{}", db_path.display());
+		}
+		return Ok(());
+	};
+	if config.profile(profile).is_none() {
+		return Err(format!(
+			"Unknown profile {profile:?}; add a [profiles.{profile}] table with a db_path to the config file"
+		));
+	}
+	profile::switch(current, profile).map_err(|e| e.to_string())?;
+	println!("Switched to {profile}");
+	Ok(())
+}
+
+/// Print the upcoming work of every profile in order, then any times that two profiles have work at once.
+pub fn combined(config: &Config) -> Result<(), String> {
+	let mut profiles = vec![];
+	for (
+		name,
+		Profile {
+			db_path, backend, ..
+		},
+	) in config.all_profiles()
+	{
+		if !db_path.exists() {
+			continue;
+		}
+		let db = storage::open(&db_path, backend)
+			.and_then(|mut storage| storage.load())
+			.map_err(|e| format!("{name}: {e}"))?;
+		profiles.push((name, db));
+	}
+	let now = Utc::now();
+	for (time, name, task) in profiles
+		.iter()
+		.flat_map(|(name, db)| {
+			db.slots.iter().filter_map(move |(time, task)| {
+				Some((*time, name, &db.tasks.get(task.as_ref()?)?.name))
+			})
+		})
+		.filter(|(time, ..)| *time >= now)
+		.sorted()
+	{
+		println!(
+			"{}\t{name}\t{task}",
+			time.with_timezone(&Local).to_rfc2822()
+		);
+	}
+	for Overlap {
+		time,
+		first: (first, first_task),
+		second: (second, second_task),
+	} in profile::overlaps(&profiles)
+	{
+		eprintln!(
+			"Overlap: at {}, {first} has {first_task} and {second} has {second_task}",
+			time.with_timezone(&Local).to_rfc2822()
+		);
+	}
+	Ok(())
+}
+
+/// List the backups, or swap one in for the database.
+/// This runs instead of loading the database, since the database might not load.
+pub fn restore(storage: &mut Shared, number: Option<usize>) -> Result<(), String> {
+	let backups = storage.backups().map_err(|e| e.to_string())?;
+	let Some(number) = number else {
+		if backups.is_empty() {
+			println!("No backups yet");
+		}
+		for (number, backup) in backups.iter().enumerate() {
+			let tasks = storage.load_backup(backup).map_or_else(
+				|e| format!("unreadable: {e}"),
+				|db| format!("{} tasks", db.tasks.len()),
+			);
+			println!(
+				"{}\t{}\t{tasks}",
+				number + 1,
+				backup
+					.time
+					.with_timezone(&Local)
+					.format("%a %d %b %Y %H:%M:%S"),
+			);
+		}
+		return Ok(());
+	};
+	let backup = number
+		.checked_sub(1)
+		.and_then(|index| backups.get(index))
+		.ok_or_else(|| format!("There is no backup {number}"))?;
+	let db = storage.restore(backup).map_err(|e| e.to_string())?;
+	println!(
+		"Restored the backup from {}, with {} tasks",
+		backup
+			.time
+			.with_timezone(&Local)
+			.format("%a %d %b %Y %H:%M:%S"),
+		db.tasks.len()
+	);
+	Ok(())
+}
+
+fn add(
+	db: &mut Db,
+	NewTask {
+		name,
+		due,
+		start,
+		estimate,
+		priority,
+		project,
+		tags,
+		dry_run,
+	}: NewTask,
+) -> Result<(), String> {
+	if due < start {
+		return Err("The task must be due after it starts".into());
+	}
+	let estimated_length =
+		length::parse(&estimate, db.timeslice_length).map_err(|e| e.to_string())?;
+	let task = CTask {
+		priority,
+		project,
+		tags: tags.into_iter().collect(),
+		..CTask::new(name, start..due, estimated_length)
+	};
+	if dry_run {
+		let diff = db.what_if(|db| db.insert_task(db.new_id(), task.clone()));
+		for line in app::diff_lines(&diff) {
+			println!("{line}");
+		}
+		return Ok(());
+	}
+	let time = |time: DateTime<Utc>| time.with_timezone(&Local).format("%a %d %b %Y %H:%M");
+	let added = format!(
+		"Added {}, taking {}, from {} until {}",
+		task.name,
+		length::format(task.estimated_length),
+		time(task.working_period.start),
+		time(task.working_period.end)
+	);
+	db.record(format!("Add {}", task.name));
+	let before = db.schedule.clone();
+	db.insert_task(db.new_id(), task);
+	let moved = PlanDiff::between(&before, &db.schedule).slots_moved;
+	println!("{added}{}", app::moved_note(moved));
+	Ok(())
+}
+
+fn shuffle(
+	db: &mut Db,
+	setup: &Setup,
+	name: &str,
+	seed: Option<u64>,
+	iterations: Option<usize>,
+) -> Result<(), String> {
+	let Some(strategy) = setup.config.strategy(name) else {
+		return Err(format!("Unknown strategy {name:?}"));
+	};
+	let budget = iterations.map_or(
+		ShuffleBudget::Time(setup.settings.shuffle_time()),
+		ShuffleBudget::Iterations,
+	);
+	db.record(format!("Shuffle for {}", strategy.name));
+	let context = Context::from(&*db);
+	let ShuffleRecord {
+		seed,
+		iterations,
+		score,
+		..
+	} = db.shuffle_seeded(
+		&strategy.name,
+		|s| strategy.score(s, &context),
+		budget,
+		seed.unwrap_or_else(|| thread_rng().gen()),
+	);
+	println!(
+		"{}: scored {score} after trying {iterations} times (seed {seed})",
+		strategy.name
+	);
+	for line in app::metric_lines(db, &strategy) {
+		eprintln!("  {line}");
+	}
+	Ok(())
+}
@@ -0,0 +1,232 @@
+//! User configuration, stored as TOML in the XDG config directory.
+
+use crate::{
+	profile::{Profile, DEFAULT_PROFILE},
+	storage::backup::BackupPolicy,
+	strategy::{Strategy, StrategyError},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::BTreeMap,
+	io,
+	path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+mod settings;
+
+pub use settings::Settings;
+
+/// The contents of the config file.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Config {
+	/// Custom strategies, which are offered alongside the built-in ones.
+	pub strategies: Vec<Strategy>,
+	/// Settings for every profile, which profiles can override.
+	pub settings: Settings,
+	/// Which backups of the database to keep.
+	pub backups: BackupPolicy,
+	/// Named profiles, each with its own database.
+	pub profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+	#[error("Error reading config file")]
+	Io(#[from] io::Error),
+	#[error("Error parsing config file")]
+	Parse(#[from] toml::de::Error),
+	#[error("Error editing config file: {0}")]
+	Edit(#[from] toml_edit::TomlError),
+	#[error(transparent)]
+	Strategy(#[from] StrategyError),
+	#[error("Bad setting: {0}")]
+	Setting(String),
+	#[error("Unknown setting {0:?}")]
+	UnknownSetting(String),
+}
+
+impl Config {
+	/// The default location of the config file, `$XDG_CONFIG_HOME/pomeranian/config.toml` on Linux.
+	#[must_use]
+	pub fn default_path() -> Option<PathBuf> {
+		dirs::config_dir().map(|dir| dir.join("pomeranian").join("config.toml"))
+	}
+
+	/// Load the config file at a path, or the default config if there isn't one.
+	pub fn load(path: &Path) -> Result<Self, ConfigError> {
+		match std::fs::read_to_string(path) {
+			Ok(text) => Self::parse(&text),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+			Err(e) => Err(e.into()),
+		}
+	}
+
+	/// Parse and validate a config file.
+	pub fn parse(text: &str) -> Result<Self, ConfigError> {
+		let config: Self = toml::from_str(text)?;
+		for strategy in &config.strategies {
+			strategy.validate()?;
+		}
+		config.settings(None)?;
+		for name in config.profiles.keys() {
+			config.settings(Some(name))?;
+		}
+		Ok(config)
+	}
+
+	/// The settings for a profile, which are the shared settings with the profile's own settings on top.
+	pub fn settings(&self, profile: Option<&str>) -> Result<Settings, ConfigError> {
+		let mut table = toml::Table::try_from(&self.settings).expect("Settings are a table");
+		if let Some(profile) = profile.and_then(|name| self.profiles.get(name)) {
+			table.extend(profile.settings.clone());
+		}
+		let settings: Settings = table.try_into()?;
+		settings.validate()?;
+		Ok(settings)
+	}
+
+	/// Change a setting in the config file at a path, for one profile or for all of them,
+	/// keeping the rest of the file, comments and all.
+	pub fn set_setting(
+		path: &Path,
+		profile: Option<&str>,
+		name: &str,
+		value: &str,
+	) -> Result<(), ConfigError> {
+		let text = match std::fs::read_to_string(path) {
+			Ok(text) => text,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+			Err(e) => return Err(e.into()),
+		};
+		let value = Self::parse(&text)?.settings(profile)?.set(name, value)?;
+		let mut document: toml_edit::DocumentMut = text.parse()?;
+		let path_to_table = match profile {
+			Some(profile) => vec!["profiles", profile, "settings"],
+			None => vec!["settings"],
+		};
+		let mut table = document.as_table_mut();
+		for (depth, key) in path_to_table.iter().enumerate() {
+			table = table
+				.entry(key)
+				.or_insert_with(|| {
+					let mut table = toml_edit::Table::new();
+					// Only the innermost table needs a header
+					table.set_implicit(depth + 1 < path_to_table.len());
+					toml_edit::Item::Table(table)
+				})
+				.as_table_mut()
+				.ok_or_else(|| ConfigError::Setting(format!("{key} isn't a table")))?;
+		}
+		table[name] = toml_edit::value(value.to_string().parse::<toml_edit::Value>()?);
+		let text = document.to_string();
+		Self::parse(&text)?;
+
+		if let Some(parent) = path.parent() {
+			std::fs::create_dir_all(parent)?;
+		}
+		let mut partial = path.as_os_str().to_os_string();
+		partial.push(".partial");
+		std::fs::write(&partial, text)?;
+		std::fs::rename(&partial, path)?;
+		Ok(())
+	}
+
+	/// Every strategy the user can pick from, built-in ones first.
+	#[must_use]
+	pub fn all_strategies(&self) -> Vec<Strategy> {
+		Strategy::builtin()
+			.into_iter()
+			.chain(self.strategies.iter().cloned())
+			.collect()
+	}
+
+	/// Every profile, including the default one even if it isn't configured.
+	#[must_use]
+	pub fn all_profiles(&self) -> BTreeMap<String, Profile> {
+		let mut profiles = self.profiles.clone();
+		profiles.entry(DEFAULT_PROFILE.to_string()).or_default();
+		profiles
+	}
+
+	/// Find a profile by its name.
+	#[must_use]
+	pub fn profile(&self, name: &str) -> Option<Profile> {
+		self.all_profiles().remove(name)
+	}
+
+	/// Find a strategy by its name, ignoring case.
+	#[must_use]
+	pub fn strategy(&self, name: &str) -> Option<Strategy> {
+		self.all_strategies()
+			.into_iter()
+			.find(|strategy| strategy.name.eq_ignore_ascii_case(name))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Config;
+	use crate::{profile::DEFAULT_PROFILE, storage::backup::BackupPolicy};
+
+	#[test]
+	fn parse_strategies() {
+		let config = Config::parse(
+			r#"
+			[[strategies]]
+			name = "Morning Focus"
+			description = "Stay on one task at a time, without front-loading work"
+			weights = { hyperfocus = 0.7, early_riser = -0.3 }
+
+			[profiles.degree]
+			db_path = "degree.sqlite"
+			"#,
+		)
+		.expect("Valid config");
+		assert_eq!(config.strategies.len(), 1);
+		assert_eq!(config.backups, BackupPolicy::default());
+		assert!(config.profile("degree").is_some());
+		assert!(config.profile(DEFAULT_PROFILE).is_some());
+		assert!(config.profile("job").is_none());
+		assert!(config.strategy("morning focus").is_some());
+		assert!(config.strategy("hyperfocus").is_some());
+
+		assert!(Config::parse(
+			r#"
+			[[strategies]]
+			name = "Broken"
+			weights = { focus = 1.0 }
+			"#,
+		)
+		.is_err());
+	}
+
+	#[test]
+	fn edit_settings() {
+		let path =
+			std::env::temp_dir().join(format!("pomeranian-config-{}.toml", std::process::id()));
+		std::fs::write(
+			&path,
+			"# Comments are kept\n[profiles.degree]\ndb_path = \"degree.sqlite\"\n",
+		)
+		.expect("Written");
+		Config::set_setting(&path, None, "timeslice_minutes", "50").expect("Set");
+		Config::set_setting(&path, Some("degree"), "timeslice_minutes", "30").expect("Set");
+		assert!(Config::set_setting(&path, None, "timeslice_minutes", "soon").is_err());
+
+		let text = std::fs::read_to_string(&path).expect("Read");
+		assert!(text.starts_with("# Comments are kept"));
+		let config = Config::parse(&text).expect("Valid config");
+		let minutes = |profile| {
+			config
+				.settings(profile)
+				.expect("Valid settings")
+				.timeslice_minutes
+		};
+		assert_eq!(minutes(None), 50);
+		assert_eq!(minutes(Some("degree")), 30);
+		assert_eq!(minutes(Some(DEFAULT_PROFILE)), 50);
+		let _ = std::fs::remove_file(&path);
+	}
+}
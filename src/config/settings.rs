@@ -0,0 +1,205 @@
+//! Settings for how the day is divided up, and for how pomeranian behaves, in the `[settings]` table of the config file.
+
+use super::ConfigError;
+use crate::db::Cycle;
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The longest that slots, breaks, imported tasks' estimates and shuffles can be set to, in minutes.
+const LONGEST_MINUTES: u64 = 24 * 60;
+
+/// Settings that apply to every profile, unless the profile overrides them in its own `[profiles.<name>.settings]` table.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+	/// The length of a work slot, in minutes.
+	pub timeslice_minutes: u64,
+	/// The length of a short break, in minutes.
+	pub short_break_minutes: u64,
+	/// The length of a long break, in minutes.
+	pub long_break_minutes: u64,
+	/// How many work slots come before each long break.
+	pub break_interval: u32,
+	/// When work starts each day, local time.
+	pub day_start: NaiveTime,
+	/// When work ends each day, local time.
+	pub day_end: NaiveTime,
+	/// The notification for starting a work slot, with `{task}` standing for the task's name.
+	pub start_notification: String,
+	/// The notification for finishing a work slot, with `{task}` standing for the task's name.
+	pub done_notification: String,
+	/// How long to spend shuffling for a strategy, in seconds.
+	pub shuffle_seconds: f64,
+	/// How long a task imported from a calendar is estimated to take, in minutes.
+	pub import_estimate_minutes: u64,
+	/// Whether to keep the plan as it is wherever possible when tasks change or everything is rescheduled,
+	/// instead of placing tasks by energy again and rescheduling from scratch.
+	pub stable_plan: bool,
+}
+
+impl Default for Settings {
+	fn default() -> Self {
+		let cycle = Cycle::default();
+		let minutes = |length: Duration| length.as_secs() / 60;
+		Self {
+			timeslice_minutes: minutes(cycle.timeslice_length),
+			short_break_minutes: minutes(cycle.short_break),
+			long_break_minutes: minutes(cycle.long_break),
+			break_interval: cycle.break_interval,
+			day_start: cycle.active_period.start,
+			day_end: cycle.active_period.end,
+			start_notification: "Start working on {task}".into(),
+			done_notification: "Done working on {task}".into(),
+			shuffle_seconds: 0.5,
+			import_estimate_minutes: 60,
+			stable_plan: false,
+		}
+	}
+}
+
+impl Settings {
+	/// Check that the settings make sense together.
+	pub fn validate(&self) -> Result<(), ConfigError> {
+		let too_long = [
+			("timeslice_minutes", self.timeslice_minutes),
+			("short_break_minutes", self.short_break_minutes),
+			("long_break_minutes", self.long_break_minutes),
+			("import_estimate_minutes", self.import_estimate_minutes),
+		]
+		.into_iter()
+		.find(|(_, minutes)| *minutes > LONGEST_MINUTES);
+		let problem = if self.timeslice_minutes == 0 {
+			"timeslice_minutes must be more than 0".into()
+		} else if let Some((name, _)) = too_long {
+			format!("{name} must be at most a day ({LONGEST_MINUTES})")
+		} else if self.break_interval == 0 {
+			"break_interval must be more than 0".into()
+		} else if self.day_start >= self.day_end {
+			"day_start must be before day_end".into()
+		} else if !(self.shuffle_seconds.is_finite() && self.shuffle_seconds > 0.0) {
+			"shuffle_seconds must be more than 0".into()
+		} else if Duration::try_from_secs_f64(self.shuffle_seconds)
+			.map_or(true, |time| time > Duration::from_mins(LONGEST_MINUTES))
+		{
+			format!(
+				"shuffle_seconds must be at most a day ({})",
+				LONGEST_MINUTES * 60
+			)
+		} else {
+			return Ok(());
+		};
+		Err(ConfigError::Setting(problem))
+	}
+
+	/// How the day is divided into slots and breaks.
+	#[must_use]
+	pub fn cycle(&self) -> Cycle {
+		Cycle {
+			timeslice_length: Duration::from_mins(self.timeslice_minutes),
+			short_break: Duration::from_mins(self.short_break_minutes),
+			long_break: Duration::from_mins(self.long_break_minutes),
+			break_interval: self.break_interval,
+			active_period: self.day_start..self.day_end,
+		}
+	}
+
+	/// How long to spend shuffling for a strategy.
+	#[must_use]
+	pub fn shuffle_time(&self) -> Duration {
+		Duration::from_secs_f64(self.shuffle_seconds)
+	}
+
+	/// How long a task imported from a calendar is estimated to take.
+	#[must_use]
+	pub fn import_estimate(&self) -> Duration {
+		Duration::from_mins(self.import_estimate_minutes)
+	}
+
+	/// Fill a task's name into a notification.
+	#[must_use]
+	pub fn notification(template: &str, task: &str) -> String {
+		template.replace("{task}", task)
+	}
+
+	/// Every setting's name and value, as they'd be written in the config file.
+	#[must_use]
+	pub fn entries(&self) -> Vec<(String, String)> {
+		toml::Table::try_from(self)
+			.expect("Settings are a table")
+			.into_iter()
+			.map(|(name, value)| {
+				let value = match value {
+					toml::Value::String(text) => text,
+					value => value.to_string(),
+				};
+				(name, value)
+			})
+			.collect()
+	}
+
+	/// Change a setting, parsing the value as whatever type the setting has.
+	/// Returns the value as it should be written in the config file.
+	pub fn set(&mut self, name: &str, value: &str) -> Result<toml::Value, ConfigError> {
+		let mut table = toml::Table::try_from(&*self).expect("Settings are a table");
+		let bad_value = || ConfigError::Setting(format!("{value:?} isn't a valid {name}"));
+		let value = match table.get(name) {
+			None => return Err(ConfigError::UnknownSetting(name.to_string())),
+			Some(toml::Value::Integer(_)) => {
+				toml::Value::Integer(value.trim().parse().map_err(|_| bad_value())?)
+			}
+			Some(toml::Value::Float(_)) => {
+				toml::Value::Float(value.trim().parse().map_err(|_| bad_value())?)
+			}
+			Some(toml::Value::Boolean(_)) => {
+				toml::Value::Boolean(match value.trim().to_lowercase().as_str() {
+					"true" | "yes" | "on" => true,
+					"false" | "no" | "off" => false,
+					_ => return Err(bad_value()),
+				})
+			}
+			Some(_) => toml::Value::String(value.to_string()),
+		};
+		table.insert(name.to_string(), value.clone());
+		let settings: Self = table.try_into().map_err(|_| bad_value())?;
+		settings.validate()?;
+		*self = settings;
+		Ok(value)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Settings;
+	use chrono::NaiveTime;
+	use std::time::Duration;
+
+	#[test]
+	fn set_settings() {
+		let mut settings = Settings::default();
+		settings.set("timeslice_minutes", "50").expect("Valid");
+		settings.set("day_start", "08:30").expect("Valid");
+		settings.set("shuffle_seconds", "2").expect("Valid");
+		settings.set("stable_plan", "yes").expect("Valid");
+		assert!(settings.stable_plan);
+		assert_eq!(settings.cycle().timeslice_length, Duration::from_mins(50));
+		assert_eq!(
+			settings.day_start,
+			NaiveTime::from_hms_opt(8, 30, 0).expect("Valid time")
+		);
+		assert_eq!(settings.shuffle_time(), Duration::from_secs(2));
+
+		assert!(settings.set("timeslice_minutes", "-5").is_err());
+		assert!(settings.set("day_end", "08:00").is_err());
+		assert!(settings.set("day_end", "noon").is_err());
+		assert!(settings.set("timeslice", "50").is_err());
+		assert!(settings.set("stable_plan", "maybe").is_err());
+		assert!(settings.set("timeslice_minutes", "1441").is_err());
+		assert!(settings
+			.set("long_break_minutes", &u64::MAX.to_string())
+			.is_err());
+		assert!(settings.set("shuffle_seconds", "1e300").is_err());
+		assert!(settings.set("short_break_minutes", "1440").is_ok());
+		assert_eq!(settings.cycle().timeslice_length, Duration::from_mins(50));
+	}
+}
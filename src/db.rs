@@ -1,16 +1,26 @@
 //! Wraps the core scheduler and pomodoro timer up together and allows storing it on disk
 
+pub mod journal;
+pub mod merge;
+pub mod migrations;
+
 use crate::{
+	db::journal::Journal,
 	pomodoro::Pomodoro,
-	scheduler::{Schedule, Task},
+	scheduler::{Outcome, Schedule, Task},
 };
-use chrono::{DateTime, Days, Local, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono::{DateTime, Days, Local, NaiveDateTime, NaiveTime, TimeZone, Timelike, Utc};
 use chrono_tz::Tz;
 use ical::{parser::ical::component::IcalEvent, property::Property};
+use itertools::Itertools;
+use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 use std::{
-	collections::{BTreeMap, HashMap},
-	ops::{Deref, Range},
+	collections::{BTreeMap, BTreeSet, HashMap},
+	convert::Infallible,
+	io::BufRead,
+	ops::{Bound, Deref, Range},
+	str::FromStr,
 	string::String,
 	sync::Arc,
 	time::{Duration, Instant},
@@ -18,9 +28,9 @@ use std::{
 use thiserror::Error;
 
 /// The database struct, as stored on disk.
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Db {
-	/// The schedule, which in this case operates on [CTask]s.
+	/// The schedule, which in this case operates on [`CTask`]s.
 	pub schedule: Schedule<CTask>,
 	/// The part of the day to schedule timeslots on.
 	pub active_period: Range<NaiveTime>,
@@ -32,24 +42,159 @@ pub struct Db {
 	pub long_break: Duration,
 	/// The list of pomodoro states that have already been created, which always correspond to a schedule slot.
 	pub pomodoro_states: Vec<(Range<DateTime<Utc>>, Pomodoro)>,
+	/// The strategy shuffle that produced the committed schedule, if there was one.
+	#[serde(default)]
+	pub last_shuffle: Option<ShuffleRecord>,
+	/// How much energy the user has at each time of day.
+	#[serde(default)]
+	pub energy_curve: EnergyCurve,
+	/// Every stretch of work that the timer has recorded, oldest first.
+	#[serde(default)]
+	pub history: Vec<Session>,
+	/// Slots that went by without any work on their task being recorded, oldest first,
+	/// until the user says whether they were done after all.
+	#[serde(default)]
+	pub missed: Vec<Session>,
+	/// Recent operations, for undoing and redoing them.
+	#[serde(default)]
+	pub journal: Journal,
+	/// Whether changes keep the plan as it is wherever they can, from the `stable_plan` setting.
+	#[serde(skip)]
+	pub stable_plan: bool,
 }
 
-impl Default for Db {
+/// A stretch of time that the user spent working on a task.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Session {
+	/// The ID of the task that was worked on. The task may since have been removed.
+	pub task: String,
+	/// When the work started and stopped.
+	pub period: Range<DateTime<Utc>>,
+}
+
+impl Session {
+	/// Whether two sessions are of the same task at overlapping times.
+	#[must_use]
+	pub fn overlaps(&self, other: &Session) -> bool {
+		self.task == other.task
+			&& self.period.start < other.period.end
+			&& other.period.start < self.period.end
+	}
+}
+
+/// The length of a generated task ID, in hex digits.
+const ID_LENGTH: usize = 16;
+/// The fewest digits of an ID that are shown to the user.
+const SHORT_ID_LENGTH: usize = 4;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum IdError {
+	#[error("No task has an ID starting with {0:?}")]
+	NotFound(String),
+	#[error("{prefix:?} could be any of {matches:?}")]
+	Ambiguous {
+		prefix: String,
+		matches: Vec<String>,
+	},
+}
+
+/// How much energy the user has in each hour of the day, local time, from 0 (none) to 10 (the most).
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EnergyCurve(pub [u8; 24]);
+
+impl Default for EnergyCurve {
+	fn default() -> Self {
+		Self([5; 24])
+	}
+}
+
+impl EnergyCurve {
+	/// The user's energy in the hour that a time falls in.
+	#[must_use]
+	pub fn at(&self, time: DateTime<Utc>) -> u8 {
+		self.0[time.with_timezone(&Local).hour() as usize]
+	}
+}
+
+/// Everything needed to reproduce a strategy shuffle, given the same starting schedule.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ShuffleRecord {
+	/// The name of the goal that was maximized.
+	pub goal: String,
+	/// The seed that the random number generator was created from.
+	pub seed: u64,
+	/// How many shuffles were tried.
+	pub iterations: usize,
+	/// The score of the committed schedule.
+	pub score: f64,
+}
+
+/// How long [`Db::shuffle_maximizing`] is allowed to keep trying.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShuffleBudget {
+	/// Keep shuffling until the time limit runs out. Fast, but not reproducible on its own.
+	Time(Duration),
+	/// Try exactly this many shuffles.
+	Iterations(usize),
+}
+
+/// How the active part of each day is divided into work slots and breaks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cycle {
+	/// The length of a work slot.
+	pub timeslice_length: Duration,
+	/// The length of a short break.
+	pub short_break: Duration,
+	/// The length of a long break.
+	pub long_break: Duration,
+	/// How many work slots come before each long break.
+	pub break_interval: u32,
+	/// The part of the day to schedule timeslots on.
+	pub active_period: Range<NaiveTime>,
+}
+
+impl Default for Cycle {
 	#[allow(clippy::unwrap_used)]
 	fn default() -> Self {
+		Self {
+			timeslice_length: Duration::from_mins(25),
+			short_break: Duration::from_mins(5),
+			long_break: Duration::from_mins(30),
+			break_interval: 4,
+			active_period: NaiveTime::from_hms_opt(9, 0, 0).unwrap()
+				..NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+		}
+	}
+}
+
+impl Default for Db {
+	fn default() -> Self {
+		let Cycle {
+			timeslice_length,
+			short_break,
+			long_break,
+			break_interval,
+			active_period,
+		} = Cycle::default();
 		Self {
 			schedule: Schedule {
 				tasks: HashMap::default(),
 				slots: BTreeMap::default(),
-				timeslice_length: Duration::from_secs(25 * 60),
+				timeslice_length,
+				pinned: BTreeSet::default(),
 			},
-			active_period: NaiveTime::from_hms_opt(9, 0, 0).unwrap()
-				..NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
-			break_interval: 4,
-			short_break: Duration::from_secs(5 * 60),
-			long_break: Duration::from_secs(30 * 60),
+			active_period,
+			break_interval,
+			short_break,
+			long_break,
 			// pomodoro: Pomodoro::LongBreak,
 			pomodoro_states: vec![],
+			last_shuffle: None,
+			energy_curve: EnergyCurve::default(),
+			history: vec![],
+			missed: vec![],
+			journal: Journal::default(),
+			stable_plan: false,
 		}
 	}
 }
@@ -65,18 +210,262 @@ impl Deref for Db {
 impl Db {
 	/// Perform housekeeping tasks to clean up old slots and such
 	pub fn housekeeping(&mut self) {
+		// One time for everything, so that a slot can't end between being checked for missing and being removed
+		let now = Utc::now();
 		self.create_slots_up_to(
 			self.schedule
 				.tasks
 				.values()
-				.map(|t| t.working_period.end)
+				.map(|t| t.allowed_period().end)
 				.max()
-				.unwrap_or(Utc::now()),
+				.unwrap_or(now),
 		);
-		self.schedule.remove_old_slots(Utc::now());
+		self.record_missed(now);
+		self.schedule.remove_old_slots(now);
 		self.pomodoro_states.sort_by_key(|(t, _)| t.start);
-		self.pomodoro_states.retain(|(t, _)| t.end > Utc::now());
+		self.pomodoro_states.retain(|(t, _)| t.end > now);
+		self.schedule_tasks();
+	}
+
+	/// Schedule every task, leaving out the time of the slots it missed until the user says whether they were done,
+	/// so that missed work isn't planned again before they've decided.
+	fn schedule_tasks(&mut self) {
+		let held_back = self.missed_totals();
+		let originals = held_back
+			.keys()
+			.filter_map(|id| Some((id.clone(), self.schedule.tasks.get(id)?.clone())))
+			.collect_vec();
+		for (id, (_, time)) in held_back {
+			if let Some(task) = self.schedule.tasks.get_mut(&id) {
+				let task = Arc::make_mut(task);
+				task.worked_length = task.worked_length.saturating_add(time);
+			}
+		}
 		self.schedule.schedule();
+		self.schedule.tasks.extend(originals);
+	}
+
+	/// Remember the slots that have ended without any work on their task being recorded, before they're thrown away.
+	fn record_missed(&mut self, now: DateTime<Utc>) {
+		let length = self.schedule.timeslice_length;
+		let missed = self
+			.schedule
+			.slots
+			.range(..now - length)
+			.filter_map(|(time, id)| Some((*time, id.clone()?)))
+			.filter(|(_, id)| self.schedule.tasks.contains_key(id))
+			.map(|(time, task)| Session {
+				task,
+				period: time..time + length,
+			})
+			.filter(|missed| !self.history.iter().any(|session| session.overlaps(missed)))
+			.collect_vec();
+		self.missed.extend(missed);
+	}
+
+	/// How many slots of each task were missed, and how long they add up to.
+	#[must_use]
+	pub fn missed_totals(&self) -> BTreeMap<String, (usize, Duration)> {
+		let mut totals = BTreeMap::<_, (usize, Duration)>::new();
+		for session in &self.missed {
+			let total = totals.entry(session.task.clone()).or_default();
+			total.0 += 1;
+			total.1 += (session.period.end - session.period.start)
+				.to_std()
+				.unwrap_or_default();
+		}
+		totals
+	}
+
+	/// Deal with a task's missed slots, counting `done` of them as worked, up to all of them, and leaving the rest to be rescheduled.
+	/// The time counted as worked goes into the history as if the timer had recorded it.
+	/// Returns how much was counted.
+	pub fn resolve_missed(&mut self, id: &str, done: Duration) -> Duration {
+		let (missed, others) = std::mem::take(&mut self.missed)
+			.into_iter()
+			.partition::<Vec<_>, _>(|session| session.task == id);
+		self.missed = others;
+		let mut credited = Duration::ZERO;
+		for mut session in missed {
+			let left = done.saturating_sub(credited);
+			if left.is_zero() {
+				break;
+			}
+			let length = (session.period.end - session.period.start)
+				.to_std()
+				.unwrap_or_default();
+			if length > left {
+				session.period.end = session.period.start + left;
+			}
+			credited += length.min(left);
+			self.history.push(session);
+		}
+		self.history.sort_by_key(|session| session.period.start);
+		self.credit(id, credited);
+		credited
+	}
+
+	/// Count time as worked on a task, up to its estimate, keeping its slots where they are.
+	pub fn credit(&mut self, id: &str, time: Duration) {
+		let Some(task) = self.schedule.tasks.get(id) else {
+			return;
+		};
+		let mut task = CTask::clone(task);
+		task.worked_length = (task.worked_length + time).min(task.estimated_length);
+		self.replace_task(id, task);
+	}
+
+	/// How the day is currently divided into slots and breaks.
+	#[must_use]
+	pub fn cycle(&self) -> Cycle {
+		Cycle {
+			timeslice_length: self.schedule.timeslice_length,
+			short_break: self.short_break,
+			long_break: self.long_break,
+			break_interval: self.break_interval,
+			active_period: self.active_period.clone(),
+		}
+	}
+
+	/// Change how the day is divided, regenerating the slots and breaks that haven't started yet.
+	/// Each task keeps its slots where it can, moving to the first free new slot that overlaps or follows each old one.
+	/// Pinned slots stay pinned in the same way.
+	/// Returns whether the cycle changed.
+	pub fn set_cycle(&mut self, cycle: Cycle) -> bool {
+		if self.cycle() == cycle {
+			return false;
+		}
+		let now = Utc::now();
+		let horizon = self.slots.keys().next_back().copied().unwrap_or(now);
+		let pins = self.pins_from(now);
+		let assigned = self.assigned_from(now);
+		self.schedule.slots.retain(|time, _| *time < now);
+		self.schedule.pinned.retain(|time| *time < now);
+		self.pomodoro_states.retain(|(time, _)| time.start < now);
+
+		self.schedule.timeslice_length = cycle.timeslice_length;
+		self.short_break = cycle.short_break;
+		self.long_break = cycle.long_break;
+		self.break_interval = cycle.break_interval;
+		self.active_period = cycle.active_period;
+		self.create_slots_up_to(horizon);
+		self.carry_pins(pins);
+		self.carry_slots(assigned);
+		self.settle();
+		true
+	}
+
+	/// The slots from a time onwards which have a task and aren't pinned.
+	fn assigned_from(&self, time: DateTime<Utc>) -> Vec<(DateTime<Utc>, String)> {
+		self.schedule
+			.slots
+			.range(time..)
+			.filter(|(time, _)| !self.schedule.is_pinned(**time))
+			.filter_map(|(time, id)| Some((*time, id.clone()?)))
+			.collect()
+	}
+
+	/// After the slots have been laid out again, give each task the first free new slot that overlaps or follows each of its old ones.
+	fn carry_slots(&mut self, assigned: Vec<(DateTime<Utc>, String)>) {
+		// Only carry over as many slots as each task wants with the new length, since scheduling frees the earliest extras
+		let length = self.schedule.timeslice_length;
+		let mut wants: HashMap<_, _> = self
+			.schedule
+			.tasks
+			.iter()
+			.map(|(id, task)| (id.clone(), task.divided_into(length)))
+			.collect();
+		for id in self.schedule.slots.values().flatten() {
+			if let Some(wants) = wants.get_mut(id) {
+				*wants = wants.saturating_sub(1);
+			}
+		}
+		for (time, id) in assigned {
+			let (Some(task), Some(wants)) = (self.schedule.tasks.get(&id), wants.get_mut(&id))
+			else {
+				continue;
+			};
+			if *wants == 0 {
+				continue;
+			}
+			let allowed = task.allowed_period();
+			if let Some((_, slot)) = self
+				.schedule
+				.slots
+				.iter_mut()
+				.skip_while(|(start, _)| **start + length <= time)
+				.find(|(start, slot)| {
+					slot.is_none()
+						&& allowed.contains(*start)
+						&& !self.schedule.pinned.contains(start)
+				}) {
+				*slot = Some(id);
+				*wants -= 1;
+			}
+		}
+	}
+
+	/// Schedule after a change, and place tasks by energy again unless the plan should stay stable.
+	fn settle(&mut self) {
+		self.schedule_tasks();
+		if !self.stable_plan {
+			self.place_by_energy();
+		}
+	}
+
+	/// Generate an ID for a new task, which no other task has.
+	#[must_use]
+	pub fn new_id(&self) -> String {
+		loop {
+			let id = format!("{:0width$x}", thread_rng().gen::<u64>(), width = ID_LENGTH);
+			if !self.schedule.tasks.contains_key(&id) {
+				return id;
+			}
+		}
+	}
+
+	/// The shortest start of a task's ID that no other task's ID starts with, for showing to the user.
+	#[must_use]
+	pub fn short_id<'a>(&self, id: &'a str) -> &'a str {
+		let shared = self
+			.schedule
+			.tasks
+			.keys()
+			.filter(|other| *other != id)
+			.map(|other| {
+				other
+					.bytes()
+					.zip(id.bytes())
+					.take_while(|(a, b)| a == b)
+					.count()
+			})
+			.max()
+			.unwrap_or(0);
+		let length = (shared + 1).max(SHORT_ID_LENGTH).min(id.len());
+		id.get(..length).unwrap_or(id)
+	}
+
+	/// Find the task whose ID starts with a prefix.
+	pub fn resolve_id(&self, prefix: &str) -> Result<String, IdError> {
+		if self.schedule.tasks.contains_key(prefix) {
+			return Ok(prefix.to_string());
+		}
+		let matches = self
+			.schedule
+			.tasks
+			.keys()
+			.filter(|id| id.starts_with(prefix))
+			.cloned()
+			.sorted()
+			.collect_vec();
+		match matches.len() {
+			0 => Err(IdError::NotFound(prefix.to_string())),
+			1 => Ok(matches.into_iter().next().expect("One match")),
+			_ => Err(IdError::Ambiguous {
+				prefix: prefix.to_string(),
+				matches,
+			}),
+		}
 	}
 
 	/// Fill out slots and pomodoro states up to the specified time.
@@ -113,7 +502,7 @@ impl Db {
 				Pomodoro::LongBreak => {
 					cursor += self.long_break;
 				}
-			};
+			}
 			let local_cursor = cursor.with_timezone(&Local);
 			if local_cursor > local_cursor.with_time(self.active_period.end).unwrap() {
 				let local_cursor = (local_cursor
@@ -127,39 +516,194 @@ impl Db {
 		}
 	}
 
+	/// Lay out the slots again from now and schedule every task, throwing away the last shuffle.
+	/// Pinned slots stay pinned, in the new slots that they overlap or come just before.
+	/// A stable plan keeps every task's slots in the same way, and otherwise everything else is scheduled from scratch.
+	/// Returns how many slots that tasks had moved.
+	pub fn reschedule(&mut self) -> usize {
+		let before = self.schedule.clone();
+		self.last_shuffle = None;
+		// Including the slot that's under way, since the new slots start now
+		let since = Utc::now() - self.schedule.timeslice_length;
+		let pins = self.pins_from(since);
+		let assigned = self.assigned_from(since);
+		self.schedule.slots.clear();
+		self.schedule.pinned.clear();
+		self.pomodoro_states.clear();
+		for (_id, task) in self.schedule.tasks.clone() {
+			self.create_slots_up_to(task.allowed_period().end);
+		}
+		self.carry_pins(pins);
+		if self.stable_plan {
+			self.carry_slots(assigned);
+		}
+		self.settle();
+		PlanDiff::between(&before, &self.schedule).slots_moved
+	}
+
+	/// The pinned slots from a time onwards, with what they're pinned to.
+	fn pins_from(&self, time: DateTime<Utc>) -> Vec<(DateTime<Utc>, Option<String>)> {
+		self.schedule
+			.pinned
+			.range(time..)
+			.filter_map(|time| Some((*time, self.schedule.slots.get(time)?.clone())))
+			.collect()
+	}
+
+	/// After the slots have been laid out again, pin the first new slot that overlaps or follows each old pinned slot.
+	fn carry_pins(&mut self, pins: Vec<(DateTime<Utc>, Option<String>)>) {
+		let length = self.schedule.timeslice_length;
+		for (time, id) in pins {
+			let new = self
+				.schedule
+				.slots
+				.keys()
+				.find(|start| **start + length > time && !self.schedule.is_pinned(**start))
+				.copied();
+			if let Some(new) = new {
+				self.schedule.pin(new, id);
+			}
+		}
+	}
+
+	/// Pin a slot to a task, or keep it free with `None`, and schedule everything else around it.
+	/// Returns false if there's no slot at that time.
+	pub fn pin_slot(&mut self, time: DateTime<Utc>, id: Option<String>) -> bool {
+		if !self.schedule.pin(time, id) {
+			return false;
+		}
+		self.last_shuffle = None;
+		self.settle();
+		true
+	}
+
+	/// Let scheduling and shuffling move what's in a slot again. Returns whether it was pinned.
+	pub fn unpin_slot(&mut self, time: DateTime<Utc>) -> bool {
+		if !self.schedule.unpin(time) {
+			return false;
+		}
+		self.last_shuffle = None;
+		self.settle();
+		true
+	}
+
+	/// Add a task for each event in an iCalendar feed that hasn't been imported already,
+	/// estimated to take `estimate`. Returns how many tasks were added.
+	pub fn import_calendar(&mut self, calendar: impl BufRead, estimate: Duration) -> usize {
+		let mut added = 0;
+		for calendar in ical::IcalParser::new(calendar).flatten() {
+			for event in &calendar.events {
+				let Ok(task) = CTask::from_event(event, estimate) else {
+					continue;
+				};
+				if !self
+					.schedule
+					.tasks
+					.values()
+					.any(|existing| existing.remote_id == task.remote_id)
+				{
+					self.insert_task(self.new_id(), task);
+					added += 1;
+				}
+			}
+		}
+		added
+	}
+
 	/// Insert a task and ensure we've done our best to schedule it.
 	pub fn insert_task(&mut self, id: String, task: impl Into<Arc<CTask>>) {
 		let task = task.into();
-		self.create_slots_up_to(task.working_period.end);
+		self.last_shuffle = None;
+		self.create_slots_up_to(task.allowed_period().end);
 		self.schedule.tasks.insert(id, task);
-		self.schedule.schedule();
+		self.settle();
+	}
+
+	/// Change a task, keeping the slots it has wherever they're still in its working and grace periods.
+	pub fn replace_task(&mut self, id: &str, task: impl Into<Arc<CTask>>) {
+		let task = task.into();
+		self.last_shuffle = None;
+		self.create_slots_up_to(task.allowed_period().end);
+		let allowed = task.allowed_period();
+		for (time, slot) in &mut self.schedule.slots {
+			if slot.as_deref() == Some(id)
+				&& !allowed.contains(time)
+				&& !self.schedule.pinned.contains(time)
+			{
+				*slot = None;
+			}
+		}
+		self.schedule.tasks.insert(id.to_string(), task);
+		self.settle();
 	}
 
-	/// Remove a task from the schedule.
+	/// Remove a task from the schedule, unpinning any slots that were pinned to it and forgetting any it missed.
 	pub fn remove_task(&mut self, id: &str) -> Option<Arc<CTask>> {
-		self.schedule
-			.slots
-			.values_mut()
-			.filter(|v| v.as_ref().map(String::as_str) == Some(id))
-			.for_each(|v| *v = None);
+		self.last_shuffle = None;
+		for (time, slot) in &mut self.schedule.slots {
+			if slot.as_deref() == Some(id) {
+				*slot = None;
+				self.schedule.pinned.remove(time);
+			}
+		}
+		self.missed.retain(|session| session.task != id);
 		let task = self.schedule.tasks.remove(id);
-		self.schedule.schedule();
+		self.settle();
 		task
 	}
 
-	/// Shuffle the schedule as many times as we can in the specified time limit, committing the permutation that got the highest score under the input Fn.
+	/// Move demanding, high-priority tasks into the slots where the user has the most energy.
+	/// Returns how many swaps were made.
+	pub fn place_by_energy(&mut self) -> usize {
+		if self
+			.schedule
+			.tasks
+			.values()
+			.all(|task| task.energy.is_none())
+		{
+			return 0;
+		}
+		let curve = self.energy_curve;
+		self.schedule
+			.place_by(|task, time| task.energy_demand() * f64::from(curve.at(time)))
+	}
+
+	/// Add up the tasks in each project. Tasks without a project are totalled under `None`.
+	#[must_use]
+	pub fn project_totals(&self) -> BTreeMap<Option<String>, ProjectTotals> {
+		let mut totals = BTreeMap::<_, ProjectTotals>::new();
+		for (id, task) in &self.schedule.tasks {
+			let total = totals.entry(task.project.clone()).or_default();
+			total.tasks += 1;
+			total.estimated_length += task.estimated_length;
+			total.worked_length += task.worked_length;
+			total.slots += self
+				.schedule
+				.slots
+				.values()
+				.filter(|slot| slot.as_ref() == Some(id))
+				.count();
+		}
+		totals
+	}
+
+	/// Shuffle the schedule as many times as the budget allows, committing the permutation that got the highest score under the input Fn.
 	pub fn shuffle_maximizing(
 		&mut self,
 		goal: impl Fn(&Schedule<CTask>) -> f64,
-		time_limit: Duration,
+		budget: ShuffleBudget,
+		rng: &mut impl Rng,
 	) -> (f64, usize) {
 		let started_at = Instant::now();
 		let mut score_to_beat = goal(&self.schedule);
 		let mut iterations = 0;
 
-		while started_at.elapsed() < time_limit {
+		while match budget {
+			ShuffleBudget::Time(time_limit) => started_at.elapsed() < time_limit,
+			ShuffleBudget::Iterations(limit) => iterations < limit,
+		} {
 			let mut copy = self.schedule.clone();
-			copy.shuffle();
+			copy.shuffle(rng);
 			let score = goal(&copy);
 			if score > score_to_beat {
 				self.schedule = copy;
@@ -170,6 +714,37 @@ impl Db {
 
 		(score_to_beat, iterations)
 	}
+
+	/// Shuffle for a named goal from a seed, and remember how to reproduce the result.
+	/// Running this again on the same schedule with the returned seed and [`ShuffleBudget::Iterations`] gives the same plan.
+	pub fn shuffle_seeded(
+		&mut self,
+		name: &str,
+		goal: impl Fn(&Schedule<CTask>) -> f64,
+		budget: ShuffleBudget,
+		seed: u64,
+	) -> ShuffleRecord {
+		let (score, iterations) =
+			self.shuffle_maximizing(goal, budget, &mut StdRng::seed_from_u64(seed));
+		let record = ShuffleRecord {
+			goal: name.to_string(),
+			seed,
+			iterations,
+			score,
+		};
+		self.last_shuffle = Some(record.clone());
+		record
+	}
+
+	/// Try out a change on a copy of the database, without touching this one,
+	/// and see how the plan would change once everything is scheduled again.
+	#[must_use]
+	pub fn what_if(&self, change: impl FnOnce(&mut Db)) -> PlanDiff {
+		let mut changed = self.clone();
+		change(&mut changed);
+		changed.schedule_tasks();
+		PlanDiff::between(&self.schedule, &changed.schedule)
+	}
 }
 
 /// Constant Task, an implementor of Task with constant fields.
@@ -187,6 +762,275 @@ pub struct CTask {
 	pub name: String,
 	/// The remote ID of a task, if it has one
 	pub remote_id: Option<String>,
+	/// Lets the task be scheduled after its due date, if it can't be finished on time.
+	#[serde(default)]
+	pub soft_deadline: Option<SoftDeadline>,
+	/// The fewest slots in a row this task should be worked on for, if there's a minimum.
+	#[serde(default)]
+	pub min_block: Option<u64>,
+	/// The most slots in a row this task should be worked on for, if there's a maximum.
+	#[serde(default)]
+	pub max_block: Option<u64>,
+	/// The most slots this task should get in one day, if there's a maximum.
+	#[serde(default)]
+	pub daily_cap: Option<u64>,
+	/// The fewest days this task should be spread out over, if it should be spread out.
+	#[serde(default)]
+	pub min_days: Option<u64>,
+	/// How much energy this task needs, on the same scale as the [`EnergyCurve`], if it's demanding.
+	#[serde(default)]
+	pub energy: Option<u8>,
+	/// The project this task is part of, such as a course, client or repository.
+	#[serde(default)]
+	pub project: Option<String>,
+	/// Free-form labels. Tags starting with `@` are contexts, like `@computer` or `@library`.
+	#[serde(default)]
+	pub tags: BTreeSet<String>,
+}
+
+impl CTask {
+	/// A task with a name, when it can be worked on and how long it should take, and nothing else set.
+	#[must_use]
+	pub fn new(
+		name: String,
+		working_period: Range<DateTime<Utc>>,
+		estimated_length: Duration,
+	) -> Self {
+		Self {
+			priority: 0,
+			working_period,
+			estimated_length,
+			worked_length: Duration::ZERO,
+			name,
+			remote_id: None,
+			soft_deadline: None,
+			min_block: None,
+			max_block: None,
+			daily_cap: None,
+			min_days: None,
+			energy: None,
+			project: None,
+			tags: BTreeSet::new(),
+		}
+	}
+
+	/// The tags that say where this task can be done, like `@library`.
+	pub fn contexts(&self) -> impl Iterator<Item = &str> {
+		self.tags
+			.iter()
+			.map(String::as_str)
+			.filter(|tag| tag.starts_with('@'))
+	}
+
+	/// How much of the estimate hasn't been worked yet.
+	#[must_use]
+	pub fn remaining_length(&self) -> Duration {
+		self.estimated_length.saturating_sub(self.worked_length)
+	}
+
+	/// How strongly this task wants high-energy slots: its required energy, scaled up by its priority.
+	#[must_use]
+	pub fn energy_demand(&self) -> f64 {
+		f64::from(self.energy.unwrap_or(0)) * (f64::from(self.priority) + 1.0)
+	}
+}
+
+/// What all of the tasks in a project add up to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProjectTotals {
+	/// How many tasks are in the project.
+	pub tasks: usize,
+	/// How many slots the project's tasks have been given.
+	pub slots: usize,
+	/// The total estimated length of the project's tasks.
+	pub estimated_length: Duration,
+	/// The total time worked on the project's tasks.
+	pub worked_length: Duration,
+}
+
+/// How a change to the database would affect the plan, from [`Db::what_if`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PlanDiff {
+	/// The slots that would go to a different task, or to none, with the task before and after.
+	pub moved: BTreeMap<DateTime<Utc>, (Option<String>, Option<String>)>,
+	/// Every task whose slots or satisfaction would change, by ID.
+	pub tasks: BTreeMap<String, TaskChange>,
+	/// How many slots that tasks had would move or be taken away from them.
+	/// A slot isn't counted when the task still has a slot that overlaps it, even if the slots have been laid out again.
+	pub slots_moved: usize,
+}
+
+/// How one task would fare before and after a change.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TaskChange {
+	/// The task's name, from after the change if it's still there.
+	pub name: String,
+	/// How many slots the task has, if it exists.
+	pub before: Option<u64>,
+	pub after: Option<u64>,
+	/// How many slots the task is missing, before and after.
+	pub missing_before: u64,
+	pub missing_after: u64,
+}
+
+impl TaskChange {
+	/// Whether the task would go from having all of its slots to missing some.
+	#[must_use]
+	pub fn becomes_unsatisfied(&self) -> bool {
+		self.before.is_some() && self.missing_before == 0 && self.missing_after > 0
+	}
+
+	/// How many slots the task would lose, if it was already there.
+	#[must_use]
+	pub fn lost(&self) -> u64 {
+		self.before
+			.zip(self.after)
+			.map_or(0, |(before, after)| before.saturating_sub(after))
+	}
+}
+
+impl PlanDiff {
+	/// Compare two plans, slot by slot and task by task.
+	#[must_use]
+	pub fn between(before: &Schedule<CTask>, after: &Schedule<CTask>) -> Self {
+		fn counts(schedule: &Schedule<CTask>) -> HashMap<&str, u64> {
+			let mut counts = HashMap::new();
+			for id in schedule.slots.values().flatten() {
+				*counts.entry(id.as_str()).or_default() += 1;
+			}
+			counts
+		}
+
+		let moved = before
+			.slots
+			.iter()
+			.filter_map(|(time, id)| {
+				let other = after.slots.get(time)?;
+				(id != other).then(|| (*time, (id.clone(), other.clone())))
+			})
+			.collect();
+
+		// Slots that ended before the new plan starts have just gone by
+		let new_start = after
+			.slots
+			.keys()
+			.next()
+			.copied()
+			.unwrap_or(DateTime::<Utc>::MAX_UTC);
+		let slots_moved = before
+			.slots
+			.iter()
+			.filter(|(time, _)| **time + before.timeslice_length > new_start)
+			.filter_map(|(time, id)| Some((*time, id.as_deref()?)))
+			.filter(|(_, id)| after.tasks.contains_key(*id))
+			.filter(|(time, id)| {
+				let overlapping = (
+					Bound::Excluded(*time - after.timeslice_length),
+					Bound::Excluded(*time + before.timeslice_length),
+				);
+				!after
+					.slots
+					.range(overlapping)
+					.any(|(_, other)| other.as_deref() == Some(*id))
+			})
+			.count();
+
+		let (counts_before, counts_after) = (counts(before), counts(after));
+		let (outcomes_before, outcomes_after) = (before.outcomes(), after.outcomes());
+		let missing = |outcomes: &BTreeMap<String, Outcome>, id: &str| {
+			outcomes.get(id).map_or(0, |outcome| outcome.missing)
+		};
+		let tasks = before
+			.tasks
+			.keys()
+			.chain(after.tasks.keys())
+			.unique()
+			.filter_map(|id| {
+				let slots = |schedule: &Schedule<CTask>, counts: &HashMap<&str, u64>| {
+					schedule
+						.tasks
+						.contains_key(id)
+						.then(|| counts.get(id.as_str()).copied().unwrap_or_default())
+				};
+				let change = TaskChange {
+					name: after.tasks.get(id).or(before.tasks.get(id))?.name.clone(),
+					before: slots(before, &counts_before),
+					after: slots(after, &counts_after),
+					missing_before: missing(&outcomes_before, id),
+					missing_after: missing(&outcomes_after, id),
+				};
+				let changed = change.before != change.after
+					|| change.missing_before != change.missing_after
+					|| before.tasks.get(id) != after.tasks.get(id);
+				changed.then(|| (id.clone(), change))
+			})
+			.collect();
+		Self {
+			moved,
+			tasks,
+			slots_moved,
+		}
+	}
+
+	/// Whether the change would leave the plan as it is.
+	#[must_use]
+	pub fn is_empty(&self) -> bool {
+		self.moved.is_empty() && self.tasks.is_empty()
+	}
+}
+
+/// Picks out tasks by project and tags.
+/// Written as words, where `project:<name>` matches a project and any other word is a tag that must be present,
+/// so `project:thesis @library` is every task in the thesis project that can be done at the library.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TaskFilter {
+	/// The project that tasks must be in, if any.
+	pub project: Option<String>,
+	/// Tags that tasks must all have.
+	pub tags: BTreeSet<String>,
+}
+
+impl TaskFilter {
+	/// Whether a task passes the filter.
+	#[must_use]
+	pub fn matches(&self, task: &CTask) -> bool {
+		self.project
+			.as_ref()
+			.is_none_or(|project| task.project.as_ref() == Some(project))
+			&& self.tags.is_subset(&task.tags)
+	}
+
+	/// Whether the filter lets every task through.
+	#[must_use]
+	pub fn is_empty(&self) -> bool {
+		self.project.is_none() && self.tags.is_empty()
+	}
+}
+
+impl FromStr for TaskFilter {
+	type Err = Infallible;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut filter = Self::default();
+		for word in s.split_whitespace() {
+			match word.strip_prefix("project:") {
+				Some(project) => filter.project = Some(project.to_string()),
+				None => {
+					filter.tags.insert(word.to_string());
+				}
+			}
+		}
+		Ok(filter)
+	}
+}
+
+/// A due date which can be missed, at a cost.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct SoftDeadline {
+	/// How long after the due date the task can still be worked on.
+	pub grace_period: Duration,
+	/// How bad each hour of lateness is, for strategies that minimize lateness.
+	pub penalty: u32,
 }
 
 impl Task for CTask {
@@ -201,7 +1045,28 @@ impl Task for CTask {
 	}
 
 	fn estimated_length(&self) -> std::time::Duration {
-		self.estimated_length - self.worked_length
+		self.remaining_length()
+	}
+
+	fn grace_period(&self) -> Duration {
+		self.soft_deadline
+			.map_or(Duration::ZERO, |deadline| deadline.grace_period)
+	}
+
+	fn min_block(&self) -> u64 {
+		self.min_block.unwrap_or(1)
+	}
+
+	fn max_block(&self) -> Option<u64> {
+		self.max_block
+	}
+
+	fn daily_cap(&self) -> Option<u64> {
+		self.daily_cap
+	}
+
+	fn min_days(&self) -> u64 {
+		self.min_days.unwrap_or(1)
 	}
 }
 
@@ -219,6 +1084,13 @@ impl TryFrom<IcalEvent> for CTask {
 	type Error = EventToTaskError;
 
 	fn try_from(event: IcalEvent) -> Result<Self, Self::Error> {
+		Self::from_event(&event, Duration::from_hours(1))
+	}
+}
+
+impl CTask {
+	/// Make a task for a calendar event that's due when the event starts, estimated to take `estimate` if it's still to come.
+	pub fn from_event(event: &IcalEvent, estimate: Duration) -> Result<Self, EventToTaskError> {
 		let properties: HashMap<_, _> = event
 			.properties
 			.iter()
@@ -233,23 +1105,17 @@ impl TryFrom<IcalEvent> for CTask {
 		let end = date_conversion(end)?;
 		let start = Utc::now().min(end);
 		let estimated_length = if end > Utc::now() {
-			Duration::from_secs_f64(1.0 * 60.0 * 60.0)
+			estimate
 		} else {
 			Duration::ZERO
 		};
-		let worked_length = Duration::from_secs_f64(0.0);
-		let priority = 0;
 		let id = properties
 			.get("UID")
 			.and_then(|e| e.value.clone())
 			.ok_or(EventToTaskError::MalformedEvent)?;
 		Ok(CTask {
-			name,
-			working_period: start..end,
-			estimated_length,
-			worked_length,
-			priority,
 			remote_id: Some(id),
+			..CTask::new(name, start..end, estimated_length)
 		})
 	}
 }
@@ -274,3 +1140,247 @@ pub fn date_conversion(event: &Property) -> Result<DateTime<Utc>, EventToTaskErr
 	let date = tz.from_local_datetime(&date).unwrap();
 	Ok(date.with_timezone(&Utc))
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{CTask, Cycle, Db, Session, TaskFilter};
+	use chrono::Utc;
+	use itertools::Itertools;
+	use std::{collections::BTreeSet, str::FromStr, time::Duration};
+
+	#[test]
+	fn filter_tasks() {
+		let task = CTask {
+			project: Some("thesis".into()),
+			tags: BTreeSet::from(["@library".into(), "reading".into()]),
+			..CTask::new(
+				"Read chapter 3".into(),
+				Utc::now()..Utc::now(),
+				Duration::ZERO,
+			)
+		};
+		let filter = |text: &str| TaskFilter::from_str(text).expect("Infallible");
+		assert!(filter("").matches(&task));
+		assert!(filter("project:thesis @library").matches(&task));
+		assert!(!filter("project:work").matches(&task));
+		assert!(!filter("@computer reading").matches(&task));
+		assert_eq!(task.contexts().collect::<Vec<_>>(), ["@library"]);
+	}
+
+	#[test]
+	fn change_cycle() {
+		let mut db = Db::default();
+		let task = CTask::new(
+			"Write report".into(),
+			Utc::now()..Utc::now() + Duration::from_hours(72),
+			Duration::from_hours(2),
+		);
+		db.insert_task("00000000000000aa".into(), task);
+		let first = |db: &Db| {
+			*db.slots
+				.iter()
+				.find(|(_, id)| id.is_some())
+				.expect("The task has slots")
+				.0
+		};
+		let before = first(&db);
+		assert!(!db.set_cycle(db.cycle()));
+
+		let cycle = Cycle {
+			timeslice_length: Duration::from_mins(50),
+			..Cycle::default()
+		};
+		assert!(db.set_cycle(cycle.clone()));
+		assert_eq!(db.cycle(), cycle);
+		assert!(db.unsatisfied_tasks().is_empty());
+		assert_eq!(db.slots.values().flatten().count(), 3);
+		assert!(first(&db) < before + cycle.timeslice_length);
+		// The slot that had already started keeps its length, and the rest are regenerated
+		assert!(db
+			.slots
+			.keys()
+			.skip(1)
+			.tuple_windows()
+			.all(|(a, b)| *a + cycle.timeslice_length <= *b));
+	}
+
+	#[test]
+	fn what_if() {
+		let task = |name: &str, priority, estimate| CTask {
+			priority,
+			..CTask::new(
+				name.into(),
+				Utc::now()..Utc::now() + Duration::from_hours(72),
+				estimate,
+			)
+		};
+		let mut db = Db::default();
+		db.insert_task(
+			"00000000000000aa".into(),
+			task("Write report", 0, Duration::from_hours(2)),
+		);
+		let before = db.clone();
+
+		let diff = db.what_if(|db| db.insert_task(db.new_id(), task("Revise", 0, Duration::ZERO)));
+		assert!(diff.moved.is_empty());
+		assert_eq!(diff.tasks.len(), 1);
+		assert_eq!(diff.slots_moved, 0);
+
+		// Something urgent that wants every slot there is pushes the report out
+		let diff = db.what_if(|db| {
+			db.insert_task(
+				"00000000000000bb".into(),
+				task("Urgent", 5, Duration::from_hours(72)),
+			);
+		});
+		assert_eq!(db, before);
+		let report = &diff.tasks["00000000000000aa"];
+		assert_eq!(report.before, Some(5));
+		assert!(report.lost() >= 4);
+		assert_eq!(
+			diff.slots_moved,
+			usize::try_from(report.lost()).expect("Small")
+		);
+		assert!(report.becomes_unsatisfied());
+		let urgent = &diff.tasks["00000000000000bb"];
+		assert_eq!(urgent.before, None);
+		assert!(!urgent.becomes_unsatisfied());
+		assert!(diff.moved.values().any(|(before, after)| before.as_deref()
+			== Some("00000000000000aa")
+			&& after.as_deref() == Some("00000000000000bb")));
+
+		assert!(db.what_if(|_| {}).is_empty());
+	}
+
+	#[test]
+	fn pins_survive_rescheduling() {
+		let mut db = Db::default();
+		let task = CTask::new(
+			"Write report".into(),
+			Utc::now()..Utc::now() + Duration::from_hours(72),
+			Duration::from_hours(1),
+		);
+		let id = "00000000000000aa".to_string();
+		db.insert_task(id.clone(), task);
+		let times = db.slots.keys().copied().collect_vec();
+		assert!(db.pin_slot(times[0], None));
+		assert!(db.pin_slot(times[10], Some(id.clone())));
+		let pinned = |db: &Db| {
+			db.pinned
+				.iter()
+				.map(|time| db.slots[time].clone())
+				.collect_vec()
+		};
+		assert_eq!(pinned(&db), [None, Some(id.clone())]);
+
+		db.reschedule();
+		assert_eq!(pinned(&db), [None, Some(id.clone())]);
+		assert_eq!(db.slots.values().flatten().count(), 3);
+		assert!(db.set_cycle(Cycle {
+			timeslice_length: Duration::from_mins(50),
+			..Cycle::default()
+		}));
+		assert_eq!(pinned(&db), [None, Some(id.clone())]);
+
+		assert!(db.unpin_slot(*db.pinned.first().expect("A pinned slot")));
+		db.remove_task(&id);
+		assert!(db.pinned.is_empty());
+	}
+
+	#[test]
+	fn stable_plan() {
+		let task = |name: &str| {
+			CTask::new(
+				name.into(),
+				Utc::now()..Utc::now() + Duration::from_hours(72),
+				Duration::from_hours(1),
+			)
+		};
+		let (report, revise) = ("00000000000000aa", "00000000000000bb");
+		let mut db = Db::default();
+		db.insert_task(report.into(), task("Write report"));
+		db.insert_task(revise.into(), task("Revise"));
+		// Swap the first slot of each task, which scheduling from scratch would never do
+		let first = |db: &Db, id: &str| {
+			*db.slots
+				.iter()
+				.find(|(_, slot)| slot.as_deref() == Some(id))
+				.expect("The task has slots")
+				.0
+		};
+		let (a, b) = (first(&db, report), first(&db, revise));
+		db.schedule.slots.insert(a, Some(revise.into()));
+		db.schedule.slots.insert(b, Some(report.into()));
+
+		let mut edited = CTask::clone(&db.tasks[report]);
+		edited.name = "Write the report".into();
+		edited.estimated_length = Duration::from_mins(50);
+		let diff = db.what_if(|db| db.replace_task(report, edited));
+		assert_eq!(diff.slots_moved, 1);
+		assert_eq!(diff.tasks[report].after, Some(2));
+
+		let mut from_scratch = db.clone();
+		assert!(from_scratch.reschedule() > 0);
+		db.stable_plan = true;
+		assert_eq!(db.reschedule(), 0);
+		assert!(first(&db, revise) < first(&db, report));
+	}
+
+	#[test]
+	fn missed_slots() {
+		let now = Utc::now();
+		let id = "00000000000000aa";
+		let mut db = Db::default();
+		db.insert_task(
+			id.into(),
+			CTask::new(
+				"Essay".into(),
+				now - Duration::from_hours(4)..now + Duration::from_hours(72),
+				Duration::from_hours(2),
+			),
+		);
+		// Three slots this morning, and only the middle one was worked
+		let starts = [3, 2, 1].map(|hours| now - Duration::from_hours(hours));
+		for start in starts {
+			db.schedule.slots.insert(start, Some(id.into()));
+		}
+		db.history.push(Session {
+			task: id.into(),
+			period: starts[1] + Duration::from_mins(5)..starts[1] + Duration::from_mins(20),
+		});
+		db.housekeeping();
+		assert_eq!(db.missed.len(), 2);
+		assert_eq!(db.missed_totals()[id], (2, Duration::from_mins(50)));
+		assert!(db.slots.keys().all(|time| *time > starts[2]));
+
+		// Until the user decides, the missed time isn't planned again, however often the plan is worked out
+		let planned = |db: &Db| {
+			db.slots
+				.values()
+				.flatten()
+				.filter(|slot| *slot == id)
+				.count()
+		};
+		assert_eq!(planned(&db), 3);
+		db.housekeeping();
+		db.reschedule();
+		assert_eq!(planned(&db), 3);
+		assert_eq!(db.missed.len(), 2);
+
+		// Done for half an hour of it, and the rest goes back in the plan
+		assert_eq!(
+			db.resolve_missed(id, Duration::from_mins(30)),
+			Duration::from_mins(30)
+		);
+		assert!(db.missed.is_empty());
+		assert_eq!(db.tasks[id].worked_length, Duration::from_mins(30));
+		assert_eq!(planned(&db), 4);
+		assert_eq!(db.history.len(), 3);
+		assert_eq!(
+			db.history[0].period,
+			starts[0]..starts[0] + Duration::from_mins(25)
+		);
+		db.housekeeping();
+		assert!(db.missed.is_empty());
+	}
+}
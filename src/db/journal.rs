@@ -0,0 +1,371 @@
+//! Undo and redo, by keeping what each operation changed in the database.
+//! Only the tasks, slots and other parts that an operation touched are kept,
+//! so undoing it after merging in another process's changes leaves theirs alone.
+
+use super::{CTask, Db, Session, ShuffleRecord};
+use crate::pomodoro::Pomodoro;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+	ops::Range,
+	sync::Arc,
+};
+
+/// How many operations can be undone.
+pub const JOURNAL_LENGTH: usize = 20;
+
+/// Recent operations, with what each of them changed.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Journal {
+	/// Operations that can be undone, oldest first.
+	pub undo: VecDeque<Entry>,
+	/// Operations that were undone and can be redone, most recently undone last.
+	pub redo: Vec<Entry>,
+	/// The operation being done now, with the database from before it, until it's finished and can be compared against.
+	#[serde(skip)]
+	pending: Option<(String, DateTime<Utc>, Box<Snapshot>)>,
+}
+
+/// One operation in the journal.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Entry {
+	/// What the operation did, for showing to the user.
+	pub operation: String,
+	/// When the operation was done.
+	pub time: DateTime<Utc>,
+	/// What the operation changed, from the other side of it: before it for undoing, and after it for redoing.
+	pub change: Change,
+}
+
+/// The parts of the database that an operation changed, as they were on one side of it.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Change {
+	/// Tasks that changed, or `None` where there wasn't a task.
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub tasks: BTreeMap<String, Option<Arc<CTask>>>,
+	/// Slots that changed, or `None` where there wasn't a slot.
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub slots: BTreeMap<DateTime<Utc>, Option<Option<String>>>,
+	/// Slots that were pinned or unpinned, and whether they were pinned.
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub pinned: BTreeMap<DateTime<Utc>, bool>,
+	/// The pomodoro states, if they changed.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub pomodoro_states: Option<Vec<(Range<DateTime<Utc>>, Pomodoro)>>,
+	/// The last shuffle, if it changed.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub last_shuffle: Option<Option<ShuffleRecord>>,
+	/// Sessions of history that were there on this side but not on the other.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub sessions: Vec<Session>,
+	/// Sessions of history that weren't there on this side, but were on the other.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub extra_sessions: Vec<Session>,
+	/// The missed slots, if they changed.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub missed: Option<Vec<Session>>,
+}
+
+/// The parts of the database that operations change, in full.
+#[derive(Clone, Debug, PartialEq)]
+struct Snapshot {
+	tasks: HashMap<String, Arc<CTask>>,
+	slots: BTreeMap<DateTime<Utc>, Option<String>>,
+	pinned: BTreeSet<DateTime<Utc>>,
+	pomodoro_states: Vec<(Range<DateTime<Utc>>, Pomodoro)>,
+	last_shuffle: Option<ShuffleRecord>,
+	history: Vec<Session>,
+	missed: Vec<Session>,
+}
+
+/// The keys whose values differ between two maps, with the values from `before`.
+fn changed<'a, K: Ord + Clone + 'a, V: PartialEq + Clone + 'a>(
+	before: impl Fn(&K) -> Option<&'a V>,
+	after: impl Fn(&K) -> Option<&'a V>,
+	keys: impl Iterator<Item = &'a K>,
+) -> BTreeMap<K, Option<V>> {
+	keys.filter(|key| before(key) != after(key))
+		.map(|key| (key.clone(), before(key).cloned()))
+		.collect()
+}
+
+/// The sessions in `sessions` that aren't in `others`, counting repeats.
+fn missing_from(sessions: &[Session], others: &[Session]) -> Vec<Session> {
+	let mut left: HashMap<(&str, DateTime<Utc>, DateTime<Utc>), usize> = HashMap::new();
+	for session in others {
+		*left
+			.entry((&session.task, session.period.start, session.period.end))
+			.or_default() += 1;
+	}
+	sessions
+		.iter()
+		.filter(|session| {
+			match left.get_mut(&(
+				session.task.as_str(),
+				session.period.start,
+				session.period.end,
+			)) {
+				Some(count) if *count > 0 => {
+					*count -= 1;
+					false
+				}
+				_ => true,
+			}
+		})
+		.cloned()
+		.collect()
+}
+
+fn changed_value<T: PartialEq + Clone>(before: &T, after: &T) -> Option<T> {
+	(before != after).then(|| before.clone())
+}
+
+impl Db {
+	fn snapshot(&self) -> Snapshot {
+		Snapshot {
+			tasks: self.schedule.tasks.clone(),
+			slots: self.schedule.slots.clone(),
+			pinned: self.schedule.pinned.clone(),
+			pomodoro_states: self.pomodoro_states.clone(),
+			last_shuffle: self.last_shuffle.clone(),
+			history: self.history.clone(),
+			missed: self.missed.clone(),
+		}
+	}
+
+	/// What changed between a snapshot and the database now, as it was in the snapshot.
+	fn changes_since(&self, before: &Snapshot) -> Change {
+		Change {
+			tasks: changed(
+				|id| before.tasks.get(id),
+				|id| self.schedule.tasks.get(id),
+				before.tasks.keys().chain(self.schedule.tasks.keys()),
+			),
+			slots: changed(
+				|time| before.slots.get(time),
+				|time| self.schedule.slots.get(time),
+				before.slots.keys().chain(self.schedule.slots.keys()),
+			),
+			pinned: before
+				.pinned
+				.symmetric_difference(&self.schedule.pinned)
+				.map(|time| (*time, before.pinned.contains(time)))
+				.collect(),
+			pomodoro_states: changed_value(&before.pomodoro_states, &self.pomodoro_states),
+			last_shuffle: changed_value(&before.last_shuffle, &self.last_shuffle),
+			sessions: missing_from(&before.history, &self.history),
+			extra_sessions: missing_from(&self.history, &before.history),
+			missed: changed_value(&before.missed, &self.missed),
+		}
+	}
+
+	/// The database now, for the same parts that a change has, so that applying the change can be reversed.
+	fn current(&self, change: &Change) -> Change {
+		Change {
+			tasks: change
+				.tasks
+				.keys()
+				.map(|id| (id.clone(), self.schedule.tasks.get(id).cloned()))
+				.collect(),
+			slots: change
+				.slots
+				.keys()
+				.map(|time| (*time, self.schedule.slots.get(time).cloned()))
+				.collect(),
+			pinned: change
+				.pinned
+				.keys()
+				.map(|time| (*time, self.schedule.pinned.contains(time)))
+				.collect(),
+			pomodoro_states: change
+				.pomodoro_states
+				.as_ref()
+				.map(|_| self.pomodoro_states.clone()),
+			last_shuffle: change
+				.last_shuffle
+				.as_ref()
+				.map(|_| self.last_shuffle.clone()),
+			sessions: change.extra_sessions.clone(),
+			extra_sessions: change.sessions.clone(),
+			missed: change.missed.as_ref().map(|_| self.missed.clone()),
+		}
+	}
+
+	fn apply(&mut self, change: Change) {
+		for (id, task) in change.tasks {
+			match task {
+				Some(task) => self.schedule.tasks.insert(id, task),
+				None => self.schedule.tasks.remove(&id),
+			};
+		}
+		for (time, slot) in change.slots {
+			match slot {
+				Some(slot) => self.schedule.slots.insert(time, slot),
+				None => self.schedule.slots.remove(&time),
+			};
+		}
+		for (time, pinned) in change.pinned {
+			if pinned {
+				self.schedule.pinned.insert(time);
+			} else {
+				self.schedule.pinned.remove(&time);
+			}
+		}
+		if let Some(pomodoro_states) = change.pomodoro_states {
+			self.pomodoro_states = pomodoro_states;
+		}
+		if let Some(last_shuffle) = change.last_shuffle {
+			self.last_shuffle = last_shuffle;
+		}
+		self.history = missing_from(&self.history, &change.extra_sessions);
+		self.history.extend(change.sessions);
+		self.history.sort_by_key(|session| session.period.start);
+		if let Some(missed) = change.missed {
+			self.missed = missed;
+		}
+	}
+
+	/// Finish journaling the operation that was last recorded, now that what it changed can be worked out.
+	/// This happens by itself before recording, undoing, redoing and saving.
+	pub fn finish_operation(&mut self) {
+		let Some((operation, time, before)) = self.journal.pending.take() else {
+			return;
+		};
+		let change = self.changes_since(&before);
+		self.journal.undo.push_back(Entry {
+			operation,
+			time,
+			change,
+		});
+		while self.journal.undo.len() > JOURNAL_LENGTH {
+			self.journal.undo.pop_front();
+		}
+	}
+
+	/// Remember the database as it is now, before an operation changes it, so the operation can be undone.
+	pub fn record(&mut self, operation: impl Into<String>) {
+		self.finish_operation();
+		self.journal.pending = Some((operation.into(), Utc::now(), Box::new(self.snapshot())));
+		self.journal.redo.clear();
+	}
+
+	/// The operation that's being done now, if it hasn't been finished yet.
+	#[must_use]
+	pub fn pending_operation(&self) -> Option<(&str, DateTime<Utc>)> {
+		self.journal
+			.pending
+			.as_ref()
+			.map(|(operation, time, _)| (operation.as_str(), *time))
+	}
+
+	/// Undo the most recent operation, returning what it was.
+	pub fn undo(&mut self) -> Option<String> {
+		self.finish_operation();
+		let entry = self.journal.undo.pop_back()?;
+		self.journal.redo.push(Entry {
+			change: self.current(&entry.change),
+			..entry.clone()
+		});
+		self.apply(entry.change);
+		Some(entry.operation)
+	}
+
+	/// Redo the most recently undone operation, returning what it was.
+	pub fn redo(&mut self) -> Option<String> {
+		self.finish_operation();
+		let entry = self.journal.redo.pop()?;
+		self.journal.undo.push_back(Entry {
+			change: self.current(&entry.change),
+			..entry.clone()
+		});
+		self.apply(entry.change);
+		Some(entry.operation)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::JOURNAL_LENGTH;
+	use crate::db::{migrations, Session};
+	use std::{sync::Arc, time::Duration};
+
+	#[test]
+	fn undo_redo() {
+		let text = include_str!("../../tests/corpus/v1.ron");
+		let original = migrations::parse(text).expect("Valid database").db;
+		let mut db = original.clone();
+
+		db.record("Remove Essay");
+		db.remove_task("3f9a2c71b0d4e856");
+		let removed = db.clone();
+		db.record("Work on Essay");
+		let start = *db.slots.keys().next().expect("A slot");
+		db.history.push(Session {
+			task: "3f9b00e4c1a27d90".into(),
+			period: start..start + Duration::from_mins(25),
+		});
+		let worked = db.clone();
+
+		assert_eq!(db.undo().as_deref(), Some("Work on Essay"));
+		assert_eq!(db.schedule, removed.schedule);
+		assert!(db.history.is_empty());
+		assert_eq!(db.undo().as_deref(), Some("Remove Essay"));
+		assert_eq!(db.schedule, original.schedule);
+		assert_eq!(db.undo(), None);
+
+		assert_eq!(db.redo().as_deref(), Some("Remove Essay"));
+		assert_eq!(db.redo().as_deref(), Some("Work on Essay"));
+		assert_eq!(db.schedule, worked.schedule);
+		assert_eq!(db.history, worked.history);
+		assert_eq!(db.redo(), None);
+
+		db.record("Pin free time");
+		assert!(db.pin_slot(start, None));
+		assert_eq!(db.undo().as_deref(), Some("Pin free time"));
+		assert!(!db.is_pinned(start));
+		assert_eq!(db.redo().as_deref(), Some("Pin free time"));
+		assert!(db.is_pinned(start));
+
+		// A new operation can't be redone past, and old operations fall off the end
+		db.undo();
+		db.record("Reschedule");
+		assert!(db.journal.redo.is_empty());
+		for _ in 0..JOURNAL_LENGTH {
+			db.record("Shuffle");
+		}
+		assert_eq!(db.journal.undo.len(), JOURNAL_LENGTH);
+	}
+
+	#[test]
+	fn undo_after_merge() {
+		let text = include_str!("../../tests/corpus/v1.ron");
+		let base = migrations::parse(text).expect("Valid database").db;
+		let (essay, other) = ("3f9a2c71b0d4e856", "3f9b00e4c1a27d90");
+
+		let mut ours = base.clone();
+		ours.record("Remove the other task");
+		ours.remove_task(other);
+		ours.finish_operation();
+
+		// Meanwhile, another process renames the essay and works on it
+		let mut theirs = base.clone();
+		Arc::make_mut(theirs.schedule.tasks.get_mut(essay).expect("Essay")).name = "Thesis".into();
+		let start = *theirs.slots.keys().next().expect("A slot");
+		let session = Session {
+			task: essay.into(),
+			period: start..start + Duration::from_mins(25),
+		};
+		theirs.history.push(session.clone());
+
+		let mut merged = ours.merge(&base, &theirs);
+		assert_eq!(merged.undo().as_deref(), Some("Remove the other task"));
+		assert_eq!(merged.tasks[other], base.tasks[other]);
+		assert_eq!(merged.slots, base.slots);
+		assert_eq!(merged.tasks[essay].name, "Thesis");
+		assert_eq!(merged.history, [session]);
+		assert_eq!(merged.redo().as_deref(), Some("Remove the other task"));
+		assert!(!merged.tasks.contains_key(other));
+		assert_eq!(merged.tasks[essay].name, "Thesis");
+	}
+}
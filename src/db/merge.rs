@@ -0,0 +1,249 @@
+//! Three-way merging, for when another process saved the database while this one was using it.
+
+use super::{CTask, Db};
+use std::{
+	collections::{BTreeMap, BTreeSet, HashMap},
+	sync::Arc,
+	time::Duration,
+};
+
+/// Pick whichever side changed a value. If both did, `conflict` decides.
+fn pick<T: PartialEq + Clone>(base: &T, ours: &T, theirs: &T, conflict: impl FnOnce() -> T) -> T {
+	if ours == base {
+		theirs.clone()
+	} else if theirs == base || theirs == ours {
+		ours.clone()
+	} else {
+		conflict()
+	}
+}
+
+fn pick_field<T: PartialEq + Clone>(base: &T, ours: &T, theirs: &T) -> T {
+	pick(base, ours, theirs, || ours.clone())
+}
+
+/// Merge each key of a map on its own, with `conflict` deciding between two changed values, given the base value.
+fn pick_keys<K: Clone, V: PartialEq + Clone>(
+	keys: impl Iterator<Item = K>,
+	get: impl Fn(&K) -> [Option<V>; 3],
+	conflict: impl Fn(Option<&V>, Option<&V>, Option<&V>) -> Option<V>,
+) -> Vec<(K, V)> {
+	keys.filter_map(|key| {
+		let [base, ours, theirs] = get(&key);
+		pick(&base, &ours, &theirs, || {
+			conflict(base.as_ref(), ours.as_ref(), theirs.as_ref())
+		})
+		.map(|value| (key, value))
+	})
+	.collect()
+}
+
+/// Which version of a task wins when two processes both changed it.
+/// A task that one side removed and the other edited is kept, and when both edited it, ours wins but keeps the time both sides worked.
+fn task_conflict(
+	base: Option<&Arc<CTask>>,
+	ours: Option<&Arc<CTask>>,
+	theirs: Option<&Arc<CTask>>,
+) -> Option<Arc<CTask>> {
+	match (ours, theirs) {
+		(Some(ours), Some(theirs)) => {
+			let base = base.map_or(Duration::ZERO, |base| base.worked_length);
+			Some(Arc::new(CTask {
+				// The same as base + (ours - base) + (theirs - base), without going below zero in between
+				worked_length: (ours.worked_length + theirs.worked_length).saturating_sub(base),
+				..CTask::clone(ours)
+			}))
+		}
+		(None, edited) | (edited, None) => edited.cloned(),
+	}
+}
+
+impl Db {
+	/// Combine the changes that we and another process made to the same database.
+	/// `base` is the database as we loaded it, `self` is how we've changed it, and `theirs` is what the other process saved.
+	/// Tasks, slots and pins are merged one by one, and when both sides changed the same thing, ours wins.
+	#[must_use]
+	pub fn merge(&self, base: &Db, theirs: &Db) -> Db {
+		let tasks: HashMap<_, _> = pick_keys(
+			self.tasks
+				.keys()
+				.chain(theirs.tasks.keys())
+				.chain(base.tasks.keys())
+				.cloned()
+				.collect::<BTreeSet<_>>()
+				.into_iter(),
+			|id| [base, self, theirs].map(|db| db.tasks.get(id).cloned()),
+			task_conflict,
+		)
+		.into_iter()
+		.collect();
+		let slots: BTreeMap<_, _> = pick_keys(
+			self.slots
+				.keys()
+				.chain(theirs.slots.keys())
+				.chain(base.slots.keys())
+				.copied()
+				.collect::<BTreeSet<_>>()
+				.into_iter(),
+			|time| [base, self, theirs].map(|db| db.slots.get(time).cloned()),
+			|_, ours, _| ours.cloned(),
+		)
+		.into_iter()
+		.map(|(time, id)| (time, id.filter(|id| tasks.contains_key(id))))
+		.collect();
+
+		let pinned: BTreeSet<_> = pick_keys(
+			self.pinned
+				.iter()
+				.chain(&theirs.pinned)
+				.chain(&base.pinned)
+				.copied()
+				.collect::<BTreeSet<_>>()
+				.into_iter(),
+			|time| [base, self, theirs].map(|db| db.pinned.contains(time).then_some(())),
+			|_, ours, _| ours.copied(),
+		)
+		.into_iter()
+		.map(|(time, ())| time)
+		.filter(|time| slots.contains_key(time))
+		.collect();
+
+		let mut history = self.history.clone();
+		history.extend(
+			theirs
+				.history
+				.iter()
+				.filter(|session| !self.history.contains(session))
+				.cloned(),
+		);
+		history.sort_by_key(|session| session.period.start);
+
+		// A missed slot that either side dealt with stays dealt with, and one that the timer on either side worked wasn't missed
+		let mut missed = vec![];
+		for session in self.missed.iter().chain(&theirs.missed) {
+			let kept = [self, theirs].iter().all(|db| db.missed.contains(session))
+				|| !base.missed.contains(session);
+			let worked = history.iter().any(|worked| worked.overlaps(session));
+			if kept && !worked && tasks.contains_key(&session.task) && !missed.contains(session) {
+				missed.push(session.clone());
+			}
+		}
+		missed.sort_by_key(|session| session.period.start);
+
+		let mut merged = Db {
+			active_period: pick_field(
+				&base.active_period,
+				&self.active_period,
+				&theirs.active_period,
+			),
+			break_interval: pick_field(
+				&base.break_interval,
+				&self.break_interval,
+				&theirs.break_interval,
+			),
+			short_break: pick_field(&base.short_break, &self.short_break, &theirs.short_break),
+			long_break: pick_field(&base.long_break, &self.long_break, &theirs.long_break),
+			pomodoro_states: pick_field(
+				&base.pomodoro_states,
+				&self.pomodoro_states,
+				&theirs.pomodoro_states,
+			),
+			last_shuffle: pick_field(&base.last_shuffle, &self.last_shuffle, &theirs.last_shuffle),
+			energy_curve: pick_field(&base.energy_curve, &self.energy_curve, &theirs.energy_curve),
+			history,
+			missed,
+			journal: pick_field(&base.journal, &self.journal, &theirs.journal),
+			schedule: self.schedule.clone(),
+			stable_plan: self.stable_plan,
+		};
+		merged.schedule.tasks = tasks;
+		merged.schedule.slots = slots;
+		merged.schedule.pinned = pinned;
+		merged.schedule.timeslice_length = pick_field(
+			&base.schedule.timeslice_length,
+			&self.schedule.timeslice_length,
+			&theirs.schedule.timeslice_length,
+		);
+		merged
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::db::{migrations, Session};
+	use std::{sync::Arc, time::Duration};
+
+	#[test]
+	fn merge_tasks() {
+		let text = include_str!("../../tests/corpus/v1.ron");
+		let base = migrations::parse(text).expect("Valid database").db;
+		let essay = "3f9a2c71b0d4e856";
+		let (slot, _) = base
+			.slots
+			.iter()
+			.find(|(_, id)| id.as_deref() == Some(essay))
+			.expect("Essay has a slot");
+
+		// We add a task, and work on the essay for ten minutes
+		let mut ours = base.clone();
+		let mut task = ours.tasks[essay].clone();
+		Arc::make_mut(&mut task).name = "Outline".into();
+		ours.schedule.tasks.insert("00000000000000aa".into(), task);
+		Arc::make_mut(ours.schedule.tasks.get_mut(essay).expect("Essay")).worked_length =
+			Duration::from_mins(10);
+
+		// Meanwhile, they work on the essay for twenty minutes and remove the other task
+		let mut theirs = base.clone();
+		let essay_task = Arc::make_mut(theirs.schedule.tasks.get_mut(essay).expect("Essay"));
+		essay_task.worked_length = Duration::from_mins(20);
+		let session = Session {
+			task: essay.into(),
+			period: *slot..*slot + Duration::from_mins(20),
+		};
+		theirs.history.push(session.clone());
+		theirs.schedule.tasks.remove("3f9b00e4c1a27d90");
+
+		let merged = ours.merge(&base, &theirs);
+		assert_eq!(merged.tasks.len(), 2);
+		assert_eq!(merged.tasks["00000000000000aa"].name, "Outline");
+		assert_eq!(merged.tasks[essay].worked_length, Duration::from_mins(30));
+		assert!(!merged.tasks.contains_key("3f9b00e4c1a27d90"));
+		assert!(merged
+			.slots
+			.values()
+			.flatten()
+			.all(|id| merged.tasks.contains_key(id)));
+		assert_eq!(merged.history, [session]);
+	}
+
+	#[test]
+	fn worked_slots_arent_missed() {
+		let text = include_str!("../../tests/corpus/v1.ron");
+		let base = migrations::parse(text).expect("Valid database").db;
+		let essay = "3f9a2c71b0d4e856";
+		let (slot, _) = base
+			.slots
+			.iter()
+			.find(|(_, id)| id.as_deref() == Some(essay))
+			.expect("Essay has a slot");
+		let length = base.schedule.timeslice_length;
+
+		// Our timer worked the slot, while their housekeeping found nothing recorded for it
+		let mut ours = base.clone();
+		let worked = Session {
+			task: essay.into(),
+			period: *slot + Duration::from_mins(2)..*slot + length,
+		};
+		ours.history.push(worked.clone());
+		let mut theirs = base.clone();
+		theirs.missed.push(Session {
+			task: essay.into(),
+			period: *slot..*slot + length,
+		});
+
+		for merged in [ours.merge(&base, &theirs), theirs.merge(&base, &ours)] {
+			assert_eq!(merged.history.last(), Some(&worked));
+			assert!(merged.missed.is_empty());
+		}
+	}
+}
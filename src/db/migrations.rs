@@ -0,0 +1,174 @@
+//! The layout of the database on disk, and upgrades from older layouts.
+//!
+//! Adding a field with `#[serde(default)]` doesn't need a new version.
+//! Anything else that changes what's stored, like renaming a field or changing what a value means,
+//! bumps [`CURRENT_VERSION`] and adds a step to [`MIGRATIONS`], plus an old file to `tests/corpus`.
+
+use super::{Db, ID_LENGTH};
+use chrono::Utc;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::{
+	io,
+	path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// The layout that this version of Pomeranian writes.
+pub const CURRENT_VERSION: u32 = 1;
+
+/// Upgrades from each older layout, in order: `MIGRATIONS[n]` turns version `n` into version `n + 1`.
+const MIGRATIONS: [fn(&mut Db); CURRENT_VERSION as usize] = [generate_ids];
+
+/// The database as it's stored on disk, marked with the version of its layout.
+/// Version 0 databases were stored as a bare [`Db`].
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Envelope {
+	/// The version of the layout that `db` was written in.
+	pub version: u32,
+	/// The database itself.
+	pub db: Db,
+}
+
+impl Default for Envelope {
+	fn default() -> Self {
+		Self::new(Db::default())
+	}
+}
+
+impl Envelope {
+	/// Wrap a database for storing in the current layout.
+	#[must_use]
+	pub fn new(db: Db) -> Self {
+		Self {
+			version: CURRENT_VERSION,
+			db,
+		}
+	}
+}
+
+#[derive(Error, Debug)]
+pub enum LoadError {
+	#[error("Error reading database")]
+	Io(#[from] io::Error),
+	#[error(
+		"Database doesn't match any known layout ({envelope}; or, as a version 0 database, {bare})"
+	)]
+	Parse {
+		envelope: ron::Error,
+		bare: ron::Error,
+	},
+	#[error("Database is version {0}, but this version of Pomeranian only understands up to version {CURRENT_VERSION}")]
+	TooNew(u32),
+}
+
+/// Parse a database written in any known layout, and upgrade it to the current one.
+pub fn parse(text: &str) -> Result<Envelope, LoadError> {
+	let (version, mut db) = match ron::from_str::<Envelope>(text) {
+		Ok(Envelope { version, db }) => (version, db),
+		Err(envelope) => match ron::from_str::<Db>(text) {
+			Ok(db) => (0, db),
+			Err(bare) => return Err(LoadError::Parse { envelope, bare }),
+		},
+	};
+	upgrade(&mut db, version)?;
+	Ok(Envelope::new(db))
+}
+
+/// Upgrade a database from the layout it was stored in to the current one.
+pub fn upgrade(db: &mut Db, version: u32) -> Result<(), LoadError> {
+	let Some(migrations) = MIGRATIONS.get(version as usize..) else {
+		return Err(LoadError::TooNew(version));
+	};
+	for migrate in migrations {
+		migrate(db);
+	}
+	Ok(())
+}
+
+/// Load the database at a path, or an empty one if there isn't a file there yet.
+pub fn load(path: &Path) -> Result<Envelope, LoadError> {
+	match std::fs::read_to_string(path) {
+		Ok(text) => parse(&text),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Envelope::default()),
+		Err(e) => Err(e.into()),
+	}
+}
+
+/// Move a database that couldn't be loaded out of the way, so that it can't be overwritten.
+/// Returns where it was moved to.
+pub fn set_aside(path: &Path) -> io::Result<PathBuf> {
+	let mut name = path.file_name().unwrap_or_default().to_os_string();
+	name.push(format!(".broken-{}", Utc::now().format("%Y%m%d%H%M%S")));
+	let destination = path.with_file_name(name);
+	std::fs::rename(path, &destination)?;
+	Ok(destination)
+}
+
+/// Version 0 to 1: tasks were keyed by their names (or their remote IDs, if imported),
+/// so give each of them a generated ID and point their slots at it.
+fn generate_ids(db: &mut Db) {
+	let old_ids = db
+		.schedule
+		.tasks
+		.keys()
+		.filter(|id| !(id.len() == ID_LENGTH && id.bytes().all(|b| b.is_ascii_hexdigit())))
+		.cloned()
+		.collect_vec();
+	for old_id in old_ids {
+		let new_id = db.new_id();
+		let task = db
+			.schedule
+			.tasks
+			.remove(&old_id)
+			.expect("The ID was just listed");
+		db.schedule.tasks.insert(new_id.clone(), task);
+		for slot in db.schedule.slots.values_mut() {
+			if slot.as_ref() == Some(&old_id) {
+				*slot = Some(new_id.clone());
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{parse, LoadError, CURRENT_VERSION};
+
+	/// Every file in the corpus is a database from an older version of Pomeranian, which should still load.
+	#[test]
+	fn corpus() {
+		let corpus = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/corpus");
+		let mut files = 0;
+		for entry in std::fs::read_dir(corpus).expect("Corpus exists") {
+			let path = entry.expect("Readable corpus").path();
+			let text = std::fs::read_to_string(&path).expect("Readable file");
+			let envelope =
+				parse(&text).unwrap_or_else(|e| panic!("{} should load: {e}", path.display()));
+			assert_eq!(envelope.version, CURRENT_VERSION);
+			let db = envelope.db;
+			assert!(!db.tasks.is_empty(), "{}", path.display());
+			for (id, task) in &db.tasks {
+				assert_eq!(db.resolve_id(db.short_id(id)).ok().as_ref(), Some(id));
+				assert!(!task.name.is_empty());
+			}
+			for id in db.slots.values().flatten() {
+				assert!(db.tasks.contains_key(id), "{}", path.display());
+			}
+			assert!(db.slots.values().any(Option::is_some));
+			files += 1;
+		}
+		assert!(files > 0);
+	}
+
+	#[test]
+	fn rejects_bad_files() {
+		assert!(matches!(parse("(nonsense)"), Err(LoadError::Parse { .. })));
+		let future = ron::to_string(&super::Envelope {
+			version: CURRENT_VERSION + 1,
+			..Default::default()
+		})
+		.expect("Serializable");
+		assert!(matches!(parse(&future), Err(LoadError::TooNew(_))));
+	}
+}
@@ -0,0 +1,143 @@
+//! Reading and writing lengths of time the way people do, like "90m", "1h30m", "2.5h" or "3 pomodoros".
+
+use crate::db::CTask;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum LengthError {
+	#[error("Couldn't understand {0:?} as a length of time")]
+	Unrecognized(String),
+	#[error("Unknown unit {0:?}, expected h, m or pomodoros")]
+	UnknownUnit(String),
+	#[error("{0:?} is too long a length of time")]
+	TooLong(String),
+}
+
+/// Work out the length that some text means, like "90m", "1h 30m", "2.5 hours" or "3 pomodoros".
+/// Pomodoros are `timeslice_length` long, and a number on its own is a number of hours.
+pub fn parse(text: &str, timeslice_length: Duration) -> Result<Duration, LengthError> {
+	let unrecognized = || LengthError::Unrecognized(text.trim().to_string());
+	let too_long = || LengthError::TooLong(text.trim().to_string());
+	// An amount of some unit, which is never negative by the time it gets here
+	let times = |unit: Duration, amount: f64| {
+		Duration::try_from_secs_f64(unit.as_secs_f64() * amount).map_err(|_| too_long())
+	};
+	let text = text.trim().to_lowercase();
+	if text.is_empty() {
+		return Err(unrecognized());
+	}
+	if let Ok(hours) = text.parse::<f64>() {
+		if hours < 0.0 || hours.is_nan() {
+			return Err(unrecognized());
+		}
+		return times(Duration::from_hours(1), hours);
+	}
+
+	let mut total = Duration::ZERO;
+	let mut rest = text.as_str();
+	while !rest.is_empty() {
+		let number_end = rest
+			.find(|c: char| !(c.is_ascii_digit() || c == '.'))
+			.unwrap_or(rest.len());
+		let amount: f64 = rest[..number_end].parse().map_err(|_| unrecognized())?;
+		rest = rest[number_end..].trim_start();
+		let unit_end = rest
+			.find(|c: char| !c.is_alphabetic())
+			.unwrap_or(rest.len());
+		let unit = match &rest[..unit_end] {
+			"" => return Err(unrecognized()),
+			"h" | "hr" | "hrs" | "hour" | "hours" => Duration::from_hours(1),
+			"m" | "min" | "mins" | "minute" | "minutes" => Duration::from_mins(1),
+			"p" | "pom" | "poms" | "pomodoro" | "pomodoros" | "slot" | "slots" => timeslice_length,
+			unit => return Err(LengthError::UnknownUnit(unit.to_string())),
+		};
+		total = total
+			.checked_add(times(unit, amount)?)
+			.ok_or_else(too_long)?;
+		rest = rest[unit_end..].trim_start();
+	}
+	Ok(total)
+}
+
+/// Write a length to the nearest minute, like "1h30m", "45m" or "2h".
+#[must_use]
+pub fn format(length: Duration) -> String {
+	let minutes = (length.as_secs() + 30) / 60;
+	match (minutes / 60, minutes % 60) {
+		(0, minutes) => format!("{minutes}m"),
+		(hours, 0) => format!("{hours}h"),
+		(hours, minutes) => format!("{hours}h{minutes}m"),
+	}
+}
+
+/// How many slots it takes to fit a length of time.
+#[must_use]
+pub fn slots(length: Duration, timeslice_length: Duration) -> u64 {
+	length.as_secs().div_ceil(timeslice_length.as_secs().max(1))
+}
+
+/// How much work is left on a task, and how many slots that takes, like "1h30m (4 slots) left".
+#[must_use]
+pub fn remaining(task: &CTask, timeslice_length: Duration) -> String {
+	let left = task.remaining_length();
+	match slots(left, timeslice_length) {
+		0 => "done".into(),
+		1 => format!("{} (1 slot) left", format(left)),
+		slots => format!("{} ({slots} slots) left", format(left)),
+	}
+}
+
+/// How far along a task is, like "30m of 2h done, 1h30m (4 slots) left".
+#[must_use]
+pub fn progress(task: &CTask, timeslice_length: Duration) -> String {
+	format!(
+		"{} of {} done, {}",
+		format(task.worked_length),
+		format(task.estimated_length),
+		remaining(task, timeslice_length)
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{format, parse, slots, LengthError};
+	use std::time::Duration;
+
+	#[test]
+	fn parse_lengths() {
+		let slot = Duration::from_mins(25);
+		let parsed = |text| parse(text, slot).unwrap_or_else(|e| panic!("{e}"));
+		assert_eq!(parsed("90m"), Duration::from_mins(90));
+		assert_eq!(parsed("1h30m"), Duration::from_mins(90));
+		assert_eq!(parsed("1h 30 min"), Duration::from_mins(90));
+		assert_eq!(parsed("2.5h"), Duration::from_mins(150));
+		assert_eq!(parsed("2 Hours"), Duration::from_hours(2));
+		assert_eq!(parsed("1.5"), Duration::from_mins(90));
+		assert_eq!(parsed("3 pomodoros"), Duration::from_mins(75));
+		assert_eq!(parsed("1 pomodoro 10m"), Duration::from_mins(35));
+		assert_eq!(parsed("0"), Duration::ZERO);
+
+		assert!(parse("", slot).is_err());
+		assert!(parse("-1h", slot).is_err());
+		assert!(parse("h", slot).is_err());
+		assert!(parse("1h 30", slot).is_err());
+		assert_eq!(
+			parse("3 fortnights", slot),
+			Err(LengthError::UnknownUnit("fortnights".into()))
+		);
+		let huge = format!("1{}h", "0".repeat(300));
+		assert_eq!(parse(&huge, slot), Err(LengthError::TooLong(huge.clone())));
+		assert!(matches!(
+			parse("5000000000000000h 5000000000000000h", slot),
+			Err(LengthError::TooLong(_))
+		));
+		assert!(matches!(parse("1e300", slot), Err(LengthError::TooLong(_))));
+
+		assert_eq!(format(Duration::from_mins(90)), "1h30m");
+		assert_eq!(format(Duration::from_hours(2)), "2h");
+		assert_eq!(format(Duration::from_secs(45 * 60 + 40)), "46m");
+		assert_eq!(format(Duration::ZERO), "0m");
+		assert_eq!(slots(Duration::from_hours(1), slot), 3);
+	}
+}
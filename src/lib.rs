@@ -6,13 +6,19 @@
 #![warn(clippy::get_unwrap)]
 #![warn(clippy::todo)]
 #![warn(clippy::unimplemented)]
-#![warn(clippy::unimplemented)]
 #![warn(missing_docs)]
 #![warn(clippy::unwrap_used)]
 #![allow(clippy::missing_errors_doc)]
 #![allow(clippy::missing_panics_doc)]
 #![allow(missing_docs)]
 
+pub mod config;
 pub mod db;
+pub mod length;
+pub mod notify;
 pub mod pomodoro;
+pub mod profile;
 pub mod scheduler;
+pub mod storage;
+pub mod strategy;
+pub mod when;
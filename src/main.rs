@@ -4,64 +4,246 @@
 #![warn(clippy::get_unwrap)]
 #![warn(clippy::todo)]
 #![warn(clippy::unimplemented)]
-#![warn(clippy::unimplemented)]
 #![warn(clippy::unwrap_used)]
 
 use clap::Parser;
-use pomeranian::db;
-use rustbreak::{deser::Ron, PathDatabase};
-use std::path::PathBuf;
+use pomeranian::{
+	config::{Config, ConfigError, Settings},
+	db::{
+		migrations::{self, LoadError},
+		Db,
+	},
+	profile::{self, Profile, DEFAULT_PROFILE},
+	storage::{Backend, Shared, StorageError},
+};
+use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
 struct Args {
-	#[arg(long, env = "POMERANIAN_DOGHOUSE", default_value = "./pom")]
-	pub db_path: PathBuf,
+	/// Use the database at this path, instead of the profile's
+	#[arg(long, env = "POMERANIAN_DOGHOUSE")]
+	pub db_path: Option<PathBuf>,
+	/// Which profile to use; defaults to the one last switched to, or the default profile
+	#[arg(long, env = "POMERANIAN_PROFILE")]
+	pub profile: Option<String>,
+	/// How the database is stored, `ron` or `sqlite`; guessed from the database's extension by default
+	#[arg(long, env = "POMERANIAN_BACKEND")]
+	pub backend: Option<Backend>,
+	/// Defaults to config.toml in the user's config directory
+	#[arg(long, env = "POMERANIAN_CONFIG")]
+	pub config: Option<PathBuf>,
+	#[command(subcommand)]
+	pub command: Option<cli::Command>,
 }
 
 // mod db;
 
-mod menu;
+mod app;
+mod cli;
+
+/// Everything read from the config file, for the profile in use.
+pub struct Setup {
+	/// Where the config file is, if there's a config directory.
+	pub config_path: Option<PathBuf>,
+	pub config: Config,
+	/// The profile in use, unless a database was given with `--db-path`.
+	pub profile: Option<String>,
+	/// The settings for the profile in use.
+	pub settings: Settings,
+}
+
+impl Setup {
+	/// Read the config file again, after it's been changed.
+	fn reload(&mut self) -> Result<(), ConfigError> {
+		if let Some(path) = &self.config_path {
+			self.config = Config::load(path)?;
+		}
+		self.settings = self.config.settings(self.profile.as_deref())?;
+		Ok(())
+	}
+
+	/// Change a setting in the config file, for just the profile in use or for every profile,
+	/// and lay out the upcoming slots again if it changes them.
+	/// Returns notes about what else happened, for the user to read.
+	fn change_setting(
+		&mut self,
+		db: &mut Db,
+		name: &str,
+		value: &str,
+		profile_only: bool,
+	) -> Result<Vec<String>, String> {
+		let Some(path) = &self.config_path else {
+			return Err("There's no config directory to keep settings in".into());
+		};
+		let profile = match (profile_only, &self.profile) {
+			(false, _) => None,
+			(true, Some(profile)) => Some(profile.as_str()),
+			(true, None) => return Err("A database path was given instead of a profile".into()),
+		};
+		Config::set_setting(path, profile, name, value).map_err(|e| e.to_string())?;
+		self.reload().map_err(|e| e.to_string())?;
+		let mut notes = vec![];
+		if let Some(profile) = &self.profile {
+			if !profile_only
+				&& self
+					.config
+					.profiles
+					.get(profile)
+					.is_some_and(|profile| profile.settings.contains_key(name))
+			{
+				notes.push(format!(
+					"The {profile} profile has its own {name}, which is unchanged"
+				));
+			}
+		}
+		db.stable_plan = self.settings.stable_plan;
+		if db.set_cycle(self.settings.cycle()) {
+			notes.push("Upcoming slots have been laid out again".into());
+		}
+		Ok(notes)
+	}
+}
+
+/// Find the profile that was asked for, or last switched to, or the default one.
+fn pick_profile(
+	config: &Config,
+	current_profile: Option<&Path>,
+	profile: Option<String>,
+) -> (String, Profile) {
+	let name = profile
+		.or_else(|| current_profile.and_then(|path| profile::current(path).ok().flatten()))
+		.unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+	let Some(profile) = config.profile(&name) else {
+		eprintln!("Unknown profile {name:?}");
+		std::process::exit(1);
+	};
+	(name, profile)
+}
 
 fn main() {
-	let Args { db_path } = Args::parse();
-	let db = PathDatabase::<db::Db, Ron>::load_from_path_or_default(db_path).expect("set up db");
+	let Args {
+		db_path,
+		profile,
+		backend,
+		config,
+		command,
+	} = Args::parse();
+	let config_path = config.or_else(Config::default_path);
+	let config = match &config_path {
+		Some(path) => Config::load(path).unwrap_or_else(|e| {
+			eprintln!("{}: {e}", path.display());
+			std::process::exit(1);
+		}),
+		None => Config::default(),
+	};
+	let current_profile = config_path.as_deref().map(profile::current_path);
+	let result = match &command {
+		Some(cli::Command::Switch { profile }) => Some(cli::switch(
+			&config,
+			current_profile.as_deref(),
+			profile.as_deref(),
+		)),
+		Some(cli::Command::Combined) => Some(cli::combined(&config)),
+		_ => None,
+	};
+	if let Some(result) = result {
+		if let Err(e) = result {
+			eprintln!("{e}");
+			std::process::exit(1);
+		}
+		return;
+	}
+	let (profile, db_path, backend) = db_path.map_or_else(
+		|| {
+			let (name, profile) = pick_profile(&config, current_profile.as_deref(), profile);
+			(Some(name), profile.db_path, backend.or(profile.backend))
+		},
+		|db_path| (None, db_path, backend),
+	);
 
-	loop {
-		db.save().expect("Save");
-		let mut db = db.borrow_data_mut().expect("Clean database");
+	let settings = config.settings(profile.as_deref()).unwrap_or_else(|e| {
+		eprintln!("{e}");
+		std::process::exit(1);
+	});
+	let mut setup = Setup {
+		config_path,
+		config,
+		profile,
+		settings,
+	};
+
+	let mut storage = Shared::open(&db_path, backend)
+		.unwrap_or_else(|e| {
+			eprintln!("Couldn't open {}: {e}", db_path.display());
+			std::process::exit(1);
+		})
+		.with_backups(setup.config.backups.clone());
+	if let Some(cli::Command::Restore { number }) = command {
+		if let Err(e) = cli::restore(&mut storage, number) {
+			eprintln!("{e}");
+			std::process::exit(1);
+		}
+		return;
+	}
+	let mut db = load(&mut storage, &db_path);
+
+	db.stable_plan = setup.settings.stable_plan;
+	if db.set_cycle(setup.settings.cycle()) {
+		eprintln!(
+			"The slot and break settings changed, so upcoming slots have been laid out again"
+		);
+	}
+
+	if let Some(command) = command {
 		db.housekeeping();
-		match dialoguer::FuzzySelect::new()
-			.items(&[
-				"view",
-				"add",
-				"remove",
-				"edit",
-				"shuffle for strategy",
-				"start working",
-				"reschedule",
-				"blackboard",
-				"exit",
-			])
-			.interact()
-			.expect("Main menu")
-		{
-			0 => menu::view(&db),
-			1 => menu::add(&mut db),
-			2 => menu::remove(&mut db),
-			3 => menu::edit(&mut db),
-			4 => menu::shuffle(&mut db),
-			5 => menu::timer(&mut db),
-			6 => {
-				db.schedule.slots.clear();
-				db.pomodoro_states.clear();
-				for (_id, task) in db.schedule.tasks.clone() {
-					db.create_slots_up_to(task.working_period.end);
-				}
+		if !db.missed.is_empty() && !matches!(command, cli::Command::Missed { .. }) {
+			for line in app::missed_lines(&db) {
+				eprintln!("{line}");
+			}
+			eprintln!("`pomeranian missed <id>` puts it back in the plan, and --done or --partly <length> count it as done");
+		}
+		let result = cli::run(&mut db, &mut setup, command);
+		save(&mut storage, &mut db);
+		if let Err(e) = result {
+			eprintln!("{e}");
+			std::process::exit(1);
+		}
+		return;
+	}
+	if let Err(e) = app::run(&mut storage, &mut db, &mut setup) {
+		eprintln!("{e}");
+	}
+	save(&mut storage, &mut db);
+}
+
+/// Load the database, or exit with the error.
+fn load(storage: &mut Shared, db_path: &Path) -> Db {
+	storage.load().unwrap_or_else(|e| {
+		eprintln!("Couldn't load {}: {e}", db_path.display());
+		// Only a file that can't be read as any known layout is moved out of the way.
+		// Newer databases, and ones that are locked or can't be read right now, are left as they are
+		if matches!(e, StorageError::Load(LoadError::Parse { .. })) {
+			match migrations::set_aside(db_path) {
+				Ok(moved) => eprintln!(
+					"It has been moved to {}, and a new database will be started next time",
+					moved.display()
+				),
+				Err(e) => eprintln!("It couldn't be moved out of the way: {e}"),
 			}
-			7 => menu::blackboard(&mut db),
-			8 => break,
-			_ => unreachable!(),
+			eprintln!("`pomeranian restore` lists backups that can be restored");
+		}
+		std::process::exit(1);
+	})
+}
+
+/// Save the database, merging in changes from any other pomeranian that saved it in the meantime.
+fn save(storage: &mut Shared, db: &mut Db) {
+	match storage.save(db) {
+		Ok(true) => eprintln!("Merged in changes from another pomeranian"),
+		Ok(false) => {}
+		Err(e) => {
+			eprintln!("Couldn't save: {e}");
+			std::process::exit(1);
 		}
 	}
-	db.save().expect("Save");
 }
@@ -0,0 +1,247 @@
+//! Notifications from the timer, and the buttons on them that send actions back to it.
+
+use chrono::{DateTime, Utc};
+use notify_rust::Notification;
+use std::{
+	collections::VecDeque,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		mpsc::{self, Receiver, RecvTimeoutError, Sender},
+		Arc, Mutex,
+	},
+};
+
+/// A button on a notification, which the timer acts on when it's pressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+	/// Keep going with the current work slot or break for another five minutes.
+	Snooze,
+	/// End the current break now, and start on the next slot straight away.
+	SkipBreak,
+	/// Count the current task as done, and stop working on it.
+	MarkDone,
+}
+
+impl Action {
+	/// What the action is called when it's sent to the notification server.
+	#[must_use]
+	pub fn id(self) -> &'static str {
+		match self {
+			Action::Snooze => "snooze",
+			Action::SkipBreak => "skip_break",
+			Action::MarkDone => "mark_done",
+		}
+	}
+
+	/// The text on the button.
+	#[must_use]
+	pub fn label(self) -> &'static str {
+		match self {
+			Action::Snooze => "Snooze 5m",
+			Action::SkipBreak => "Skip break",
+			Action::MarkDone => "Mark done",
+		}
+	}
+
+	/// The action that the notification server says was picked, if it's one of ours.
+	#[must_use]
+	pub fn from_id(id: &str) -> Option<Self> {
+		[Action::Snooze, Action::SkipBreak, Action::MarkDone]
+			.into_iter()
+			.find(|action| action.id() == id)
+	}
+}
+
+/// What a notification says, which buttons it has, and which work slot or break it's about.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Notice {
+	pub summary: String,
+	pub actions: Vec<Action>,
+	/// When the work slot or break was planned to start, so that a button pressed after it's over can be told apart.
+	pub phase: DateTime<Utc>,
+}
+
+/// Somewhere to send the timer's notifications.
+pub trait Notifier {
+	fn notify(&mut self, notice: &Notice) -> Result<(), String>;
+}
+
+/// Notifications on the desktop, with the buttons pressed on them sent to `actions` along with the phase they were about.
+/// Each notification replaces the last one, so only one thread is ever waiting for buttons to be pressed.
+pub struct Desktop {
+	actions: Sender<(DateTime<Utc>, Action)>,
+	/// The ID of the notification that was shown last.
+	shown: Option<u32>,
+	/// Whether a thread is waiting for the buttons on the notification to be pressed.
+	waiting: Arc<AtomicBool>,
+	/// The phase of the notice being shown.
+	phase: Arc<Mutex<DateTime<Utc>>>,
+}
+
+impl Desktop {
+	#[must_use]
+	pub fn new(actions: Sender<(DateTime<Utc>, Action)>) -> Self {
+		Self {
+			actions,
+			shown: None,
+			waiting: Arc::default(),
+			phase: Arc::default(),
+		}
+	}
+}
+
+impl Notifier for Desktop {
+	fn notify(&mut self, notice: &Notice) -> Result<(), String> {
+		let mut notification = Notification::new();
+		notification.summary(&notice.summary);
+		for action in &notice.actions {
+			notification.action(action.id(), action.label());
+		}
+		if let Some(id) = self.shown {
+			notification.id(id);
+		}
+		*self
+			.phase
+			.lock()
+			.expect("Nothing panics while holding the lock") = notice.phase;
+		let handle = notification.show().map_err(|e| e.to_string())?;
+		self.shown = Some(handle.id());
+		// Waiting blocks until the notification is acted on or closed, and keeps waiting while it's replaced
+		if !self.waiting.swap(true, Ordering::SeqCst) {
+			let (actions, waiting, phase) = (
+				self.actions.clone(),
+				self.waiting.clone(),
+				self.phase.clone(),
+			);
+			std::thread::spawn(move || {
+				handle.wait_for_action(|id| {
+					waiting.store(false, Ordering::SeqCst);
+					if let Some(action) = Action::from_id(id) {
+						let phase = *phase.lock().expect("Nothing panics while holding the lock");
+						// The app may have quit since, and then there's nobody to tell
+						let _ = actions.send((phase, action));
+					}
+				});
+			});
+		}
+		Ok(())
+	}
+}
+
+/// Sends notices when they come due, from a thread of its own, so that they go out on time whatever the app is doing.
+pub struct Outbox {
+	plans: Sender<Vec<(DateTime<Utc>, Notice)>>,
+}
+
+impl Outbox {
+	/// Start sending notices through a notifier, with the errors from any that couldn't be shown sent to `errors`.
+	pub fn start(mut notifier: impl Notifier + Send + 'static, errors: Sender<String>) -> Self {
+		let (plans, new_plans) = mpsc::channel::<Vec<(DateTime<Utc>, Notice)>>();
+		std::thread::spawn(move || {
+			let mut plan: VecDeque<(DateTime<Utc>, Notice)> = VecDeque::new();
+			loop {
+				let received = match plan.front() {
+					Some((time, _)) => {
+						new_plans.recv_timeout((*time - Utc::now()).to_std().unwrap_or_default())
+					}
+					None => new_plans.recv().map_err(|_| RecvTimeoutError::Disconnected),
+				};
+				match received {
+					Ok(mut notices) => {
+						notices.sort_by_key(|(time, _)| *time);
+						plan = notices.into();
+					}
+					Err(RecvTimeoutError::Timeout) => {}
+					Err(RecvTimeoutError::Disconnected) => break,
+				}
+				while plan.front().is_some_and(|(time, _)| *time <= Utc::now()) {
+					let (_, notice) = plan.pop_front().expect("There's a notice");
+					if let Err(e) = notifier.notify(&notice) {
+						let _ = errors.send(e);
+					}
+				}
+			}
+		});
+		Self { plans }
+	}
+
+	/// Replace the notices waiting to be sent. Any that are already due are sent straight away, in order.
+	pub fn plan(&self, notices: Vec<(DateTime<Utc>, Notice)>) {
+		// The thread only stops when the outbox is dropped
+		let _ = self.plans.send(notices);
+	}
+}
+
+/// An outbox for desktop notifications, with the buttons pressed on them and the errors from showing them.
+pub struct Notifications {
+	pub outbox: Outbox,
+	/// The buttons that were pressed, with the phase of the notice each was on.
+	pub actions: Receiver<(DateTime<Utc>, Action)>,
+	/// Why notifications couldn't be shown.
+	pub errors: Receiver<String>,
+}
+
+impl Notifications {
+	#[must_use]
+	pub fn desktop() -> Self {
+		let (actions_sender, actions) = mpsc::channel();
+		let (errors_sender, errors) = mpsc::channel();
+		Self {
+			outbox: Outbox::start(Desktop::new(actions_sender), errors_sender),
+			actions,
+			errors,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Notice, Notifier, Outbox};
+	use chrono::{DateTime, Utc};
+	use std::{
+		sync::mpsc::{self, Sender},
+		time::Duration,
+	};
+
+	#[test]
+	fn outbox_sends_when_due() {
+		struct Recorder(Sender<String>);
+		impl Notifier for Recorder {
+			fn notify(&mut self, notice: &Notice) -> Result<(), String> {
+				if notice.summary == "broken" {
+					return Err("No notification server".into());
+				}
+				self.0
+					.send(notice.summary.clone())
+					.map_err(|e| e.to_string())
+			}
+		}
+		let notice = |summary: &str| Notice {
+			summary: summary.into(),
+			actions: vec![],
+			phase: DateTime::default(),
+		};
+		let (sender, sent) = mpsc::channel();
+		let (errors_sender, errors) = mpsc::channel();
+		let outbox = Outbox::start(Recorder(sender), errors_sender);
+		let wait = Duration::from_secs(5);
+		let now = Utc::now();
+
+		outbox.plan(vec![
+			(now + Duration::from_hours(1), notice("replaced")),
+			(now, notice("first")),
+		]);
+		assert_eq!(sent.recv_timeout(wait).as_deref(), Ok("first"));
+		outbox.plan(vec![(
+			Utc::now() + Duration::from_millis(50),
+			notice("second"),
+		)]);
+		assert_eq!(sent.recv_timeout(wait).as_deref(), Ok("second"));
+		outbox.plan(vec![(Utc::now(), notice("broken"))]);
+		assert_eq!(
+			errors.recv_timeout(wait).as_deref(),
+			Ok("No notification server")
+		);
+		assert!(sent.try_recv().is_err());
+	}
+}
@@ -4,14 +4,14 @@ use serde::{Deserialize, Serialize};
 /// The pomodoro state machine.
 #[derive(Serialize, Deserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
 pub enum Pomodoro {
-	/// Work(n) represents a 25-minute work period in the typical pomodoro technique.
-	/// Becomes Break(n-1), or LongBreak if n=0.
+	/// `Work(n)` represents a 25-minute work period in the typical pomodoro technique.
+	/// Becomes `Break(n-1)`, or `LongBreak` if n=0.
 	Work(u32),
-	/// Break(n) represents a 5-minute break period in the typical pomodoro technique.
-	/// Becomes Work(n).
+	/// `Break(n)` represents a 5-minute break period in the typical pomodoro technique.
+	/// Becomes `Work(n)`.
 	Break(u32),
-	/// The state machine starts in LongBreak, which represents a 30-minute break in the typical pomodoro technique.
-	/// LongBreak becomes Work(n-1) where n is the break interval.
+	/// The state machine starts in `LongBreak`, which represents a 30-minute break in the typical pomodoro technique.
+	/// `LongBreak` becomes `Work(n-1)` where n is the break interval.
 	#[default]
 	LongBreak,
 }
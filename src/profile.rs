@@ -0,0 +1,136 @@
+//! Named profiles, each with its own database, to keep separate plans like a job and a degree apart.
+
+use crate::{db::Db, storage::Backend};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+	io,
+	path::{Path, PathBuf},
+};
+
+/// The profile used when none has been picked, which keeps its database at `./pom` unless configured otherwise.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Where a profile keeps its database, set in a `[profiles.<name>]` table of the config file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Profile {
+	/// The path to the database, `./pom` by default
+	pub db_path: PathBuf,
+	/// How the database is stored, `ron` or `sqlite`; guessed from its extension by default
+	pub backend: Option<Backend>,
+	/// Settings that are different for this profile, overriding the shared ones
+	#[serde(skip_serializing_if = "toml::Table::is_empty")]
+	pub settings: toml::Table,
+}
+
+impl Default for Profile {
+	fn default() -> Self {
+		Self {
+			db_path: PathBuf::from("./pom"),
+			backend: None,
+			settings: toml::Table::new(),
+		}
+	}
+}
+
+/// The file that remembers which profile was last switched to, kept next to the config file.
+#[must_use]
+pub fn current_path(config_path: &Path) -> PathBuf {
+	config_path.with_file_name("profile")
+}
+
+/// The profile that was last switched to, if any.
+pub fn current(path: &Path) -> io::Result<Option<String>> {
+	match std::fs::read_to_string(path) {
+		Ok(name) => Ok(Some(name.trim().to_string()).filter(|name| !name.is_empty())),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+		Err(e) => Err(e),
+	}
+}
+
+/// Make a profile the one that's used when none is given.
+pub fn switch(path: &Path, name: &str) -> io::Result<()> {
+	if let Some(parent) = path.parent() {
+		std::fs::create_dir_all(parent)?;
+	}
+	std::fs::write(path, format!("{name}\n"))
+}
+
+/// A slot that has a task in one profile, and overlaps a slot with a task in another.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Overlap {
+	/// When the overlap starts.
+	pub time: DateTime<Utc>,
+	/// The profile and task name of each side.
+	pub first: (String, String),
+	pub second: (String, String),
+}
+
+/// Find every time that two profiles have work planned at once.
+/// Profiles can use different slot lengths, so slots overlap whenever their time ranges do.
+#[must_use]
+pub fn overlaps(profiles: &[(String, Db)]) -> Vec<Overlap> {
+	let mut claims = profiles
+		.iter()
+		.flat_map(|(profile, db)| {
+			db.slots.iter().filter_map(move |(start, task)| {
+				let task = &db.tasks.get(task.as_ref()?)?.name;
+				Some((*start, *start + db.schedule.timeslice_length, profile, task))
+			})
+		})
+		.collect::<Vec<_>>();
+	claims.sort_by_key(|(start, ..)| *start);
+
+	let mut overlaps = vec![];
+	for (index, (_, end, profile, task)) in claims.iter().enumerate() {
+		for (start, _, other_profile, other_task) in claims[index + 1..]
+			.iter()
+			.take_while(|(start, ..)| start < end)
+		{
+			if profile != other_profile {
+				overlaps.push(Overlap {
+					time: *start,
+					first: ((*profile).clone(), (*task).clone()),
+					second: ((*other_profile).clone(), (*other_task).clone()),
+				});
+			}
+		}
+	}
+	overlaps
+}
+
+#[cfg(test)]
+mod tests {
+	use super::overlaps;
+	use crate::db::migrations;
+	use std::time::Duration;
+
+	#[test]
+	fn find_overlaps() {
+		let text = include_str!("../tests/corpus/v1.ron");
+		let job = migrations::parse(text).expect("Valid database").db;
+		let (first, _) = job
+			.slots
+			.iter()
+			.find(|(_, task)| task.is_some())
+			.expect("A slot with a task");
+		let clashing = *first + Duration::from_mins(10);
+
+		// The degree uses shorter slots, one of which starts partway through the job's first task
+		let mut degree = job.clone();
+		degree.schedule.timeslice_length = Duration::from_mins(5);
+		degree.schedule.slots = [
+			(clashing, job.slots[first].clone()),
+			(*first - Duration::from_mins(5), job.slots[first].clone()),
+			(clashing + Duration::from_mins(5), None),
+		]
+		.into();
+
+		let found = overlaps(&[("job".into(), job.clone()), ("degree".into(), degree)]);
+		assert_eq!(found.len(), 1);
+		assert_eq!(found[0].time, clashing);
+		assert_eq!(found[0].first.0, "job");
+		assert_eq!(found[0].second.0, "degree");
+	}
+}
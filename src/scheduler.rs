@@ -1,14 +1,14 @@
 //! The scheduler for organizing tasks.
 //! This isn't great since copies of the task are stored as map keys, but it works OK
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration as TimeDelta, Local, NaiveDate, Utc};
 use itertools::Itertools;
 use rand::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::{
-	collections::{BTreeMap, HashMap, HashSet},
-	fmt::Debug,
-	ops::Range,
+	collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+	fmt::{self, Debug, Display, Formatter},
+	ops::{Bound, Range},
 	string::String,
 	sync::{
 		atomic::{AtomicI64, Ordering},
@@ -36,6 +36,134 @@ pub trait Task {
 			.as_secs()
 			.div_ceil(duration.as_secs())
 	}
+	/// How long after the end of its working period this task can still be worked on, late.
+	/// Zero means that the end of the working period is a hard deadline.
+	fn grace_period(&self) -> Duration {
+		Duration::ZERO
+	}
+	/// The working period, extended by the grace period.
+	fn allowed_period(&self) -> Range<DateTime<Utc>> {
+		let working_period = self.working_period();
+		working_period.start..(working_period.end + self.grace_period())
+	}
+	/// The fewest slots in a row that this task should be worked on for.
+	fn min_block(&self) -> u64 {
+		1
+	}
+	/// The most slots in a row that this task should be worked on for, if there's a limit.
+	fn max_block(&self) -> Option<u64> {
+		None
+	}
+	/// The most slots that this task should get in one day, if there's a limit.
+	fn daily_cap(&self) -> Option<u64> {
+		None
+	}
+	/// The fewest days that this task should be spread out over.
+	fn min_days(&self) -> u64 {
+		1
+	}
+}
+
+/// How well the scheduler managed to fit a task which is late or unsatisfied.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Outcome {
+	/// The number of slots the task needs but doesn't have.
+	pub missing: u64,
+	/// The number of slots the task has which start after its due date.
+	pub late_slots: u64,
+	/// How long after its due date the task's last slot ends.
+	pub lateness: Duration,
+	/// Why the task couldn't get the slots it's missing.
+	pub reason: Option<Reason>,
+}
+
+/// The reason that a task is missing slots.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Reason {
+	/// There aren't enough slots in the task's working and grace periods, even if it had all of them.
+	NotEnoughTime {
+		/// The number of slots in the working and grace periods.
+		available: u64,
+	},
+	/// The daily cap, or spreading the task out, doesn't allow enough slots on the days available.
+	DailyLimit {
+		/// The most slots the task can have in a day.
+		per_day: u64,
+		/// The number of days with slots in the working and grace periods.
+		days: u64,
+	},
+	/// There aren't enough runs of slots long enough for the task's minimum block size.
+	BlockSize {
+		/// The fewest slots in a row the task can be worked on for.
+		min_block: u64,
+		/// The number of slots in runs that are long enough.
+		available: u64,
+	},
+	/// There would be enough time, but other tasks have it.
+	Crowded,
+}
+
+impl Display for Reason {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Reason::NotEnoughTime { available } => {
+				write!(f, "only {available} slots fit before the deadline")
+			}
+			Reason::DailyLimit { per_day, days } => write!(
+				f,
+				"at most {per_day} slots a day over {days} days leaves too little time"
+			),
+			Reason::BlockSize {
+				min_block,
+				available,
+			} => write!(
+				f,
+				"only {available} slots are in runs of at least {min_block} in a row"
+			),
+			Reason::Crowded => write!(f, "other tasks have the time it needs"),
+		}
+	}
+}
+
+/// Why a task has the slots it has, and what would get it the ones it's missing.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Explanation {
+	/// The number of slots the task needs.
+	pub wants: u64,
+	/// The number of slots the task has.
+	pub has: u64,
+	/// The number of slots in the task's working and grace periods, whoever has them.
+	pub available: u64,
+	/// The number of those slots which nobody has and aren't pinned.
+	pub free: u64,
+	/// The number of those slots which are pinned to other tasks or pinned free, so that it can't take them.
+	pub pinned: u64,
+	/// Tasks with at least this task's priority which have slots in its working and grace periods,
+	/// so that it can't take them, with how many they have, most first.
+	pub competitors: Vec<(String, u64)>,
+	/// Why the task couldn't get the slots it's missing, if it's missing any.
+	pub reason: Option<Reason>,
+	/// Changes to the task which would get it the slots it's missing, if it's missing any.
+	pub fixes: Vec<Fix>,
+}
+
+/// A change to a task which would get it all of the slots it needs.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Fix {
+	/// Make it due this much later, to reach enough slots that are free or have lower-priority tasks.
+	ExtendDeadline(Duration),
+	/// Estimate it to take this much less time, so that the slots it has are enough.
+	LowerEstimate(Duration),
+	/// Give it a higher priority than this task, so that it can take enough slots from it and from tasks below it.
+	RaisePriority {
+		/// The ID of the task to outrank.
+		above: String,
+	},
+}
+
+/// The local calendar day that a slot is on, for daily limits.
+fn day(time: DateTime<Utc>) -> NaiveDate {
+	time.with_timezone(&Local).date_naive()
 }
 
 /// Tasks are organized first by claiming the first (length) slots in their working period, in ascending length order.
@@ -48,6 +176,9 @@ pub struct Schedule<T: Task> {
 	pub slots: BTreeMap<DateTime<Utc>, Option<String>>,
 	/// The length of each timeslice.
 	pub timeslice_length: Duration,
+	/// Slots that the user has decided on, which scheduling and shuffling leave as they are, whether they hold a task or are kept free.
+	#[serde(default)]
+	pub pinned: BTreeSet<DateTime<Utc>>,
 }
 
 impl<T: Task + Debug> Schedule<T> {
@@ -79,15 +210,213 @@ impl<T: Task + Debug> Schedule<T> {
 			.collect()
 	}
 
+	/// Tasks which are late or unsatisfied, and by how much.
+	#[must_use]
+	pub fn outcomes(&self) -> BTreeMap<String, Outcome> {
+		let mut outcomes: BTreeMap<String, Outcome> = BTreeMap::new();
+		let mut counts: HashMap<&str, u64> = HashMap::new();
+		for (time, id) in &self.slots {
+			let Some(id) = id.as_deref() else {
+				continue;
+			};
+			let Some(task) = self.tasks.get(id) else {
+				continue;
+			};
+			*counts.entry(id).or_default() += 1;
+			let due = task.working_period().end;
+			if *time >= due {
+				let outcome = outcomes.entry(id.to_string()).or_default();
+				outcome.late_slots += 1;
+				outcome.lateness = (*time + self.timeslice_length - due)
+					.to_std()
+					.expect("Slot ends after the due date");
+			}
+		}
+		for (id, task) in &self.tasks {
+			let has = counts.get(id.as_str()).copied().unwrap_or_default();
+			let wants = task.divided_into(self.timeslice_length);
+			if has < wants {
+				let outcome = outcomes.entry(id.clone()).or_default();
+				outcome.missing = wants - has;
+				outcome.reason = Some(self.reason(task));
+			}
+		}
+		outcomes
+	}
+
+	/// Work out why a task can't get enough slots, from the slots there are in its working and grace periods.
+	fn reason(&self, task: &T) -> Reason {
+		let wants = task.divided_into(self.timeslice_length);
+		let range = task.allowed_period();
+		let available = self.slots.range(range.clone()).count() as u64;
+		if available < wants {
+			return Reason::NotEnoughTime { available };
+		}
+		let per_day = self.day_limit(task);
+		let days = self
+			.slots
+			.range(range.clone())
+			.map(|(time, _)| day(*time))
+			.unique()
+			.count() as u64;
+		if per_day.saturating_mul(days) < wants {
+			return Reason::DailyLimit { per_day, days };
+		}
+		let (min_block, _) = self.block_limits(task);
+		if min_block > 1 {
+			let available = self
+				.runs(range, |_, _| true)
+				.into_iter()
+				.map(|run| run.len() as u64)
+				.filter(|len| *len >= min_block)
+				.sum();
+			if available < wants {
+				return Reason::BlockSize {
+					min_block,
+					available,
+				};
+			}
+		}
+		Reason::Crowded
+	}
+
+	/// Explain how a task is placed: how many slots it has out of those it could have, which tasks it can't take them from,
+	/// and what would get it any that it's missing.
+	/// Daily limits and block sizes aren't taken into account by the fixes, so they're the least that would help.
+	#[must_use]
+	pub fn explain(&self, id: &str) -> Option<Explanation> {
+		let task = self.tasks.get(id)?;
+		let wants = task.divided_into(self.timeslice_length);
+		let range = task.allowed_period();
+		let has = self
+			.slots
+			.values()
+			.filter(|slot| slot.as_deref() == Some(id))
+			.count() as u64;
+
+		let (mut available, mut free, mut own, mut pinned) = (0, 0, 0, 0);
+		let mut holders: HashMap<&str, u64> = HashMap::new();
+		for (time, slot) in self.slots.range(range.clone()) {
+			available += 1;
+			match slot.as_deref() {
+				Some(other) if other == id => own += 1,
+				_ if self.pinned.contains(time) => pinned += 1,
+				None => free += 1,
+				Some(other) => *holders.entry(other).or_default() += 1,
+			}
+		}
+		let outranks = |other: &str| {
+			self.tasks
+				.get(other)
+				.is_none_or(|other| other.priority() < task.priority())
+		};
+		let competitors = holders
+			.iter()
+			.filter(|(other, _)| !outranks(other))
+			.map(|(other, count)| ((*other).to_string(), *count))
+			.sorted_by_key(|(other, count)| (std::cmp::Reverse(*count), other.clone()))
+			.collect_vec();
+
+		let missing = wants.saturating_sub(has);
+		let mut fixes = vec![];
+		if missing > 0 {
+			let takeable = |time: &DateTime<Utc>, slot: &Option<String>| {
+				slot.as_deref() == Some(id)
+					|| (!self.pinned.contains(time) && slot.as_deref().is_none_or(outranks))
+			};
+			let extra = self
+				.slots
+				.range(range.end..)
+				.filter(|(time, slot)| takeable(time, slot))
+				.nth(usize::try_from(missing - 1).unwrap_or(usize::MAX));
+			if let Some((time, _)) = extra {
+				let extension = (*time + self.timeslice_length - range.end)
+					.to_std()
+					.expect("Slot ends after the deadline");
+				fixes.push(Fix::ExtendDeadline(extension));
+			}
+
+			let fits = self
+				.timeslice_length
+				.saturating_mul(u32::try_from(has).unwrap_or(u32::MAX));
+			fixes.push(Fix::LowerEstimate(
+				task.estimated_length().saturating_sub(fits),
+			));
+
+			// Outrank competitors from the lowest priority up, until enough slots could be taken
+			let mut takeable_count = free
+				+ own + holders
+				.iter()
+				.filter(|(other, _)| outranks(other))
+				.map(|(_, count)| count)
+				.sum::<u64>();
+			if takeable_count < wants {
+				let ranked = competitors
+					.iter()
+					.map(|(other, count)| (self.tasks[other.as_str()].priority(), other, count))
+					.sorted_by_key(|(priority, ..)| *priority)
+					.collect_vec();
+				for (index, (priority, other, count)) in ranked.iter().enumerate() {
+					takeable_count += *count;
+					// Outranking one task outranks every other task with the same priority
+					let last_of_priority = ranked
+						.get(index + 1)
+						.is_none_or(|(next, ..)| next != priority);
+					if last_of_priority && takeable_count >= wants {
+						fixes.push(Fix::RaisePriority {
+							above: (*other).clone(),
+						});
+						break;
+					}
+				}
+			}
+		}
+
+		Some(Explanation {
+			wants,
+			has,
+			available,
+			free,
+			pinned,
+			competitors,
+			reason: (missing > 0).then(|| self.reason(task)),
+			fixes,
+		})
+	}
+
 	/// Remove all slots that end in the past.
 	pub fn remove_old_slots(&mut self, before: DateTime<Utc>) {
 		self.slots
 			.retain(|t, _| (*t + self.timeslice_length) >= before);
+		self.pinned.retain(|t| self.slots.contains_key(t));
+	}
+
+	/// Whether a slot is pinned, so that only the user changes what's in it.
+	#[must_use]
+	pub fn is_pinned(&self, time: DateTime<Utc>) -> bool {
+		self.pinned.contains(&time)
+	}
+
+	/// Put a task in a slot, or keep it free with `None`, and stop scheduling and shuffling from changing it.
+	/// Returns false if there's no slot at that time.
+	pub fn pin(&mut self, time: DateTime<Utc>, id: Option<String>) -> bool {
+		let Some(slot) = self.slots.get_mut(&time) else {
+			return false;
+		};
+		*slot = id;
+		self.pinned.insert(time);
+		true
 	}
 
-	/// Try to satisfy every task.
+	/// Let scheduling and shuffling change a slot again. Returns whether it was pinned.
+	pub fn unpin(&mut self, time: DateTime<Utc>) -> bool {
+		self.pinned.remove(&time)
+	}
+
+	/// Try to satisfy every task, on time if possible and within its grace period if not, around the slots that are pinned.
+	/// Returns the tasks which are late or unsatisfied.
 	#[allow(clippy::missing_panics_doc)]
-	pub fn schedule(&mut self) -> HashSet<String> {
+	pub fn schedule(&mut self) -> BTreeMap<String, Outcome> {
 		let mut tasks: HashMap<_, _> = self
 			.tasks
 			.iter()
@@ -117,40 +446,46 @@ impl<T: Task + Debug> Schedule<T> {
 			})
 			.collect();
 
-		// Free up slots for tasks with more than they need
-		for slot in &mut self.slots.values_mut().filter(|v| v.is_some()) {
+		// Free up slots for tasks with more than they need, except for the ones the user pinned
+		for (time, slot) in &mut self.slots {
 			let Some(id) = slot.clone() else {
-				unreachable!()
+				continue;
 			};
 			let Some((_task, wants_change)) = tasks.get_mut(&id) else {
 				*slot = None;
+				self.pinned.remove(time);
 				continue;
 			};
-			if wants_change.load(Ordering::Relaxed) >= 0 {
+			if wants_change.load(Ordering::Relaxed) >= 0 || self.pinned.contains(time) {
 				continue;
 			}
 			*slot = None;
 			wants_change.fetch_add(1, Ordering::Relaxed);
 		}
+		self.repair(&tasks);
 
 		// Each task takes what it needs, in ascending order of working period length
-		for (id, (task, wants_change)) in tasks.iter_mut().sorted_by_key(|(_, (task, _))| {
-			let wp = task.working_period();
-			wp.end - wp.start
-		}) {
-			let mut working_period = self.slots.range_mut(task.working_period());
-			'take: while wants_change.load(Ordering::Relaxed) > 0 {
-				let slot = match working_period.next() {
-					Some((_, slot @ None)) => slot,
-					Some((_, Some(_))) => continue 'take,
-					None => break 'take,
-				};
-				*slot = Some(id.clone());
-				wants_change.fetch_sub(1, Ordering::Relaxed);
-			}
-		}
+		let by_length = tasks
+			.iter()
+			.sorted_by_key(|(_, (task, _))| {
+				let wp = task.working_period();
+				wp.end - wp.start
+			})
+			.collect_vec();
+		self.claim_all(&by_length);
 
-		// The Timeslice Hunger Games
+		self.hunger_games(&tasks);
+
+		// Stealing single slots can leave blocks that are too short or too long, so fix them up
+		self.repair(&tasks);
+		self.claim_all(&by_length);
+		self.repair(&tasks);
+
+		self.outcomes()
+	}
+
+	/// The Timeslice Hunger Games: each task which still wants slots steals them from lower-priority tasks, until nothing changes.
+	fn hunger_games(&mut self, tasks: &HashMap<String, (Arc<T>, AtomicI64)>) {
 		loop {
 			let mut done = true;
 
@@ -158,26 +493,43 @@ impl<T: Task + Debug> Schedule<T> {
 				.iter()
 				.filter(|(_, (_, w))| w.load(Ordering::Relaxed) > 0)
 			{
+				if self.block_limits(task).0 > 1 {
+					// Stealing single slots would only leave blocks that are too short
+					if self.steal_window(id, task, wants_change, tasks) {
+						done = false;
+					}
+					continue 'task;
+				}
+				let due = task.working_period().end;
+				let day_limit = self.day_limit(task);
+				let mut per_day = self.slots_per_day(id);
+				// Slots before the due date are stolen first
 				let candidates: Vec<_> = self
 					.slots
-					.range(task.working_period())
+					.range(task.allowed_period())
+					.filter(|(s, _)| !self.pinned.contains(s))
 					.filter_map(|(s, t)| {
 						t.as_ref()
-							.map(|t| (*s, t.to_string(), self.tasks[t.as_str()].priority()))
+							.map(|t| (*s, t.clone(), self.tasks[t.as_str()].priority()))
 					})
 					.filter(|(_, _, p)| *p < task.priority())
-					.sorted_by_key(|(_, _t, p)| *p)
+					.sorted_by_key(|(s, _t, p)| (*s >= due, *p))
 					.map(|(slot, task, _)| (slot, task))
 					.collect();
 				for (slot, candidate_task) in candidates {
+					let today = per_day.entry(day(slot)).or_default();
+					if *today >= day_limit {
+						continue;
+					}
+					*today += 1;
 					let (_, candidate_wants_change) = &tasks[&candidate_task];
 					done = false;
 					candidate_wants_change.fetch_add(1, Ordering::Relaxed);
+					self.slots.insert(slot, Some(id.clone()));
 					let wants = wants_change.fetch_sub(1, Ordering::Relaxed) - 1;
 					if wants == 0 {
 						continue 'task;
 					}
-					self.slots.insert(slot, Some(id.clone()));
 				}
 			}
 
@@ -185,91 +537,544 @@ impl<T: Task + Debug> Schedule<T> {
 				break;
 			}
 		}
+	}
 
-		tasks
-			.into_iter()
-			.filter(|(_, (_, wants))| wants.load(Ordering::Relaxed) != 0)
-			.map(|(id, _)| id)
-			.collect()
+	/// Let a task take over the first window of slots which is long enough for a block, and only has free slots, its own slots or slots of lower-priority tasks.
+	/// Returns whether it took anything.
+	fn steal_window(
+		&mut self,
+		id: &str,
+		task: &T,
+		wants_change: &AtomicI64,
+		tasks: &HashMap<String, (Arc<T>, AtomicI64)>,
+	) -> bool {
+		let (min, max) = self.block_limits(task);
+		let day_limit = self.day_limit(task);
+		let mut per_day = self.slots_per_day(id);
+		let windows = self.runs(task.allowed_period(), |time, slot| {
+			slot == Some(id)
+				|| (!self.pinned.contains(&time)
+					&& slot.is_none_or(|other| self.tasks[other].priority() < task.priority()))
+		});
+		for window in windows {
+			let own = window
+				.iter()
+				.filter(|time| self.slots[*time].as_deref() == Some(id))
+				.count();
+			let wants = wants_change.load(Ordering::Relaxed);
+			if (window.len() as u64) < min
+				|| own == window.len()
+				|| (own == 0 && wants < i64::try_from(min).unwrap_or(i64::MAX))
+			{
+				continue;
+			}
+			let mut took = false;
+			for time in window
+				.into_iter()
+				.take(usize::try_from(max).unwrap_or(usize::MAX))
+			{
+				if wants_change.load(Ordering::Relaxed) <= 0 {
+					break;
+				}
+				if self.slots[&time].as_deref() != Some(id) {
+					let today = per_day.entry(day(time)).or_default();
+					if *today >= day_limit {
+						break;
+					}
+					*today += 1;
+				}
+				match self.slots.insert(time, Some(id.to_string())).flatten() {
+					Some(owner) if owner == id => continue,
+					Some(victim) => {
+						tasks[&victim].1.fetch_add(1, Ordering::Relaxed);
+					}
+					None => {}
+				}
+				wants_change.fetch_sub(1, Ordering::Relaxed);
+				took = true;
+			}
+			if took {
+				return true;
+			}
+		}
+		false
+	}
+
+	/// Let each task claim free slots on time, and then let anything that didn't fit claim free slots in its grace period.
+	fn claim_all(&mut self, tasks: &[(&String, &(Arc<T>, AtomicI64))]) {
+		for (id, (task, wants_change)) in tasks {
+			self.claim(id, wants_change, task.working_period());
+		}
+		for (id, (task, wants_change)) in tasks {
+			let working_period = task.working_period();
+			self.claim(
+				id,
+				wants_change,
+				working_period.end..task.allowed_period().end,
+			);
+		}
+	}
+
+	/// Give a task free slots from a range of time until it doesn't want any more.
+	/// A task with a minimum block size only starts a new block where there's room to finish it.
+	fn claim(&mut self, id: &str, wants_change: &AtomicI64, range: Range<DateTime<Utc>>) {
+		if range.is_empty() {
+			return;
+		}
+		let Some(task) = self.tasks.get(id).cloned() else {
+			return;
+		};
+		let (min, max) = self.block_limits(&*task);
+		let day_limit = self.day_limit(&task);
+		let mut per_day = self.slots_per_day(id);
+		let free = self
+			.slots
+			.range(range.clone())
+			.filter(|(time, slot)| slot.is_none() && !self.pinned.contains(time))
+			.map(|(time, _)| *time)
+			.collect_vec();
+		for time in free {
+			let wants = wants_change.load(Ordering::Relaxed);
+			if wants <= 0 {
+				break;
+			}
+			if per_day
+				.get(&day(time))
+				.is_some_and(|today| *today >= day_limit)
+			{
+				continue;
+			}
+			if min > 1 || max < u64::MAX {
+				let ours = |_, slot: Option<&str>| slot == Some(id);
+				let before = self.count_run(time, self.slots.range(..time).rev(), ours);
+				let after = self.count_run(
+					time,
+					self.slots.range((Bound::Excluded(time), Bound::Unbounded)),
+					ours,
+				);
+				if before + 1 + after > max {
+					continue;
+				}
+				if before == 0 && after == 0 {
+					let room = 1 + self.count_run(
+						time,
+						self.slots
+							.range((Bound::Excluded(time), Bound::Excluded(range.end))),
+						|time, slot| slot.is_none() && !self.pinned.contains(&time),
+					);
+					if room < min || wants < i64::try_from(min).unwrap_or(i64::MAX) {
+						continue;
+					}
+				}
+			}
+			self.slots.insert(time, Some(id.to_string()));
+			*per_day.entry(day(time)).or_default() += 1;
+			wants_change.fetch_sub(1, Ordering::Relaxed);
+		}
+	}
+
+	/// The most slots a task can have in one day, from its daily cap and how many days it has to be spread out over.
+	/// Slots that don't split evenly over the days go one each to some of them.
+	fn day_limit(&self, task: &T) -> u64 {
+		let spread = match task.min_days() {
+			0 | 1 => u64::MAX,
+			days => task.divided_into(self.timeslice_length).div_ceil(days),
+		};
+		task.daily_cap().unwrap_or(u64::MAX).min(spread)
+	}
+
+	/// How many slots a task has on each day.
+	#[must_use]
+	pub fn slots_per_day(&self, id: &str) -> HashMap<NaiveDate, u64> {
+		let mut per_day = HashMap::new();
+		for (time, _) in self
+			.slots
+			.iter()
+			.filter(|(_, slot)| slot.as_deref() == Some(id))
+		{
+			*per_day.entry(day(*time)).or_default() += 1;
+		}
+		per_day
+	}
+
+	/// Whether a task is within its daily limit on every day.
+	#[must_use]
+	pub fn days_ok(&self, id: &str) -> bool {
+		let Some(task) = self.tasks.get(id) else {
+			return true;
+		};
+		let day_limit = self.day_limit(task);
+		day_limit == u64::MAX || self.slots_per_day(id).values().all(|n| *n <= day_limit)
+	}
+
+	/// Whether a task's slots are within all of its block size and daily limits.
+	#[must_use]
+	pub fn constraints_ok(&self, id: &str) -> bool {
+		self.blocks_ok(id) && self.days_ok(id)
+	}
+
+	/// Whether two neighbouring slots are close enough together to be worked on as one block.
+	/// Short breaks don't split up a block, but long breaks and the end of the day do.
+	fn contiguous(&self, earlier: DateTime<Utc>, later: DateTime<Utc>) -> bool {
+		(later - earlier)
+			.to_std()
+			.is_ok_and(|gap| gap <= self.timeslice_length * 2)
+	}
+
+	/// Count the slots in a row, walking away from `time`, for as long as they match.
+	fn count_run<'a>(
+		&self,
+		time: DateTime<Utc>,
+		slots: impl Iterator<Item = (&'a DateTime<Utc>, &'a Option<String>)>,
+		matches: impl Fn(DateTime<Utc>, Option<&str>) -> bool,
+	) -> u64 {
+		let mut count = 0;
+		let mut cursor = time;
+		for (slot_time, slot) in slots {
+			if !self.contiguous(cursor.min(*slot_time), cursor.max(*slot_time))
+				|| !matches(*slot_time, slot.as_deref())
+			{
+				break;
+			}
+			count += 1;
+			cursor = *slot_time;
+		}
+		count
+	}
+
+	/// The smallest and largest number of slots in a row that a task can be worked on for.
+	/// A task that needs fewer slots than its minimum block can be done in one go.
+	fn block_limits(&self, task: &T) -> (u64, u64) {
+		let min = task
+			.min_block()
+			.min(task.divided_into(self.timeslice_length))
+			.max(1);
+		let max = task.max_block().unwrap_or(u64::MAX).max(min);
+		(min, max)
+	}
+
+	/// The blocks of slots in a row that a task has been given.
+	#[must_use]
+	pub fn blocks(&self, id: &str) -> Vec<Vec<DateTime<Utc>>> {
+		self.runs(
+			DateTime::<Utc>::MIN_UTC..DateTime::<Utc>::MAX_UTC,
+			|_, slot| slot == Some(id),
+		)
+	}
+
+	/// Find the runs of slots in a row within a range of time which all match.
+	fn runs(
+		&self,
+		range: Range<DateTime<Utc>>,
+		matches: impl Fn(DateTime<Utc>, Option<&str>) -> bool,
+	) -> Vec<Vec<DateTime<Utc>>> {
+		let mut runs: Vec<Vec<DateTime<Utc>>> = vec![];
+		let mut previous: Option<(DateTime<Utc>, bool)> = None;
+		for (time, slot) in self.slots.range(range) {
+			let in_run = matches(*time, slot.as_deref());
+			if in_run {
+				match (previous, runs.last_mut()) {
+					(Some((previous, true)), Some(run)) if self.contiguous(previous, *time) => {
+						run.push(*time);
+					}
+					_ => runs.push(vec![*time]),
+				}
+			}
+			previous = Some((*time, in_run));
+		}
+		runs
+	}
+
+	/// Whether every block a task has been given is within its block size limits.
+	#[must_use]
+	pub fn blocks_ok(&self, id: &str) -> bool {
+		let Some(task) = self.tasks.get(id) else {
+			return true;
+		};
+		let (min, max) = self.block_limits(task);
+		if min == 1 && max == u64::MAX {
+			return true;
+		}
+		self.blocks(id)
+			.iter()
+			.all(|block| (min..=max).contains(&(block.len() as u64)))
+	}
+
+	/// How many slots in a row a task has through `time`, or 0 if `time` isn't one of its slots.
+	fn run_through(&self, id: &str, time: DateTime<Utc>) -> u64 {
+		if self.slots.get(&time).and_then(Option::as_deref) != Some(id) {
+			return 0;
+		}
+		let ours = |_, slot: Option<&str>| slot == Some(id);
+		let before = self.count_run(time, self.slots.range(..time).rev(), ours);
+		let after = self.count_run(
+			time,
+			self.slots.range((Bound::Excluded(time), Bound::Unbounded)),
+			ours,
+		);
+		before + 1 + after
+	}
+
+	/// Whether a task is still within its block size and daily limits after the slots at `times` changed hands.
+	/// Only the blocks next to those slots and the days they're on are checked, so this assumes everything else was already fine.
+	fn swap_ok(&self, id: &str, times: [DateTime<Utc>; 2]) -> bool {
+		let Some(task) = self.tasks.get(id) else {
+			return true;
+		};
+		let day_limit = self.day_limit(task);
+		if day_limit < u64::MAX {
+			for time in times {
+				let today = day(time);
+				let count = self
+					.slots
+					.range(time - TimeDelta::days(2)..time + TimeDelta::days(2))
+					.filter(|(slot_time, slot)| {
+						slot.as_deref() == Some(id) && day(**slot_time) == today
+					})
+					.count();
+				if count as u64 > day_limit {
+					return false;
+				}
+			}
+		}
+		let (min, max) = self.block_limits(task);
+		if min == 1 && max == u64::MAX {
+			return true;
+		}
+		times.into_iter().all(|time| {
+			let neighbours = [
+				self.slots.range(..time).next_back(),
+				self.slots
+					.range((Bound::Excluded(time), Bound::Unbounded))
+					.next(),
+			];
+			std::iter::once(time)
+				.chain(neighbours.into_iter().flatten().map(|(time, _)| *time))
+				.map(|time| self.run_through(id, time))
+				.all(|run| run == 0 || (min..=max).contains(&run))
+		})
+	}
+
+	/// Free up slots that are over a task's daily limit, blocks which are too short, and split up blocks which are too long.
+	fn repair(&mut self, tasks: &HashMap<String, (Arc<T>, AtomicI64)>) {
+		for (id, (task, wants_change)) in tasks {
+			let day_limit = self.day_limit(task);
+			if day_limit < u64::MAX {
+				let mut per_day: HashMap<NaiveDate, u64> = HashMap::new();
+				let over = self
+					.slots
+					.iter()
+					.filter(|(_, slot)| slot.as_deref() == Some(id.as_str()))
+					.map(|(time, _)| *time)
+					.filter(|time| {
+						let today = per_day.entry(day(*time)).or_default();
+						*today += 1;
+						*today > day_limit
+					})
+					.filter(|time| !self.pinned.contains(time))
+					.collect_vec();
+				for time in over {
+					self.slots.insert(time, None);
+					wants_change.fetch_add(1, Ordering::Relaxed);
+				}
+			}
+
+			let (min, max) = self.block_limits(task);
+			if min == 1 && max == u64::MAX {
+				continue;
+			}
+			let max = usize::try_from(max).unwrap_or(usize::MAX);
+			// Splitting a block can leave a piece that is too short, so keep going until nothing changes
+			loop {
+				let freed = self
+					.blocks(id)
+					.into_iter()
+					.flat_map(|block| {
+						if (block.len() as u64) < min {
+							block
+						} else {
+							// Free every (max + 1)th slot, so that no piece is longer than `max`
+							block
+								.into_iter()
+								.enumerate()
+								.filter(|(index, _)| index % max.saturating_add(1) == max)
+								.map(|(_, time)| time)
+								.collect()
+						}
+					})
+					.filter(|time| !self.pinned.contains(time))
+					.collect_vec();
+				if freed.is_empty() {
+					break;
+				}
+				for time in freed {
+					self.slots.insert(time, None);
+					wants_change.fetch_add(1, Ordering::Relaxed);
+				}
+			}
+		}
 	}
 
 	/// Shuffle tasks randomly, while still keeping every task in a slot within its working period.
+	/// Slots in a grace period can only move earlier, so shuffling never makes a task later.
+	/// Swaps that would break a task's block size or daily limits are undone, and pinned slots are left alone.
+	/// The same RNG state and schedule will always produce the same shuffle.
 	#[allow(clippy::missing_panics_doc)] // Should never actually panic
-	pub fn shuffle(&mut self) {
-		let mut rng = thread_rng();
+	pub fn shuffle(&mut self, rng: &mut impl Rng) {
 		let total_range = DateTime::<Utc>::MIN_UTC..DateTime::<Utc>::MAX_UTC;
+		let times = self.slots.keys().copied().collect_vec();
 
-		for index in 0..self.slots.len() {
-			let mut slots = self.slots.iter_mut();
-			let Some((l_time, left)) = slots.nth(index) else {
-				unreachable!()
-			};
+		for (index, l_time) in times.iter().enumerate() {
+			if self.pinned.contains(l_time) {
+				continue;
+			}
+			let left = self.slots[l_time].clone();
 			let range = left
 				.as_ref()
 				.map(|l| self.tasks[l.as_str()].working_period())
 				.unwrap_or(total_range.clone());
-			let mut candidates = [left]
-				.into_iter()
-				.chain(
-					slots
-						.take_while(|(time, _)| range.contains(time))
-						.filter(|(_, t)| {
-							t.as_ref().map_or(true, |t| {
-								self.tasks[t.as_str()].working_period().contains(l_time)
-							})
-						})
-						.map(|(_, right)| right),
-				)
+			let candidates = times[index + 1..]
+				.iter()
+				.take_while(|time| range.contains(time))
+				.filter(|time| !self.pinned.contains(*time))
+				.filter(|time| {
+					self.slots[*time]
+						.as_ref()
+						.is_none_or(|t| self.tasks[t.as_str()].allowed_period().contains(l_time))
+				})
 				.collect_vec();
-			if candidates.len() < 2 {
+			if candidates.is_empty() {
 				// If there's only one candidate, it's ourselves and it wouldn't make sense to swap
 				continue;
 			}
-			// Pick a slot to switch
-			let index = rng.gen_range(0..candidates.len());
-			// Set up references
-			let [left, rest @ ..] = &mut *candidates else {
-				// This pattern will never fail, but the compiler doesn't know it yet
-				unreachable!()
-			};
+			// Pick a slot to switch, where 0 is ourselves
+			let index = rng.gen_range(0..=candidates.len());
 			// if the index is 0, we've picked ourselves and it doesn't make sense to swap
-			if index != 0 {
-				std::mem::swap(
-					*left,
-					rest.get_mut(index.saturating_sub(1)).expect(
-						"Always in range because rest has a length one less than candidate",
-					),
-				);
+			let Some(r_time) = index.checked_sub(1).map(|index| candidates[index]) else {
+				continue;
+			};
+			let right = self.slots.insert(*r_time, left.clone()).flatten();
+			self.slots.insert(*l_time, right.clone());
+			if !left
+				.iter()
+				.chain(&right)
+				.all(|id| self.swap_ok(id, [*l_time, *r_time]))
+			{
+				self.slots.insert(*r_time, right);
+				self.slots.insert(*l_time, left);
 			}
 		}
 	}
 
+	/// Swap slots between tasks to raise the total fitness of every task in its slot, without breaking any of their constraints.
+	/// Like shuffling, this never moves a task later into its grace period, or touches pinned slots.
+	/// Returns how many swaps were made.
+	pub fn place_by(&mut self, fitness: impl Fn(&T, DateTime<Utc>) -> f64) -> usize {
+		let fit = |id: Option<&str>, time: DateTime<Utc>| {
+			id.and_then(|id| self.tasks.get(id))
+				.map_or(0.0, |task| fitness(task, time))
+		};
+		let times = self
+			.slots
+			.keys()
+			.filter(|time| !self.pinned.contains(time))
+			.copied()
+			.collect_vec();
+		let mut swaps = 0;
+		loop {
+			let mut improved = false;
+			for (index, l_time) in times.iter().enumerate() {
+				for r_time in &times[index + 1..] {
+					let left = self.slots[l_time].clone();
+					let right = self.slots[r_time].clone();
+					if left == right {
+						continue;
+					}
+					let gain = fit(right.as_deref(), *l_time) + fit(left.as_deref(), *r_time)
+						- fit(left.as_deref(), *l_time)
+						- fit(right.as_deref(), *r_time);
+					if gain <= f64::EPSILON {
+						continue;
+					}
+					let legal = left
+						.as_ref()
+						.is_none_or(|l| self.tasks[l].working_period().contains(r_time))
+						&& right
+							.as_ref()
+							.is_none_or(|r| self.tasks[r].allowed_period().contains(l_time));
+					if !legal {
+						continue;
+					}
+					self.slots.insert(*l_time, right.clone());
+					self.slots.insert(*r_time, left.clone());
+					if left
+						.iter()
+						.chain(&right)
+						.all(|id| self.swap_ok(id, [*l_time, *r_time]))
+					{
+						improved = true;
+						swaps += 1;
+					} else {
+						self.slots.insert(*l_time, left);
+						self.slots.insert(*r_time, right);
+					}
+				}
+			}
+			if !improved {
+				break;
+			}
+		}
+		swaps
+	}
+
 	#[cfg(test)]
 	pub(crate) fn check_times(&self) -> bool {
 		for (time, task) in &self.slots {
 			if let Some(task) = task {
-				if !self.tasks[task].working_period().contains(time) {
+				if !self.tasks[task].allowed_period().contains(time) {
 					return false;
 				}
 			}
 		}
-		true
+		self.tasks.keys().all(|id| self.constraints_ok(id))
 	}
 }
 
 #[cfg(test)]
 mod tests {
-	use super::{Schedule, Task};
-	use chrono::{DateTime, TimeZone, Utc};
+	use super::{day, Explanation, Fix, Outcome, Reason, Schedule, Task};
+	use chrono::{DateTime, Local, TimeZone, Utc};
 	use itertools::Itertools;
+	use rand::{rngs::StdRng, thread_rng, SeedableRng};
 	use serde::{Deserialize, Serialize};
-	use std::{collections::BTreeMap, ops::Range, time::Duration};
+	use std::{
+		collections::{BTreeMap, BTreeSet},
+		ops::Range,
+		time::Duration,
+	};
 
-	#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Hash)]
+	#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Hash, Clone)]
 	pub struct ExplicitTask {
 		pub priority: i64,
 		pub work_period: Range<DateTime<Utc>>,
 		pub length: Duration,
+		pub grace: Duration,
+		pub blocks: (u64, Option<u64>),
+		pub days: (Option<u64>, u64),
+	}
+
+	// A task with no limits, so that each test only has to set what it cares about
+	impl Default for ExplicitTask {
+		fn default() -> Self {
+			Self {
+				priority: 0,
+				work_period: DateTime::<Utc>::MIN_UTC..DateTime::<Utc>::MAX_UTC,
+				length: Duration::ZERO,
+				grace: Duration::ZERO,
+				blocks: (1, None),
+				days: (None, 1),
+			}
+		}
 	}
 
 	impl Task for ExplicitTask {
@@ -286,13 +1091,33 @@ mod tests {
 		fn estimated_length(&self) -> Duration {
 			self.length
 		}
+
+		fn grace_period(&self) -> Duration {
+			self.grace
+		}
+
+		fn min_block(&self) -> u64 {
+			self.blocks.0
+		}
+
+		fn max_block(&self) -> Option<u64> {
+			self.blocks.1
+		}
+
+		fn daily_cap(&self) -> Option<u64> {
+			self.days.0
+		}
+
+		fn min_days(&self) -> u64 {
+			self.days.1
+		}
 	}
 
 	#[test]
 	fn possible() {
 		let start = Utc.with_ymd_and_hms(2024, 3, 30, 0, 0, 0).unwrap();
 		let end = Utc.with_ymd_and_hms(2024, 3, 31, 0, 0, 0).unwrap();
-		let hour = Duration::from_secs(60 * 60);
+		let hour = Duration::from_hours(1);
 		let tasks = (1..9)
 			.map(|i| {
 				(
@@ -300,7 +1125,8 @@ mod tests {
 					ExplicitTask {
 						priority: i64::from(i),
 						work_period: (start + (hour * i))..(start + (hour * i * 3)),
-						length: Duration::from_secs(30 * 60),
+						length: Duration::from_mins(30),
+						..ExplicitTask::default()
 					}
 					.into(),
 				)
@@ -309,12 +1135,13 @@ mod tests {
 		let mut schedule = Schedule {
 			tasks,
 			slots: BTreeMap::default(),
-			timeslice_length: Duration::from_secs(25 * 60),
+			timeslice_length: Duration::from_mins(25),
+			pinned: BTreeSet::new(),
 		};
-		schedule.layout_slots(&(start..end), Duration::from_secs(30 * 60));
+		schedule.layout_slots(&(start..end), Duration::from_mins(30));
 
 		let failed = schedule.schedule();
-		schedule.shuffle();
+		schedule.shuffle(&mut thread_rng());
 		assert!(schedule.check_times());
 
 		assert!(failed.is_empty());
@@ -331,7 +1158,8 @@ mod tests {
 					ExplicitTask {
 						priority: i,
 						work_period: start..end,
-						length: Duration::from_secs(25 * 60),
+						length: Duration::from_mins(25),
+						..ExplicitTask::default()
 					}
 					.into(),
 				)
@@ -340,22 +1168,71 @@ mod tests {
 		let mut schedule = Schedule {
 			tasks: tasks.iter().cloned().collect(),
 			slots: BTreeMap::default(),
-			timeslice_length: Duration::from_secs(25 * 60),
+			timeslice_length: Duration::from_mins(25),
+			pinned: BTreeSet::new(),
 		};
-		schedule.layout_slots(&(start..end), Duration::from_secs(30 * 60));
+		schedule.layout_slots(&(start..end), Duration::from_mins(30));
 
 		let failed = schedule.schedule();
-		schedule.shuffle();
+		schedule.shuffle(&mut thread_rng());
 		assert!(schedule.check_times());
 
-		assert_eq!(failed.into_iter().collect_vec(), &["0".to_string()]);
+		assert_eq!(failed.into_keys().collect_vec(), &["0".to_string()]);
+	}
+
+	#[test]
+	fn steals_last_slot() {
+		let start = Utc.with_ymd_and_hms(2024, 3, 30, 9, 0, 0).unwrap();
+		let end = start + Duration::from_hours(1);
+		// The low-priority task has the shorter working period, so it claims both slots first
+		let tasks = [
+			(
+				"low".to_string(),
+				ExplicitTask {
+					priority: 0,
+					work_period: start..end,
+					length: Duration::from_mins(50),
+					..ExplicitTask::default()
+				}
+				.into(),
+			),
+			(
+				"high".to_string(),
+				ExplicitTask {
+					priority: 1,
+					work_period: start..end + Duration::from_hours(1),
+					length: Duration::from_mins(25),
+					..ExplicitTask::default()
+				}
+				.into(),
+			),
+		];
+		let mut schedule = Schedule {
+			tasks: tasks.iter().cloned().collect(),
+			slots: BTreeMap::default(),
+			timeslice_length: Duration::from_mins(25),
+			pinned: BTreeSet::new(),
+		};
+		schedule.layout_slots(&(start..end), Duration::from_mins(30));
+		assert_eq!(schedule.slots.len(), 2);
+
+		let failed = schedule.schedule();
+		let count = |id: &str| {
+			schedule
+				.slots
+				.values()
+				.filter(|slot| slot.as_deref() == Some(id))
+				.count()
+		};
+		assert_eq!((count("high"), count("low")), (1, 1));
+		assert_eq!(failed.into_keys().collect_vec(), ["low".to_string()]);
 	}
 
 	#[test]
 	fn check_starvation() {
 		let start = Utc.with_ymd_and_hms(2024, 3, 30, 0, 0, 0).unwrap();
 		let end = Utc.with_ymd_and_hms(2024, 3, 31, 0, 0, 0).unwrap();
-		let hour = Duration::from_secs(60 * 60);
+		let hour = Duration::from_hours(1);
 
 		let tasks = [
 			(
@@ -363,7 +1240,8 @@ mod tests {
 				ExplicitTask {
 					priority: 1,
 					work_period: (start + (hour * 4))..(start + (hour * 6)),
-					length: Duration::from_secs(60 * 60),
+					length: Duration::from_hours(1),
+					..ExplicitTask::default()
 				}
 				.into(),
 			),
@@ -372,7 +1250,8 @@ mod tests {
 				ExplicitTask {
 					priority: 9,
 					work_period: (start + (hour * 2))..(start + (hour * 23)),
-					length: Duration::from_secs(13 * 60 * 60),
+					length: Duration::from_hours(13),
+					..ExplicitTask::default()
 				}
 				.into(),
 			),
@@ -381,15 +1260,417 @@ mod tests {
 		let mut schedule = Schedule {
 			tasks: tasks.iter().cloned().collect(),
 			slots: BTreeMap::default(),
-			timeslice_length: Duration::from_secs(25 * 60),
+			timeslice_length: Duration::from_mins(25),
+			pinned: BTreeSet::new(),
 		};
-		schedule.layout_slots(&(start..end), Duration::from_secs(30 * 60));
+		schedule.layout_slots(&(start..end), Duration::from_mins(30));
 
 		let failed = schedule.schedule();
-		schedule.shuffle();
+		schedule.shuffle(&mut thread_rng());
 		assert!(schedule.check_times());
 
 		eprintln!("{failed:?}");
 		assert!(failed.is_empty());
 	}
+
+	#[test]
+	fn shuffle_is_reproducible() {
+		let start = Utc.with_ymd_and_hms(2024, 3, 30, 0, 0, 0).unwrap();
+		let end = Utc.with_ymd_and_hms(2024, 3, 31, 0, 0, 0).unwrap();
+		let hour = Duration::from_hours(1);
+		let tasks = (1..9)
+			.map(|i| {
+				(
+					i.to_string(),
+					ExplicitTask {
+						priority: i64::from(i),
+						work_period: (start + (hour * i))..(start + (hour * i * 3)),
+						length: Duration::from_mins(90),
+						..ExplicitTask::default()
+					}
+					.into(),
+				)
+			})
+			.collect();
+		let mut schedule = Schedule {
+			tasks,
+			slots: BTreeMap::default(),
+			timeslice_length: Duration::from_mins(25),
+			pinned: BTreeSet::new(),
+		};
+		schedule.layout_slots(&(start..end), Duration::from_mins(30));
+		schedule.schedule();
+
+		let mut left = schedule.clone();
+		let mut right = schedule.clone();
+		left.shuffle(&mut StdRng::seed_from_u64(1234));
+		right.shuffle(&mut StdRng::seed_from_u64(1234));
+		assert_eq!(left, right);
+		assert!(left.check_times());
+
+		right.shuffle(&mut StdRng::seed_from_u64(1234));
+		assert_ne!(left, right);
+	}
+
+	#[test]
+	fn grace_period() {
+		let start = Utc.with_ymd_and_hms(2024, 3, 30, 0, 0, 0).unwrap();
+		let end = Utc.with_ymd_and_hms(2024, 3, 31, 0, 0, 0).unwrap();
+		let hour = Duration::from_hours(1);
+		let task = |priority, grace| ExplicitTask {
+			priority,
+			work_period: start..(start + hour * 2),
+			length: Duration::from_mins(150),
+			grace,
+			..ExplicitTask::default()
+		};
+
+		for (grace, expected) in [
+			(
+				Duration::ZERO,
+				Outcome {
+					missing: 2,
+					late_slots: 0,
+					lateness: Duration::ZERO,
+					reason: Some(Reason::NotEnoughTime { available: 4 }),
+				},
+			),
+			(
+				hour * 2,
+				Outcome {
+					missing: 0,
+					late_slots: 2,
+					lateness: Duration::from_mins(55),
+					reason: None,
+				},
+			),
+		] {
+			let mut schedule = Schedule {
+				tasks: [("late".to_string(), task(1, grace).into())]
+					.into_iter()
+					.collect(),
+				slots: BTreeMap::default(),
+				timeslice_length: Duration::from_mins(25),
+				pinned: BTreeSet::new(),
+			};
+			schedule.layout_slots(&(start..end), Duration::from_mins(30));
+
+			let outcomes = schedule.schedule();
+			assert!(schedule.check_times());
+			assert_eq!(outcomes.get("late"), Some(&expected));
+
+			for _ in 0..16 {
+				schedule.shuffle(&mut thread_rng());
+				assert!(schedule.check_times());
+				assert!(schedule.outcomes()["late"].lateness <= expected.lateness);
+			}
+		}
+	}
+
+	#[test]
+	fn block_sizes() {
+		let start = Utc.with_ymd_and_hms(2024, 3, 30, 0, 0, 0).unwrap();
+		let end = Utc.with_ymd_and_hms(2024, 3, 31, 0, 0, 0).unwrap();
+		let hour = Duration::from_hours(1);
+		let tasks = [
+			(
+				"deep".to_string(),
+				ExplicitTask {
+					priority: 2,
+					work_period: start..(start + hour * 6),
+					length: Duration::from_mins(150),
+					blocks: (3, None),
+					..ExplicitTask::default()
+				}
+				.into(),
+			),
+			(
+				"email".to_string(),
+				ExplicitTask {
+					priority: 1,
+					work_period: start..end,
+					length: Duration::from_mins(100),
+					blocks: (1, Some(1)),
+					..ExplicitTask::default()
+				}
+				.into(),
+			),
+			(
+				"filler".to_string(),
+				ExplicitTask {
+					priority: 3,
+					work_period: start..(start + hour * 2),
+					length: Duration::from_mins(25),
+					..ExplicitTask::default()
+				}
+				.into(),
+			),
+		];
+		let mut schedule = Schedule {
+			tasks: tasks.iter().cloned().collect(),
+			slots: BTreeMap::default(),
+			timeslice_length: Duration::from_mins(25),
+			pinned: BTreeSet::new(),
+		};
+		// A gap every two hours splits up blocks
+		for hours in (0..24).step_by(2) {
+			let block_start = start + hour * hours;
+			schedule.layout_slots(
+				&(block_start..(block_start + hour + Duration::from_mins(30))),
+				Duration::from_mins(30),
+			);
+		}
+
+		let failed = schedule.schedule();
+		assert!(schedule.check_times());
+		assert!(failed.is_empty(), "{failed:?}");
+		assert!(schedule.blocks("deep").iter().all(|block| block.len() >= 3));
+		assert!(schedule
+			.blocks("email")
+			.iter()
+			.all(|block| block.len() == 1));
+
+		let mut rng = StdRng::seed_from_u64(29);
+		for _ in 0..64 {
+			schedule.shuffle(&mut rng);
+			assert!(schedule.check_times());
+		}
+	}
+
+	#[test]
+	fn daily_limits() {
+		let start = Utc.with_ymd_and_hms(2024, 3, 30, 0, 0, 0).unwrap();
+		let end = Utc.with_ymd_and_hms(2024, 4, 4, 0, 0, 0).unwrap();
+		let task = |length, days| ExplicitTask {
+			priority: 1,
+			work_period: start..end,
+			length: Duration::from_mins(25) * length,
+			days,
+			..ExplicitTask::default()
+		};
+		let tasks = [
+			("thesis".to_string(), task(12, (Some(4), 1)).into()),
+			("spread".to_string(), task(8, (None, 4)).into()),
+			("impossible".to_string(), task(10, (Some(1), 1)).into()),
+		];
+		let mut schedule = Schedule {
+			tasks: tasks.iter().cloned().collect(),
+			slots: BTreeMap::default(),
+			timeslice_length: Duration::from_mins(25),
+			pinned: BTreeSet::new(),
+		};
+		schedule.layout_slots(&(start..end), Duration::from_mins(30));
+
+		let failed = schedule.schedule();
+		assert!(schedule.check_times());
+		assert_eq!(failed.keys().collect_vec(), ["impossible"]);
+		assert!(matches!(
+			failed["impossible"].reason,
+			Some(Reason::DailyLimit { per_day: 1, .. })
+		));
+		assert!(schedule.slots_per_day("thesis").values().all(|n| *n <= 4));
+		assert!(schedule.slots_per_day("spread").len() >= 4);
+		assert_eq!(
+			schedule.slots_per_day("impossible").len(),
+			schedule
+				.slots
+				.keys()
+				.map(|time| day(*time))
+				.unique()
+				.count()
+		);
+
+		let mut rng = StdRng::seed_from_u64(30);
+		for _ in 0..16 {
+			schedule.shuffle(&mut rng);
+			assert!(schedule.check_times());
+		}
+	}
+
+	#[test]
+	fn uneven_spread() {
+		// Exactly four days, wherever the tests run
+		let start = Local
+			.with_ymd_and_hms(2024, 3, 30, 0, 0, 0)
+			.unwrap()
+			.with_timezone(&Utc);
+		let end = Local
+			.with_ymd_and_hms(2024, 4, 3, 0, 0, 0)
+			.unwrap()
+			.with_timezone(&Utc);
+		let mut schedule = Schedule {
+			tasks: [(
+				"essay".to_string(),
+				ExplicitTask {
+					priority: 1,
+					work_period: start..end,
+					length: Duration::from_mins(25) * 10,
+					days: (None, 4),
+					..ExplicitTask::default()
+				}
+				.into(),
+			)]
+			.into(),
+			slots: BTreeMap::default(),
+			timeslice_length: Duration::from_mins(25),
+			pinned: BTreeSet::new(),
+		};
+		schedule.layout_slots(&(start..end), Duration::from_mins(30));
+
+		// Ten slots over four days can't be split evenly, but 3, 3, 2 and 2 fits
+		assert!(schedule.schedule().is_empty());
+		let per_day = schedule.slots_per_day("essay");
+		assert_eq!(per_day.len(), 4);
+		assert!(per_day.values().all(|n| *n <= 3));
+		assert_eq!(per_day.values().sum::<u64>(), 10);
+	}
+
+	#[test]
+	fn place_by_fitness() {
+		let start = Utc.with_ymd_and_hms(2024, 3, 30, 0, 0, 0).unwrap();
+		let hour = Duration::from_hours(1);
+		let task = |priority| ExplicitTask {
+			priority,
+			work_period: start..(start + hour * 4),
+			length: Duration::from_mins(50),
+			..ExplicitTask::default()
+		};
+		let mut schedule = Schedule {
+			tasks: [
+				("hard".to_string(), task(2).into()),
+				("easy".to_string(), task(1).into()),
+			]
+			.into_iter()
+			.collect(),
+			slots: BTreeMap::default(),
+			timeslice_length: Duration::from_mins(25),
+			pinned: BTreeSet::new(),
+		};
+		schedule.layout_slots(&(start..(start + hour * 4)), Duration::from_mins(30));
+		schedule.schedule();
+
+		// Pretend that the end of the period is when there's the most energy, and "hard" needs it most
+		let peak = start + hour * 3;
+		schedule.place_by(|task, time| {
+			let energy = if time >= peak { 10.0 } else { 1.0 };
+			energy * f64::from(u8::try_from(task.priority).expect("Small priority"))
+		});
+		assert!(schedule.check_times());
+		assert_eq!(
+			schedule
+				.slots
+				.range(peak..)
+				.map(|(_, id)| id.as_deref())
+				.collect_vec(),
+			[Some("hard"), Some("hard")]
+		);
+	}
+
+	#[test]
+	fn explain() {
+		let start = Utc.with_ymd_and_hms(2024, 3, 30, 0, 0, 0).unwrap();
+		let hour = Duration::from_hours(1);
+		let task = |priority, length| ExplicitTask {
+			priority,
+			work_period: start..start + hour * 2,
+			length,
+			..ExplicitTask::default()
+		};
+		let mut schedule = Schedule {
+			tasks: [
+				("low".to_string(), task(1, Duration::from_mins(75)).into()),
+				("high".to_string(), task(5, Duration::from_mins(50)).into()),
+			]
+			.into(),
+			slots: BTreeMap::default(),
+			timeslice_length: Duration::from_mins(25),
+			pinned: BTreeSet::new(),
+		};
+		schedule.layout_slots(&(start..start + hour * 4), Duration::from_mins(30));
+		schedule.schedule();
+
+		assert_eq!(
+			schedule.explain("low"),
+			Some(Explanation {
+				wants: 3,
+				has: 2,
+				available: 4,
+				free: 0,
+				pinned: 0,
+				competitors: vec![("high".into(), 2)],
+				reason: Some(Reason::Crowded),
+				fixes: vec![
+					Fix::ExtendDeadline(Duration::from_mins(25)),
+					Fix::LowerEstimate(Duration::from_mins(25)),
+					Fix::RaisePriority {
+						above: "high".into()
+					},
+				],
+			})
+		);
+		let high = schedule.explain("high").expect("The task exists");
+		assert_eq!((high.wants, high.has), (2, 2));
+		assert!(high.competitors.is_empty());
+		assert!(high.fixes.is_empty());
+		assert_eq!(schedule.explain("none"), None);
+	}
+
+	#[test]
+	fn pinned_slots() {
+		let start = Utc.with_ymd_and_hms(2024, 3, 30, 9, 0, 0).unwrap();
+		let hour = Duration::from_hours(1);
+		let task = |priority, length| ExplicitTask {
+			priority,
+			work_period: start..start + hour * 4,
+			length,
+			..ExplicitTask::default()
+		};
+		let mut schedule = Schedule {
+			tasks: [
+				("low".to_string(), task(0, hour * 2).into()),
+				("high".to_string(), task(5, hour).into()),
+			]
+			.into_iter()
+			.collect(),
+			slots: BTreeMap::default(),
+			timeslice_length: Duration::from_mins(25),
+			pinned: BTreeSet::new(),
+		};
+		schedule.layout_slots(&(start..start + hour * 4), Duration::from_mins(30));
+		let report = start + hour;
+		assert!(schedule.pin(start, None));
+		assert!(schedule.pin(report, Some("low".into())));
+		assert!(!schedule.pin(start + Duration::from_mins(10), None));
+
+		// There's one slot too few, and the higher priority task still can't take the pinned one
+		let outcomes = schedule.schedule();
+		assert_eq!(outcomes["low"].missing, 1);
+		assert!(!outcomes.contains_key("high"));
+		let pinned_as_set = |schedule: &Schedule<ExplicitTask>| {
+			schedule.slots[&start].is_none() && schedule.slots[&report].as_deref() == Some("low")
+		};
+		assert!(pinned_as_set(&schedule));
+
+		let mut rng = StdRng::seed_from_u64(7);
+		for _ in 0..20 {
+			schedule.shuffle(&mut rng);
+			assert!(pinned_as_set(&schedule));
+		}
+		schedule.place_by(|task, time| {
+			if task.priority > 0 && time <= report {
+				1.0
+			} else {
+				0.0
+			}
+		});
+		assert!(pinned_as_set(&schedule));
+		assert_eq!(schedule.explain("low").map(|e| e.pinned), Some(1));
+
+		assert!(schedule.unpin(start));
+		assert!(!schedule.unpin(start));
+		schedule.schedule();
+		assert!(schedule.slots[&start].is_some());
+		schedule.remove_old_slots(start + hour * 2);
+		assert!(schedule.pinned.is_empty());
+	}
 }
@@ -0,0 +1,352 @@
+//! Where the database is kept between runs.
+//! The RON file is the original format; `SQLite` keeps tasks, slots and history in tables that other tools can query.
+
+use crate::db::{
+	migrations::{self, LoadError, CURRENT_VERSION},
+	Db,
+};
+use rustbreak::backend::{Backend as _, PathBackend};
+use serde::{Deserialize, Serialize};
+use std::{
+	fs::{File, OpenOptions},
+	hash::{DefaultHasher, Hash, Hasher},
+	io,
+	path::{Path, PathBuf},
+	str::FromStr,
+};
+use thiserror::Error;
+
+pub mod backup;
+mod sqlite;
+
+use backup::{Backup, BackupPolicy};
+pub use sqlite::Sqlite;
+
+/// Somewhere to keep the database.
+pub trait Storage {
+	/// Load the database, upgrading it from older layouts, or an empty database if nothing has been stored yet.
+	fn load(&mut self) -> Result<Db, StorageError>;
+	/// Store the database, replacing whatever was stored before.
+	fn save(&mut self, db: &Db) -> Result<(), StorageError>;
+	/// A token that changes whenever the stored database does, to notice when another process has saved it.
+	fn revision(&mut self) -> Result<u64, StorageError>;
+}
+
+#[derive(Error, Debug)]
+pub enum StorageError {
+	#[error(transparent)]
+	Load(#[from] LoadError),
+	#[error("Error writing database file: {0}")]
+	Write(#[from] rustbreak::error::BackendError),
+	#[error("Error serializing database: {0}")]
+	Serialize(#[from] ron::Error),
+	#[error("SQLite error: {0}")]
+	Sqlite(#[from] rusqlite::Error),
+	#[error("Database has a bad value for {0}")]
+	Corrupt(String),
+	#[error("Error locking database: {0}")]
+	Lock(#[from] io::Error),
+	#[error("Error backing up database: {0}")]
+	Backup(io::Error),
+}
+
+/// The kinds of storage that a database can be kept in.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+	/// A RON file, rewritten in full on every save.
+	Ron,
+	/// An `SQLite` database.
+	Sqlite,
+}
+
+#[derive(Error, Debug)]
+#[error("Unknown storage backend {0:?}, expected ron or sqlite")]
+pub struct UnknownBackend(String);
+
+impl FromStr for Backend {
+	type Err = UnknownBackend;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_ascii_lowercase().as_str() {
+			"ron" => Ok(Self::Ron),
+			"sqlite" => Ok(Self::Sqlite),
+			_ => Err(UnknownBackend(s.to_string())),
+		}
+	}
+}
+
+impl Backend {
+	/// Guess the backend from a path's extension: `.sqlite`, `.sqlite3` and `.db` are `SQLite`, and anything else is RON.
+	#[must_use]
+	pub fn for_path(path: &Path) -> Self {
+		match path.extension().and_then(|extension| extension.to_str()) {
+			Some("sqlite" | "sqlite3" | "db") => Self::Sqlite,
+			_ => Self::Ron,
+		}
+	}
+}
+
+/// Open the storage at a path, with the given backend or the one that suits its extension.
+pub fn open(path: &Path, backend: Option<Backend>) -> Result<Box<dyn Storage>, StorageError> {
+	Ok(match backend.unwrap_or_else(|| Backend::for_path(path)) {
+		Backend::Ron => Box::new(RonFile::new(path)),
+		Backend::Sqlite => Box::new(Sqlite::open(path)?),
+	})
+}
+
+/// A RON file holding a versioned [`migrations::Envelope`].
+/// Saving writes a temporary file next to it and renames it into place, so the file is never half-written.
+pub struct RonFile {
+	path: PathBuf,
+}
+
+/// The borrowed equivalent of [`migrations::Envelope`], to save without cloning the database.
+#[derive(Serialize)]
+struct Envelope<'a> {
+	version: u32,
+	db: &'a Db,
+}
+
+impl RonFile {
+	#[must_use]
+	pub fn new(path: &Path) -> Self {
+		Self {
+			path: path.to_path_buf(),
+		}
+	}
+}
+
+impl Storage for RonFile {
+	fn load(&mut self) -> Result<Db, StorageError> {
+		Ok(migrations::load(&self.path)?.db)
+	}
+
+	fn save(&mut self, db: &Db) -> Result<(), StorageError> {
+		let text = ron::ser::to_string_pretty(
+			&Envelope {
+				version: CURRENT_VERSION,
+				db,
+			},
+			ron::ser::PrettyConfig::default(),
+		)?;
+		let (mut backend, _) = PathBackend::from_path_or_create(self.path.clone())?;
+		backend.put_data(text.as_bytes())?;
+		Ok(())
+	}
+
+	fn revision(&mut self) -> Result<u64, StorageError> {
+		match std::fs::read(&self.path) {
+			Ok(bytes) => {
+				let mut hasher = DefaultHasher::new();
+				bytes.hash(&mut hasher);
+				Ok(hasher.finish())
+			}
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+			Err(e) => Err(LoadError::from(e).into()),
+		}
+	}
+}
+
+/// Storage that several processes can use at once, like the timer in one terminal and CLI commands in another.
+/// Loading and saving hold an advisory lock on `<path>.lock`,
+/// and saving merges in whatever other processes saved since this one last loaded or saved.
+pub struct Shared {
+	path: PathBuf,
+	backend: Backend,
+	storage: Box<dyn Storage>,
+	/// Which backups to keep, if the database is backed up before saving.
+	backups: Option<BackupPolicy>,
+	lock: File,
+	/// The database as of the last load or save, to merge against.
+	base: Db,
+	/// The revision of the stored database as of the last load or save.
+	revision: u64,
+}
+
+impl Shared {
+	/// Open the storage at a path, as for [`open`].
+	pub fn open(path: &Path, backend: Option<Backend>) -> Result<Self, StorageError> {
+		let mut lock_path = path.as_os_str().to_os_string();
+		lock_path.push(".lock");
+		let backend = backend.unwrap_or_else(|| Backend::for_path(path));
+		Ok(Self {
+			path: path.to_path_buf(),
+			backend,
+			storage: open(path, Some(backend))?,
+			backups: None,
+			lock: OpenOptions::new()
+				.create(true)
+				.truncate(false)
+				.write(true)
+				.open(lock_path)?,
+			base: Db::default(),
+			revision: 0,
+		})
+	}
+
+	/// Back up the stored database before each save, keeping the backups that the policy says to.
+	#[must_use]
+	pub fn with_backups(self, policy: BackupPolicy) -> Self {
+		Self {
+			backups: Some(policy),
+			..self
+		}
+	}
+
+	/// Load the database.
+	pub fn load(&mut self) -> Result<Db, StorageError> {
+		self.lock.lock_shared()?;
+		let loaded = self
+			.storage
+			.revision()
+			.and_then(|revision| Ok((revision, self.storage.load()?)));
+		self.lock.unlock()?;
+		let (revision, db) = loaded?;
+		self.revision = revision;
+		self.base = db.clone();
+		Ok(db)
+	}
+
+	/// Save the database, first merging in any changes that another process saved.
+	/// Returns whether there were changes to merge, in which case `db` now includes them.
+	pub fn save(&mut self, db: &mut Db) -> Result<bool, StorageError> {
+		self.lock.lock()?;
+		let saved = self.save_locked(db);
+		self.lock.unlock()?;
+		saved
+	}
+
+	fn save_locked(&mut self, db: &mut Db) -> Result<bool, StorageError> {
+		db.finish_operation();
+		let merged = self.storage.revision()? != self.revision;
+		if merged {
+			let theirs = self.storage.load()?;
+			*db = db.merge(&self.base, &theirs);
+			self.base = theirs;
+		}
+		if *db != self.base {
+			if let Some(policy) = &self.backups {
+				backup::take(&self.path)
+					.and_then(|_| backup::prune(&self.path, policy))
+					.map_err(StorageError::Backup)?;
+			}
+			self.storage.save(db)?;
+			self.base = db.clone();
+		}
+		self.revision = self.storage.revision()?;
+		Ok(merged)
+	}
+
+	/// The backups of the database, newest first.
+	pub fn backups(&self) -> Result<Vec<Backup>, StorageError> {
+		backup::list(&self.path).map_err(StorageError::Backup)
+	}
+
+	/// Load a backup without restoring it, to see what's in it.
+	pub fn load_backup(&self, backup: &Backup) -> Result<Db, StorageError> {
+		open(&backup.path, Some(self.backend))?.load()
+	}
+
+	/// Swap a backup in for the stored database, backing up the database it replaces first.
+	/// Returns the restored database, which other processes will merge into theirs when they next save.
+	pub fn restore(&mut self, backup: &Backup) -> Result<Db, StorageError> {
+		self.lock.lock()?;
+		let restored = self.restore_locked(backup);
+		self.lock.unlock()?;
+		restored
+	}
+
+	fn restore_locked(&mut self, backup: &Backup) -> Result<Db, StorageError> {
+		backup::take(&self.path)
+			.and_then(|_| backup::replace(&backup.path, &self.path))
+			.and_then(|()| backup::prune(&self.path, &self.backups.clone().unwrap_or_default()))
+			.map_err(StorageError::Backup)?;
+		// The old storage may still have the replaced file open
+		self.storage = open(&self.path, Some(self.backend))?;
+		self.revision = self.storage.revision()?;
+		self.base = self.storage.load()?;
+		Ok(self.base.clone())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{backup, Backend, BackupPolicy, Shared};
+	use crate::db::{migrations, CTask};
+	use std::sync::Arc;
+
+	/// Two processes that both add a task to the same database keep both tasks.
+	fn concurrent_edits(backend: Backend) {
+		let path = std::env::temp_dir().join(format!(
+			"pomeranian-shared-{}-{backend:?}",
+			std::process::id()
+		));
+		let _ = std::fs::remove_file(&path);
+		let text = include_str!("../tests/corpus/v1.ron");
+		let mut start = migrations::parse(text).expect("Valid database").db;
+		let mut first = Shared::open(&path, Some(backend)).expect("Opened");
+		first.load().expect("Loaded");
+		first.save(&mut start).expect("Saved");
+
+		let mut second = Shared::open(&path, Some(backend)).expect("Opened");
+		let mut ours = first.load().expect("Loaded");
+		let mut theirs = second.load().expect("Loaded");
+		let task = |name: &str| {
+			Arc::new(CTask {
+				name: name.into(),
+				..CTask::clone(&start.tasks["3f9a2c71b0d4e856"])
+			})
+		};
+		ours.schedule
+			.tasks
+			.insert("000000000000000a".into(), task("Ours"));
+		theirs
+			.schedule
+			.tasks
+			.insert("000000000000000b".into(), task("Theirs"));
+
+		assert!(!second.save(&mut theirs).expect("Saved"));
+		assert!(first.save(&mut ours).expect("Saved"));
+		assert_eq!(ours.tasks.len(), 4);
+		assert_eq!(second.load().expect("Loaded"), ours);
+		let _ = std::fs::remove_file(&path);
+	}
+
+	#[test]
+	fn restore_backup() {
+		let path = std::env::temp_dir().join(format!("pomeranian-backup-{}", std::process::id()));
+		let text = include_str!("../tests/corpus/v1.ron");
+		let start = migrations::parse(text).expect("Valid database").db;
+		let mut storage = Shared::open(&path, None)
+			.expect("Opened")
+			.with_backups(BackupPolicy::default());
+		storage.load().expect("Loaded");
+		assert!(storage.backups().expect("Listed").is_empty());
+
+		let mut db = start.clone();
+		storage.save(&mut db).expect("Saved");
+		db.schedule.tasks.clear();
+		storage.save(&mut db).expect("Saved");
+		let backups = storage.backups().expect("Listed");
+		assert_eq!(backups.len(), 1);
+		assert_eq!(storage.load_backup(&backups[0]).expect("Loaded"), start);
+
+		assert_eq!(storage.restore(&backups[0]).expect("Restored"), start);
+		assert_eq!(storage.load().expect("Loaded"), start);
+		// The database that was replaced is backed up too
+		assert_eq!(storage.backups().expect("Listed").len(), 2);
+		let _ = std::fs::remove_file(&path);
+		let _ = std::fs::remove_dir_all(backup::directory(&path));
+	}
+
+	#[test]
+	fn concurrent_ron() {
+		concurrent_edits(Backend::Ron);
+	}
+
+	#[test]
+	fn concurrent_sqlite() {
+		concurrent_edits(Backend::Sqlite);
+	}
+}
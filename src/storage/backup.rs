@@ -0,0 +1,177 @@
+//! Rotating copies of the stored database, taken before each save, so a bad write can be undone by restoring one.
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+	cmp::Reverse,
+	collections::BTreeSet,
+	ffi::OsString,
+	io,
+	path::{Path, PathBuf},
+};
+
+const TIME_FORMAT: &str = "%Y%m%d%H%M%S%3f";
+
+/// Which backups to keep, set in the `[backups]` table of the config file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct BackupPolicy {
+	/// How many of the most recent backups to keep.
+	pub keep_last: usize,
+	/// For how many days to also keep the last backup of each day.
+	pub daily_for: u32,
+}
+
+impl Default for BackupPolicy {
+	fn default() -> Self {
+		Self {
+			keep_last: 10,
+			daily_for: 7,
+		}
+	}
+}
+
+impl BackupPolicy {
+	/// Which of the backups, sorted newest first, the policy no longer keeps.
+	#[must_use]
+	pub fn expired<'a>(&self, backups: &'a [Backup], now: DateTime<Utc>) -> Vec<&'a Backup> {
+		let oldest_day = (now - TimeDelta::days(i64::from(self.daily_for)))
+			.with_timezone(&Local)
+			.date_naive();
+		let mut days = BTreeSet::new();
+		backups
+			.iter()
+			.enumerate()
+			.filter(|(index, backup)| {
+				let day = backup.time.with_timezone(&Local).date_naive();
+				let daily = day > oldest_day && days.insert(day);
+				!(*index < self.keep_last || daily)
+			})
+			.map(|(_, backup)| backup)
+			.collect()
+	}
+}
+
+/// A copy of the database from a point in time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Backup {
+	pub path: PathBuf,
+	/// When the copy was taken.
+	pub time: DateTime<Utc>,
+}
+
+/// The directory that a database's backups are kept in, `<path>.backups`.
+#[must_use]
+pub fn directory(path: &Path) -> PathBuf {
+	let mut name = path.as_os_str().to_os_string();
+	name.push(".backups");
+	PathBuf::from(name)
+}
+
+/// Every backup of the database at a path, newest first.
+pub fn list(path: &Path) -> io::Result<Vec<Backup>> {
+	let prefix = backup_prefix(path);
+	let mut backups = vec![];
+	let entries = match std::fs::read_dir(directory(path)) {
+		Ok(entries) => entries,
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(backups),
+		Err(e) => return Err(e),
+	};
+	for entry in entries {
+		let entry = entry?;
+		let name = entry.file_name();
+		let Some(time) = name
+			.to_str()
+			.and_then(|name| name.strip_prefix(prefix.to_str()?))
+			.and_then(|time| NaiveDateTime::parse_from_str(time, TIME_FORMAT).ok())
+		else {
+			continue;
+		};
+		backups.push(Backup {
+			path: entry.path(),
+			time: time.and_utc(),
+		});
+	}
+	backups.sort_by_key(|backup| Reverse(backup.time));
+	Ok(backups)
+}
+
+/// Copy the database at a path into its backups, if it exists.
+pub fn take(path: &Path) -> io::Result<Option<Backup>> {
+	if !path.exists() {
+		return Ok(None);
+	}
+	let directory = directory(path);
+	std::fs::create_dir_all(&directory)?;
+	let time = Utc::now();
+	let mut name = backup_prefix(path);
+	name.push(time.format(TIME_FORMAT).to_string());
+	let backup = Backup {
+		path: directory.join(name),
+		time,
+	};
+	replace(path, &backup.path)?;
+	Ok(Some(backup))
+}
+
+/// Delete the backups of the database at a path that the policy doesn't keep.
+pub fn prune(path: &Path, policy: &BackupPolicy) -> io::Result<()> {
+	for expired in policy.expired(&list(path)?, Utc::now()) {
+		std::fs::remove_file(&expired.path)?;
+	}
+	Ok(())
+}
+
+/// Copy `from` over `to` atomically, by copying it next to `to` and renaming the copy into place,
+/// so that `to` is never left half-written.
+pub fn replace(from: &Path, to: &Path) -> io::Result<()> {
+	let mut name = to.as_os_str().to_os_string();
+	name.push(".partial");
+	let partial = PathBuf::from(name);
+	std::fs::copy(from, &partial)?;
+	std::fs::rename(&partial, to)
+}
+
+fn backup_prefix(path: &Path) -> OsString {
+	let mut prefix = path.file_name().unwrap_or_default().to_os_string();
+	prefix.push(".");
+	prefix
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Backup, BackupPolicy};
+	use chrono::{Local, TimeDelta, TimeZone, Timelike, Utc};
+	use std::path::PathBuf;
+
+	#[test]
+	fn expire_backups() {
+		let now = Local
+			.with_ymd_and_hms(2024, 5, 20, 18, 0, 0)
+			.unwrap()
+			.with_timezone(&Utc);
+		// Three backups an hour apart on each of the last twenty days, newest first
+		let backups = (0..20)
+			.flat_map(|day| (0..3).map(move |hour| TimeDelta::days(day) + TimeDelta::hours(hour)))
+			.map(|age| Backup {
+				path: PathBuf::new(),
+				time: now - age,
+			})
+			.collect::<Vec<_>>();
+		let policy = BackupPolicy {
+			keep_last: 4,
+			daily_for: 7,
+		};
+		let expired = policy.expired(&backups, now);
+		let kept = backups
+			.iter()
+			.filter(|backup| !expired.contains(backup))
+			.collect::<Vec<_>>();
+		// The last four, and the newest of each of the five days before them
+		assert_eq!(kept.len(), 4 + 5);
+		assert_eq!(kept[..4], backups.iter().take(4).collect::<Vec<_>>());
+		assert!(kept[4..]
+			.iter()
+			.all(|backup| backup.time.with_timezone(&Local).hour() == 18));
+	}
+}
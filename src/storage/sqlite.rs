@@ -0,0 +1,501 @@
+//! Keeps the database in `SQLite`, with a table each for tasks, slots, pins, pomodoro states, work sessions and missed slots.
+//! Session history is only ever appended to, so saving doesn't get slower as it grows.
+
+use super::{Storage, StorageError};
+use crate::{
+	db::{
+		journal::Entry,
+		migrations::{self, CURRENT_VERSION},
+		CTask, Db, EnergyCurve, Session, SoftDeadline,
+	},
+	pomodoro::Pomodoro,
+};
+use chrono::{DateTime, NaiveTime, Utc};
+use itertools::Itertools;
+use rusqlite::{params, Connection, OptionalExtension, Row, Transaction};
+use std::{
+	collections::{BTreeMap, BTreeSet, HashMap},
+	path::Path,
+	str::FromStr,
+	sync::Arc,
+	time::Duration,
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS settings (
+	name TEXT PRIMARY KEY,
+	value TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS tasks (
+	id TEXT PRIMARY KEY,
+	name TEXT NOT NULL,
+	priority INTEGER NOT NULL,
+	start TEXT NOT NULL,
+	due TEXT NOT NULL,
+	estimated_secs REAL NOT NULL,
+	worked_secs REAL NOT NULL,
+	remote_id TEXT,
+	grace_secs REAL,
+	lateness_penalty INTEGER,
+	min_block INTEGER,
+	max_block INTEGER,
+	daily_cap INTEGER,
+	min_days INTEGER,
+	energy INTEGER,
+	project TEXT
+);
+CREATE TABLE IF NOT EXISTS task_tags (
+	task TEXT NOT NULL,
+	tag TEXT NOT NULL,
+	PRIMARY KEY (task, tag)
+);
+CREATE TABLE IF NOT EXISTS slots (
+	start TEXT PRIMARY KEY,
+	task TEXT
+);
+CREATE TABLE IF NOT EXISTS pinned (
+	start TEXT PRIMARY KEY
+);
+CREATE TABLE IF NOT EXISTS pomodoro_states (
+	start TEXT PRIMARY KEY,
+	end TEXT NOT NULL,
+	kind TEXT NOT NULL,
+	count INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS sessions (
+	task TEXT NOT NULL,
+	start TEXT NOT NULL,
+	end TEXT NOT NULL,
+	PRIMARY KEY (task, start)
+);
+CREATE TABLE IF NOT EXISTS missed (
+	task TEXT NOT NULL,
+	start TEXT NOT NULL,
+	end TEXT NOT NULL,
+	PRIMARY KEY (task, start)
+);
+CREATE TABLE IF NOT EXISTS journal (
+	stack TEXT NOT NULL,
+	position INTEGER NOT NULL,
+	operation TEXT NOT NULL,
+	time TEXT NOT NULL,
+	change TEXT NOT NULL,
+	PRIMARY KEY (stack, position)
+);
+";
+
+/// An `SQLite` database file.
+pub struct Sqlite {
+	connection: Connection,
+}
+
+impl Sqlite {
+	/// Open (or create) an `SQLite` database file.
+	pub fn open(path: &Path) -> Result<Self, StorageError> {
+		Ok(Self {
+			connection: Connection::open(path)?,
+		})
+	}
+
+	/// An `SQLite` database that only lives in memory, for testing.
+	pub fn in_memory() -> Result<Self, StorageError> {
+		Ok(Self {
+			connection: Connection::open_in_memory()?,
+		})
+	}
+
+	/// One stack of the journal, in order.
+	fn journal(&self, stack: &str) -> Result<Vec<Entry>, StorageError> {
+		let mut statement = self.connection.prepare(
+			"SELECT operation, time, change FROM journal WHERE stack = ?1 ORDER BY position",
+		)?;
+		let mut rows = statement.query([stack])?;
+		let mut entries = vec![];
+		while let Some(row) = rows.next()? {
+			entries.push(Entry {
+				operation: row.get(0)?,
+				time: row.get(1)?,
+				change: ron::from_str(&row.get::<_, String>(2)?)
+					.map_err(|_| StorageError::Corrupt("journal".into()))?,
+			});
+		}
+		Ok(entries)
+	}
+
+	/// The sessions kept in a table, oldest first.
+	fn sessions(&self, table: &str) -> Result<Vec<Session>, StorageError> {
+		Ok(self
+			.connection
+			.prepare(&format!(
+				"SELECT task, start, end FROM {table} ORDER BY start"
+			))?
+			.query_map([], |row| {
+				Ok(Session {
+					task: row.get(0)?,
+					period: row.get(1)?..row.get(2)?,
+				})
+			})?
+			.collect::<Result<_, _>>()?)
+	}
+
+	fn setting<T: FromStr>(&self, name: &str) -> Result<Option<T>, StorageError> {
+		self.connection
+			.query_row(
+				"SELECT value FROM settings WHERE name = ?1",
+				[name],
+				|row| row.get::<_, String>(0),
+			)
+			.optional()?
+			.map(|value| {
+				value
+					.parse()
+					.map_err(|_| StorageError::Corrupt(name.to_string()))
+			})
+			.transpose()
+	}
+}
+
+fn seconds(secs: f64) -> Duration {
+	Duration::from_secs_f64(secs.max(0.0))
+}
+
+fn task_from_row(row: &Row) -> rusqlite::Result<(String, CTask)> {
+	let grace: Option<f64> = row.get("grace_secs")?;
+	let penalty: Option<u32> = row.get("lateness_penalty")?;
+	Ok((
+		row.get("id")?,
+		CTask {
+			priority: row.get("priority")?,
+			working_period: row.get("start")?..row.get("due")?,
+			estimated_length: seconds(row.get("estimated_secs")?),
+			worked_length: seconds(row.get("worked_secs")?),
+			name: row.get("name")?,
+			remote_id: row.get("remote_id")?,
+			soft_deadline: grace.map(|grace| SoftDeadline {
+				grace_period: seconds(grace),
+				penalty: penalty.unwrap_or(0),
+			}),
+			min_block: row.get("min_block")?,
+			max_block: row.get("max_block")?,
+			daily_cap: row.get("daily_cap")?,
+			min_days: row.get("min_days")?,
+			energy: row.get("energy")?,
+			project: row.get("project")?,
+			tags: BTreeSet::default(),
+		},
+	))
+}
+
+fn pomodoro_name(pomodoro: Pomodoro) -> (&'static str, u32) {
+	match pomodoro {
+		Pomodoro::Work(n) => ("work", n),
+		Pomodoro::Break(n) => ("break", n),
+		Pomodoro::LongBreak => ("long_break", 0),
+	}
+}
+
+impl Storage for Sqlite {
+	fn load(&mut self) -> Result<Db, StorageError> {
+		self.connection.execute_batch(SCHEMA)?;
+		let Some(version) = self.setting::<u32>("version")? else {
+			return Ok(Db::default());
+		};
+		let defaults = Db::default();
+		let mut db = Db {
+			active_period: self
+				.setting::<NaiveTime>("active_period_start")?
+				.unwrap_or(defaults.active_period.start)
+				..self
+					.setting::<NaiveTime>("active_period_end")?
+					.unwrap_or(defaults.active_period.end),
+			break_interval: self
+				.setting("break_interval")?
+				.unwrap_or(defaults.break_interval),
+			short_break: self
+				.setting("short_break_secs")?
+				.map_or(defaults.short_break, seconds),
+			long_break: self
+				.setting("long_break_secs")?
+				.map_or(defaults.long_break, seconds),
+			last_shuffle: self
+				.setting::<String>("last_shuffle")?
+				.map(|record| {
+					ron::from_str(&record).map_err(|_| StorageError::Corrupt("last_shuffle".into()))
+				})
+				.transpose()?,
+			energy_curve: match self.setting::<String>("energy_curve")? {
+				Some(curve) => EnergyCurve(
+					curve
+						.split(',')
+						.map(str::parse)
+						.collect::<Result<Vec<u8>, _>>()
+						.ok()
+						.and_then(|levels| levels.try_into().ok())
+						.ok_or_else(|| StorageError::Corrupt("energy_curve".into()))?,
+				),
+				None => EnergyCurve::default(),
+			},
+			..defaults
+		};
+		if let Some(length) = self.setting("timeslice_secs")? {
+			db.schedule.timeslice_length = seconds(length);
+		}
+
+		let mut tasks: HashMap<String, CTask> = self
+			.connection
+			.prepare("SELECT * FROM tasks")?
+			.query_map([], task_from_row)?
+			.collect::<Result<_, _>>()?;
+		let mut statement = self.connection.prepare("SELECT task, tag FROM task_tags")?;
+		let mut rows = statement.query([])?;
+		while let Some(row) = rows.next()? {
+			if let Some(task) = tasks.get_mut(&row.get::<_, String>(0)?) {
+				task.tags.insert(row.get(1)?);
+			}
+		}
+		db.schedule.tasks = tasks
+			.into_iter()
+			.map(|(id, task)| (id, Arc::new(task)))
+			.collect();
+
+		db.schedule.slots = self
+			.connection
+			.prepare("SELECT start, task FROM slots")?
+			.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+			.collect::<Result<BTreeMap<_, _>, _>>()?;
+		db.schedule.pinned = self
+			.connection
+			.prepare("SELECT start FROM pinned")?
+			.query_map([], |row| row.get(0))?
+			.collect::<Result<BTreeSet<_>, _>>()?;
+
+		let mut statement = self
+			.connection
+			.prepare("SELECT start, end, kind, count FROM pomodoro_states ORDER BY start")?;
+		let mut rows = statement.query([])?;
+		while let Some(row) = rows.next()? {
+			let count = row.get(3)?;
+			let state = match row.get::<_, String>(2)?.as_str() {
+				"work" => Pomodoro::Work(count),
+				"break" => Pomodoro::Break(count),
+				"long_break" => Pomodoro::LongBreak,
+				_ => return Err(StorageError::Corrupt("pomodoro_states".into())),
+			};
+			db.pomodoro_states.push((row.get(0)?..row.get(1)?, state));
+		}
+
+		db.history = self.sessions("sessions")?;
+		db.missed = self.sessions("missed")?;
+		db.journal.undo = self.journal("undo")?.into();
+		db.journal.redo = self.journal("redo")?;
+
+		migrations::upgrade(&mut db, version)?;
+		Ok(db)
+	}
+
+	fn save(&mut self, db: &Db) -> Result<(), StorageError> {
+		self.connection.execute_batch(SCHEMA)?;
+		let transaction = self.connection.transaction()?;
+
+		let revision = transaction
+			.query_row(
+				"SELECT value FROM settings WHERE name = 'revision'",
+				[],
+				|row| row.get::<_, String>(0),
+			)
+			.optional()?
+			.and_then(|revision| revision.parse::<u64>().ok())
+			.unwrap_or(0);
+		let settings = [
+			("version", CURRENT_VERSION.to_string()),
+			("revision", (revision + 1).to_string()),
+			("active_period_start", db.active_period.start.to_string()),
+			("active_period_end", db.active_period.end.to_string()),
+			("break_interval", db.break_interval.to_string()),
+			("short_break_secs", db.short_break.as_secs_f64().to_string()),
+			("long_break_secs", db.long_break.as_secs_f64().to_string()),
+			(
+				"timeslice_secs",
+				db.schedule.timeslice_length.as_secs_f64().to_string(),
+			),
+			("energy_curve", db.energy_curve.0.iter().join(",")),
+		];
+		for (name, value) in settings {
+			transaction.execute(
+				"INSERT OR REPLACE INTO settings (name, value) VALUES (?1, ?2)",
+				params![name, value],
+			)?;
+		}
+		match &db.last_shuffle {
+			Some(record) => transaction.execute(
+				"INSERT OR REPLACE INTO settings (name, value) VALUES ('last_shuffle', ?1)",
+				[ron::to_string(record)?],
+			)?,
+			None => transaction.execute("DELETE FROM settings WHERE name = 'last_shuffle'", [])?,
+		};
+
+		transaction.execute_batch(
+			"DELETE FROM tasks; DELETE FROM task_tags; DELETE FROM slots; DELETE FROM pinned; DELETE FROM pomodoro_states;",
+		)?;
+		for (id, task) in &db.schedule.tasks {
+			insert_task(&transaction, id, task)?;
+		}
+		for (start, task) in &db.schedule.slots {
+			transaction.execute(
+				"INSERT INTO slots (start, task) VALUES (?1, ?2)",
+				params![start, task],
+			)?;
+		}
+		for start in &db.schedule.pinned {
+			transaction.execute("INSERT INTO pinned (start) VALUES (?1)", [start])?;
+		}
+		for (period, state) in &db.pomodoro_states {
+			let (kind, count) = pomodoro_name(*state);
+			transaction.execute(
+				"INSERT INTO pomodoro_states (start, end, kind, count) VALUES (?1, ?2, ?3, ?4)",
+				params![period.start, period.end, kind, count],
+			)?;
+		}
+
+		save_history(&transaction, &db.history)?;
+		transaction.execute("DELETE FROM missed", [])?;
+		insert_sessions(&transaction, "missed", &db.missed)?;
+		transaction.execute("DELETE FROM journal", [])?;
+		for (stack, entries) in [
+			("undo", db.journal.undo.iter().collect_vec()),
+			("redo", db.journal.redo.iter().collect_vec()),
+		] {
+			for (position, entry) in entries.into_iter().enumerate() {
+				transaction.execute(
+					"INSERT INTO journal (stack, position, operation, time, change) VALUES (?1, ?2, ?3, ?4, ?5)",
+					params![stack, position, entry.operation, entry.time, ron::to_string(&entry.change)?],
+				)?;
+			}
+		}
+
+		transaction.commit()?;
+		Ok(())
+	}
+
+	fn revision(&mut self) -> Result<u64, StorageError> {
+		self.connection.execute_batch(SCHEMA)?;
+		Ok(self.setting("revision")?.unwrap_or(0))
+	}
+}
+
+fn insert_task(transaction: &Transaction, id: &str, task: &CTask) -> Result<(), StorageError> {
+	transaction.execute(
+		"INSERT INTO tasks (id, name, priority, start, due, estimated_secs, worked_secs, remote_id, grace_secs, lateness_penalty, min_block, max_block, daily_cap, min_days, energy, project)
+		VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+		params![
+			id,
+			task.name,
+			task.priority,
+			task.working_period.start,
+			task.working_period.end,
+			task.estimated_length.as_secs_f64(),
+			task.worked_length.as_secs_f64(),
+			task.remote_id,
+			task.soft_deadline.map(|d| d.grace_period.as_secs_f64()),
+			task.soft_deadline.map(|d| d.penalty),
+			task.min_block,
+			task.max_block,
+			task.daily_cap,
+			task.min_days,
+			task.energy,
+			task.project,
+		],
+	)?;
+	for tag in &task.tags {
+		transaction.execute(
+			"INSERT INTO task_tags (task, tag) VALUES (?1, ?2)",
+			params![id, tag],
+		)?;
+	}
+	Ok(())
+}
+
+/// History is append-only, so only write sessions newer than the newest one stored,
+/// unless the stored history isn't the start of the history any more.
+fn save_history(transaction: &Transaction, history: &[Session]) -> Result<(), StorageError> {
+	let stored: usize =
+		transaction.query_row("SELECT COUNT(*) FROM sessions", [], |row| row.get(0))?;
+	let newest: Option<DateTime<Utc>> =
+		transaction.query_row("SELECT MAX(start) FROM sessions", [], |row| row.get(0))?;
+	let saved = newest.map_or(0, |newest| {
+		history.partition_point(|session| session.period.start <= newest)
+	});
+	let unsaved = if saved == stored {
+		saved
+	} else {
+		transaction.execute("DELETE FROM sessions", [])?;
+		0
+	};
+	insert_sessions(transaction, "sessions", &history[unsaved..])
+}
+
+fn insert_sessions(
+	transaction: &Transaction,
+	table: &str,
+	sessions: &[Session],
+) -> Result<(), StorageError> {
+	for session in sessions {
+		transaction.execute(
+			&format!("INSERT OR REPLACE INTO {table} (task, start, end) VALUES (?1, ?2, ?3)"),
+			params![session.task, session.period.start, session.period.end],
+		)?;
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Sqlite;
+	use crate::{
+		db::{migrations, Session},
+		pomodoro::Pomodoro,
+		storage::Storage,
+	};
+	use chrono::{TimeZone, Utc};
+	use std::time::Duration;
+
+	#[test]
+	fn round_trip() {
+		let text = include_str!("../../tests/corpus/v1.ron");
+		let mut db = migrations::parse(text).expect("Valid database").db;
+		let start = Utc.with_ymd_and_hms(2024, 4, 1, 9, 0, 0).unwrap();
+		db.pomodoro_states
+			.push((start..start + Duration::from_mins(25), Pomodoro::Work(3)));
+		let slot = *db.slots.keys().next().expect("A slot");
+		db.schedule.pin(slot, None);
+		let task = db.tasks.keys().next().expect("A task").clone();
+		db.history.push(Session {
+			task,
+			period: start..start + Duration::from_mins(20),
+		});
+		db.missed.push(Session {
+			task: db.tasks.keys().nth(1).expect("Another task").clone(),
+			period: start - Duration::from_mins(25)..start,
+		});
+		db.record("Remove a task");
+		db.remove_task(&db.tasks.keys().next().expect("A task").clone());
+		db.record("Remove another task");
+		db.undo();
+
+		let mut storage = Sqlite::in_memory().expect("SQLite");
+		storage.save(&db).expect("Saved");
+		assert_eq!(storage.load().expect("Loaded"), db);
+
+		// Saving again only appends new history, and notices history that's gone
+		let first = db.history[0].clone();
+		db.history.push(Session {
+			period: first.period.end..first.period.end + Duration::from_mins(5),
+			..first
+		});
+		storage.save(&db).expect("Saved");
+		assert_eq!(storage.load().expect("Loaded"), db);
+		db.history.clear();
+		storage.save(&db).expect("Saved");
+		assert_eq!(storage.load().expect("Loaded"), db);
+	}
+}
@@ -0,0 +1,405 @@
+//! Goals for shuffling the schedule.
+//! Each goal is a [`Metric`], and a [`Strategy`] is a weighted sum of metrics which shuffling tries to maximize.
+
+use crate::{
+	db::{CTask, Db, EnergyCurve},
+	scheduler::Schedule,
+};
+use chrono::{Local, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use thiserror::Error;
+
+/// A named measurement of a schedule.
+#[derive(Clone, Copy, Debug)]
+pub struct Metric {
+	/// The name that strategies use to refer to this metric.
+	pub name: &'static str,
+	/// What this metric measures, and what maximizing it does.
+	pub description: &'static str,
+	/// Measure a schedule.
+	pub measure: fn(&Schedule<CTask>, &Context) -> f64,
+}
+
+/// What metrics know about the user, besides the schedule itself.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Context {
+	/// How much energy the user has at each time of day.
+	pub energy_curve: EnergyCurve,
+}
+
+impl From<&Db> for Context {
+	fn from(db: &Db) -> Self {
+		Self {
+			energy_curve: db.energy_curve,
+		}
+	}
+}
+
+/// Every metric that strategies can be built from.
+pub const METRICS: [Metric; 7] = [
+	Metric {
+		name: "small_victories",
+		description: "Average seconds from now until each task's last slot. Minimize it to finish tasks as soon as possible.",
+		measure: small_victories,
+	},
+	Metric {
+		name: "early_riser",
+		description: "Average seconds from now until each free slot. Maximize it to get work out of the way first.",
+		measure: early_riser,
+	},
+	Metric {
+		name: "explosive",
+		description: "Average length of a run of free slots. Maximize it to bunch work together, minimize it to spread work out.",
+		measure: explosive,
+	},
+	Metric {
+		name: "hyperfocus",
+		description: "Average length of a run of slots for the same task. Maximize it to avoid switching between tasks.",
+		measure: hyperfocus,
+	},
+	Metric {
+		name: "lateness",
+		description: "Hours that tasks will finish after their due dates, weighted by each task's lateness penalty. Minimize it to catch up on late tasks.",
+		measure: lateness,
+	},
+	Metric {
+		name: "energy",
+		description: "Average of each slot's energy times the energy its task needs. Maximize it to do demanding work when you have the most energy.",
+		measure: energy,
+	},
+	Metric {
+		name: "context_switches",
+		description: "How many times in a day work moves between tasks with no context (`@` tag) in common. Minimize it to batch work by context.",
+		measure: context_switches,
+	},
+];
+
+/// Look up a metric by name.
+#[must_use]
+pub fn metric(name: &str) -> Option<&'static Metric> {
+	METRICS.iter().find(|metric| metric.name == name)
+}
+
+/// Measure a schedule with every metric.
+#[must_use]
+pub fn measure_all(schedule: &Schedule<CTask>, context: &Context) -> Vec<(&'static str, f64)> {
+	METRICS
+		.iter()
+		.map(|metric| (metric.name, (metric.measure)(schedule, context)))
+		.collect()
+}
+
+/// A weighted combination of metrics. Shuffling for a strategy maximizes the sum of each metric times its weight.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Strategy {
+	/// The human-friendly name of this strategy.
+	pub name: String,
+	/// What this strategy is for.
+	#[serde(default)]
+	pub description: String,
+	/// The weight of each metric, by metric name. Negative weights minimize a metric.
+	pub weights: BTreeMap<String, f64>,
+}
+
+#[derive(Error, Debug)]
+pub enum StrategyError {
+	#[error("Strategy {strategy:?} uses unknown metric {metric:?}")]
+	UnknownMetric { strategy: String, metric: String },
+	#[error("Strategy {0:?} doesn't weigh any metrics")]
+	Empty(String),
+}
+
+impl Strategy {
+	fn single(name: &str, description: &str, metric: &str, weight: f64) -> Self {
+		Self {
+			name: name.to_string(),
+			description: description.to_string(),
+			weights: BTreeMap::from([(metric.to_string(), weight)]),
+		}
+	}
+
+	/// The strategies that are always available.
+	#[must_use]
+	pub fn builtin() -> Vec<Self> {
+		vec![
+			Self::single(
+				"Small Victories",
+				"Finish tasks as soon as possible",
+				"small_victories",
+				-1.0,
+			),
+			Self::single(
+				"Procrastinator",
+				"Finish tasks as late as possible",
+				"small_victories",
+				1.0,
+			),
+			Self::single("Early Riser", "Work first, relax later", "early_riser", 1.0),
+			Self::single(
+				"Problem for Future Me",
+				"Relax first, work later",
+				"early_riser",
+				-1.0,
+			),
+			Self::single("PWM", "Spread work out evenly", "explosive", -1.0),
+			Self::single("Explosive", "Bunch work together", "explosive", 1.0),
+			Self::single(
+				"Context Switch",
+				"Switch tasks as often as possible",
+				"hyperfocus",
+				-1.0,
+			),
+			Self::single(
+				"Hyperfocus",
+				"Stay on one task as long as possible",
+				"hyperfocus",
+				1.0,
+			),
+			Self::single(
+				"Energized",
+				"Do demanding work when you have the most energy",
+				"energy",
+				1.0,
+			),
+			Self::single(
+				"Batch by Context",
+				"Do everything in one place before moving to the next",
+				"context_switches",
+				-1.0,
+			),
+		]
+	}
+
+	/// Make sure that every metric this strategy uses exists.
+	pub fn validate(&self) -> Result<(), StrategyError> {
+		if self.weights.is_empty() {
+			return Err(StrategyError::Empty(self.name.clone()));
+		}
+		match self.weights.keys().find(|name| metric(name).is_none()) {
+			Some(unknown) => Err(StrategyError::UnknownMetric {
+				strategy: self.name.clone(),
+				metric: unknown.clone(),
+			}),
+			None => Ok(()),
+		}
+	}
+
+	/// Score a schedule under this strategy. Unknown metrics are ignored.
+	#[must_use]
+	pub fn score(&self, schedule: &Schedule<CTask>, context: &Context) -> f64 {
+		self.weights
+			.iter()
+			.filter_map(|(name, weight)| metric(name).map(|metric| (metric, weight)))
+			.map(|(metric, weight)| weight * (metric.measure)(schedule, context))
+			.sum()
+	}
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn small_victories(sched: &Schedule<CTask>, _: &Context) -> f64 {
+	let ttc = sched
+		.tasks
+		.keys()
+		.filter_map(|id| {
+			sched
+				.slots
+				.iter()
+				.filter(|(_, task_)| task_.as_ref() == Some(id))
+				.map(|(time, _)| *time - Utc::now())
+				.map(|t| t.num_seconds())
+				.max()
+		})
+		.collect::<Vec<_>>();
+	(ttc.iter().copied().sum::<i64>() as f64) / (ttc.len() as f64)
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn early_riser(sched: &Schedule<CTask>, _: &Context) -> f64 {
+	let ttb = sched
+		.slots
+		.iter()
+		.filter(|(_, slot)| slot.is_none())
+		.map(|(t, _)| *t - Utc::now())
+		.map(|d| d.num_seconds())
+		.collect::<Vec<_>>();
+
+	(ttb.iter().copied().sum::<i64>() as f64) / (ttb.len() as f64)
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn explosive(sched: &Schedule<CTask>, _: &Context) -> f64 {
+	let mut lengths = vec![];
+	let mut in_combo = false;
+	for slot in sched.slots.values().map(Option::is_some) {
+		match (slot, in_combo) {
+			(false, true) => {
+				*lengths.last_mut().expect(
+					"We can only enter a combo after pushing to the list, so this can't fail.",
+				) += 1;
+			}
+			(false, false) => {
+				lengths.push(1);
+				in_combo = true;
+			}
+			(true, _) => in_combo = false,
+		}
+	}
+
+	f64::from(lengths.iter().copied().sum::<u32>()) / (lengths.len() as f64)
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn hyperfocus(sched: &Schedule<CTask>, _: &Context) -> f64 {
+	let mut combos = vec![];
+	let mut current = None;
+	for task in sched.slots.values() {
+		match (task, current) {
+			(Some(task), Some(c)) if task == c => {
+				*combos.last_mut().expect(
+					"We can only enter a combo after pushing to the list, so this can't fail.",
+				) += 1;
+			}
+			(Some(task), _) => {
+				current = Some(task);
+				combos.push(1);
+			}
+			(None, _) => {
+				current = None;
+			}
+		}
+	}
+
+	f64::from(combos.iter().copied().sum::<u32>()) / (combos.len() as f64)
+}
+
+fn lateness(sched: &Schedule<CTask>, _: &Context) -> f64 {
+	sched
+		.outcomes()
+		.into_iter()
+		.map(|(id, outcome)| {
+			let penalty = sched.tasks[&id]
+				.soft_deadline
+				.map_or(0, |deadline| deadline.penalty);
+			f64::from(penalty) * outcome.lateness.as_secs_f64() / (60.0 * 60.0)
+		})
+		.sum()
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn energy(sched: &Schedule<CTask>, context: &Context) -> f64 {
+	let matches = sched
+		.slots
+		.iter()
+		.filter_map(|(time, id)| Some((time, sched.tasks.get(id.as_ref()?)?)))
+		.map(|(time, task)| {
+			f64::from(task.energy.unwrap_or(0)) * f64::from(context.energy_curve.at(*time))
+		})
+		.collect::<Vec<_>>();
+	if matches.is_empty() {
+		return 0.0;
+	}
+	matches.iter().sum::<f64>() / (matches.len() as f64)
+}
+
+fn context_switches(sched: &Schedule<CTask>, _: &Context) -> f64 {
+	let mut switches = 0;
+	let mut previous: Option<(NaiveDate, BTreeSet<&str>)> = None;
+	for (time, id) in &sched.slots {
+		let Some(task) = id.as_ref().and_then(|id| sched.tasks.get(id)) else {
+			continue;
+		};
+		let contexts: BTreeSet<_> = task.contexts().collect();
+		if contexts.is_empty() {
+			continue;
+		}
+		let day = time.with_timezone(&Local).date_naive();
+		if let Some((previous_day, previous_contexts)) = &previous {
+			if *previous_day == day && previous_contexts.is_disjoint(&contexts) {
+				switches += 1;
+			}
+		}
+		previous = Some((day, contexts));
+	}
+	f64::from(switches)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{context_switches, explosive, hyperfocus, Context, Strategy};
+	use crate::db::{CTask, Db};
+	use chrono::{TimeZone, Utc};
+	use std::{collections::BTreeMap, time::Duration};
+
+	#[test]
+	fn weighted_sum() {
+		let mut db = Db::default();
+		let start = Utc.with_ymd_and_hms(2024, 3, 30, 0, 0, 0).unwrap();
+		db.schedule.layout_slots(
+			&(start..start + Duration::from_hours(4)),
+			Duration::from_mins(30),
+		);
+		for (index, slot) in db.schedule.slots.values_mut().enumerate() {
+			if index % 3 == 0 {
+				*slot = Some("a".into());
+			}
+		}
+		let strategy = Strategy {
+			name: "Mixed".into(),
+			description: String::new(),
+			weights: BTreeMap::from([("hyperfocus".into(), 0.7), ("explosive".into(), -0.3)]),
+		};
+		strategy.validate().expect("Valid strategy");
+		let context = Context::from(&db);
+		let expected =
+			0.7 * hyperfocus(&db.schedule, &context) - 0.3 * explosive(&db.schedule, &context);
+		assert!((strategy.score(&db.schedule, &context) - expected).abs() < f64::EPSILON);
+	}
+
+	#[test]
+	fn batch_by_context() {
+		let mut db = Db::default();
+		let start = Utc.with_ymd_and_hms(2024, 3, 30, 12, 0, 0).unwrap();
+		for (id, context) in [
+			("essay", "@computer"),
+			("book", "@library"),
+			("code", "@computer"),
+		] {
+			let task = CTask {
+				tags: [context.to_string()].into(),
+				..CTask::new(
+					id.into(),
+					start..start + Duration::from_hours(2),
+					Duration::from_mins(25),
+				)
+			};
+			db.schedule.tasks.insert(id.into(), task.into());
+		}
+		db.schedule.layout_slots(
+			&(start..start + Duration::from_mins(90)),
+			Duration::from_mins(30),
+		);
+		let context = Context::from(&db);
+		let mut assign = |order: [&str; 3]| {
+			for (slot, id) in db.schedule.slots.values_mut().zip(order) {
+				*slot = Some(id.into());
+			}
+			context_switches(&db.schedule, &context)
+		};
+		assert!((assign(["essay", "book", "code"]) - 2.0).abs() < f64::EPSILON);
+		assert!((assign(["essay", "code", "book"]) - 1.0).abs() < f64::EPSILON);
+	}
+
+	#[test]
+	fn unknown_metrics_are_rejected() {
+		let strategy = Strategy {
+			name: "Typo".into(),
+			description: String::new(),
+			weights: BTreeMap::from([("hyperfocsu".into(), 1.0)]),
+		};
+		assert!(strategy.validate().is_err());
+		for builtin in Strategy::builtin() {
+			builtin.validate().expect("Valid built-in strategy");
+		}
+	}
+}
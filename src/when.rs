@@ -0,0 +1,264 @@
+//! Reading times the way people write them, like "tomorrow 17:00", "friday", "in 3 days" or "end of month".
+
+use chrono::{
+	DateTime, Datelike, Duration as TimeDelta, Months, NaiveDate, NaiveDateTime, NaiveTime,
+	TimeZone, Timelike, Weekday,
+};
+use thiserror::Error;
+
+/// The format that times are written in when they're shown for editing, which [`parse`] always accepts.
+pub const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%:z";
+
+/// Which end of a day to pick when only the day is given: the start of a task's working period
+/// begins when the day does, but a task due on a day can be worked on until the day ends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+	Start,
+	End,
+}
+
+impl Side {
+	fn time(self) -> NaiveTime {
+		match self {
+			Self::Start => NaiveTime::MIN,
+			Self::End => NaiveTime::from_hms_opt(23, 59, 0).expect("Valid time"),
+		}
+	}
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum WhenError {
+	#[error("Couldn't understand {0:?} as a time")]
+	Unrecognized(String),
+	#[error("{0} doesn't exist in this timezone")]
+	Nonexistent(NaiveDateTime),
+}
+
+/// What the day part of the text means.
+enum Day {
+	Date(NaiveDate),
+	/// A date that stands for its end, whichever side was asked for.
+	EndOf(NaiveDate),
+	/// An exact time, which can't be combined with a time of day.
+	Exact(NaiveDateTime),
+}
+
+/// Work out the time that some text means, relative to `now` and in its timezone.
+/// Days without a time of day resolve to the start or end of the day, depending on `side`.
+pub fn parse<Tz: TimeZone>(
+	text: &str,
+	side: Side,
+	now: &DateTime<Tz>,
+) -> Result<DateTime<Tz>, WhenError> {
+	let unrecognized = || WhenError::Unrecognized(text.trim().to_string());
+	let timezone = now.timezone();
+	if let Ok(time) = DateTime::parse_from_str(text.trim(), TIME_FORMAT)
+		.or_else(|_| DateTime::parse_from_rfc3339(text.trim()))
+	{
+		return Ok(time.with_timezone(&timezone));
+	}
+
+	let now = now
+		.naive_local()
+		.with_nanosecond(0)
+		.unwrap_or(now.naive_local());
+	let lower = text.trim().to_lowercase();
+	let words = lower.split_whitespace().collect::<Vec<_>>();
+	let (day, time) = split_time(&words).ok_or_else(unrecognized)?;
+	let day = if day.is_empty() {
+		Day::Date(now.date())
+	} else {
+		parse_day(&day, now).ok_or_else(unrecognized)?
+	};
+	let resolved = match (day, time) {
+		(Day::Exact(_), Some(_)) => return Err(unrecognized()),
+		(Day::Exact(time), None) => time,
+		(Day::Date(date) | Day::EndOf(date), Some(time)) => date.and_time(time),
+		(Day::Date(date), None) => date.and_time(side.time()),
+		(Day::EndOf(date), None) => date.and_time(Side::End.time()),
+	};
+	timezone
+		.from_local_datetime(&resolved)
+		.earliest()
+		.ok_or(WhenError::Nonexistent(resolved))
+}
+
+/// Split the words into the day and the time of day, which comes last if it's there at all.
+fn split_time(words: &[&str]) -> Option<(String, Option<NaiveTime>)> {
+	for length in [2, 1] {
+		let Some(split) = words.len().checked_sub(length) else {
+			continue;
+		};
+		if let Some(time) = parse_time(&words[split..].concat()) {
+			let day = &words[..split];
+			let day = day.strip_suffix(&["at"]).unwrap_or(day);
+			return Some((day.join(" "), Some(time)));
+		}
+	}
+	if words.is_empty() {
+		return None;
+	}
+	Some((words.join(" "), None))
+}
+
+/// A time of day like "17:00", "5pm", "5:30 pm" or "noon".
+fn parse_time(text: &str) -> Option<NaiveTime> {
+	match text {
+		"noon" | "midday" => return NaiveTime::from_hms_opt(12, 0, 0),
+		"midnight" => return Some(NaiveTime::MIN),
+		_ => {}
+	}
+	let (text, offset) = if let Some(text) = text.strip_suffix("am") {
+		(text, Some(0))
+	} else if let Some(text) = text.strip_suffix("pm") {
+		(text, Some(12))
+	} else {
+		(text, None)
+	};
+	let (hour, minute) = text.split_once(':').unwrap_or((text, "0"));
+	let (mut hour, minute): (u32, u32) = (hour.parse().ok()?, minute.parse().ok()?);
+	if let Some(offset) = offset {
+		if !(1..=12).contains(&hour) {
+			return None;
+		}
+		hour = hour % 12 + offset;
+	} else if !text.contains(':') {
+		// A bare number is more likely a day of the month than an hour
+		return None;
+	}
+	NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+fn parse_day(text: &str, now: NaiveDateTime) -> Option<Day> {
+	let today = now.date();
+	let monday = today - TimeDelta::days(today.weekday().num_days_from_monday().into());
+	let first_of_month = today.with_day(1)?;
+	let day = match text {
+		"now" => Day::Exact(now),
+		"today" => Day::Date(today),
+		"tomorrow" => Day::Date(today.succ_opt()?),
+		"yesterday" => Day::Date(today.pred_opt()?),
+		"tonight" | "end of day" | "end of today" => Day::EndOf(today),
+		"next week" => Day::Date(monday + TimeDelta::weeks(1)),
+		"end of week" | "end of the week" => Day::EndOf(monday + TimeDelta::days(6)),
+		"next month" => Day::Date(first_of_month.checked_add_months(Months::new(1))?),
+		"end of month" | "end of the month" => Day::EndOf(
+			first_of_month
+				.checked_add_months(Months::new(1))?
+				.pred_opt()?,
+		),
+		_ => {
+			if let Some(rest) = text.strip_prefix("in ") {
+				return parse_offset(rest, now);
+			}
+			let weekday = text
+				.strip_prefix("next ")
+				.or_else(|| text.strip_prefix("on "))
+				.unwrap_or(text);
+			if let Ok(weekday) = weekday.parse::<Weekday>() {
+				// The next one after today, so "friday" on a Friday means a week later
+				let ahead = (weekday.num_days_from_monday() + 7
+					- today.weekday().num_days_from_monday())
+					% 7;
+				let ahead = if ahead == 0 { 7 } else { ahead };
+				return Some(Day::Date(today + TimeDelta::days(ahead.into())));
+			}
+			return parse_date(text, today).map(Day::Date);
+		}
+	};
+	Some(day)
+}
+
+/// An amount of time from now, like "3 days", "an hour" or "2 weeks".
+fn parse_offset(text: &str, now: NaiveDateTime) -> Option<Day> {
+	let (amount, unit) = text.split_once(' ').or_else(|| {
+		let split = text.find(|c: char| !c.is_ascii_digit())?;
+		Some(text.split_at(split))
+	})?;
+	let amount: u32 = match amount {
+		"a" | "an" | "one" => 1,
+		amount => amount.parse().ok()?,
+	};
+	let unit = unit.trim();
+	let unit = unit.strip_suffix('s').unwrap_or(unit);
+	// Amounts too big for a date are unrecognized, like any other nonsense
+	let time = match unit {
+		"m" | "min" | "minute" => now.checked_add_signed(TimeDelta::try_minutes(amount.into())?)?,
+		"h" | "hr" | "hour" => now.checked_add_signed(TimeDelta::try_hours(amount.into())?)?,
+		"d" | "day" => now.checked_add_signed(TimeDelta::try_days(amount.into())?)?,
+		"w" | "wk" | "week" => now.checked_add_signed(TimeDelta::try_weeks(amount.into())?)?,
+		"month" => now.checked_add_months(Months::new(amount))?,
+		_ => return None,
+	};
+	Some(Day::Exact(time))
+}
+
+/// A date written out, like "2026-10-23", "23 oct" or "october 23".
+/// Without a year, it's the next time that date comes round.
+fn parse_date(text: &str, today: NaiveDate) -> Option<NaiveDate> {
+	if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+		return Some(date);
+	}
+	["%d %B %Y", "%B %d %Y", "%d %b %Y", "%b %d %Y"]
+		.into_iter()
+		.find_map(|format| {
+			let date =
+				NaiveDate::parse_from_str(&format!("{text} {}", today.year()), format).ok()?;
+			if date < today {
+				date.with_year(today.year() + 1)
+			} else {
+				Some(date)
+			}
+		})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{parse, Side, WhenError};
+	use chrono::{FixedOffset, TimeZone, Timelike, Utc};
+
+	#[test]
+	fn parse_natural_times() {
+		let timezone = FixedOffset::east_opt(2 * 60 * 60).expect("Valid offset");
+		// A Wednesday afternoon
+		let now = timezone
+			.with_ymd_and_hms(2026, 10, 14, 15, 30, 12)
+			.single()
+			.expect("Valid time");
+		let at = |text: &str, side| {
+			parse(text, side, &now)
+				.unwrap_or_else(|e| panic!("{e}"))
+				.format("%a %Y-%m-%d %H:%M")
+				.to_string()
+		};
+		assert_eq!(at("now", Side::Start), "Wed 2026-10-14 15:30");
+		assert_eq!(at("tomorrow 17:00", Side::Start), "Thu 2026-10-15 17:00");
+		assert_eq!(at("Tomorrow at 5pm", Side::Start), "Thu 2026-10-15 17:00");
+		assert_eq!(at("tomorrow", Side::Start), "Thu 2026-10-15 00:00");
+		assert_eq!(at("friday", Side::End), "Fri 2026-10-16 23:59");
+		assert_eq!(at("wednesday 9:30 am", Side::End), "Wed 2026-10-21 09:30");
+		assert_eq!(at("in 3 days", Side::End), "Sat 2026-10-17 15:30");
+		assert_eq!(at("in 90m", Side::End), "Wed 2026-10-14 17:00");
+		assert_eq!(at("next week", Side::Start), "Mon 2026-10-19 00:00");
+		assert_eq!(at("end of month", Side::Start), "Sat 2026-10-31 23:59");
+		assert_eq!(at("end of week 17:00", Side::Start), "Sun 2026-10-18 17:00");
+		assert_eq!(at("2026-11-02 noon", Side::Start), "Mon 2026-11-02 12:00");
+		assert_eq!(at("3 march", Side::End), "Wed 2027-03-03 23:59");
+		assert_eq!(at("18:00", Side::Start), "Wed 2026-10-14 18:00");
+
+		let exact = parse("2026-10-20 09:00:00+00:00", Side::Start, &now).expect("Valid time");
+		assert_eq!(exact.with_timezone(&Utc).hour(), 9);
+		assert_eq!(exact.hour(), 11);
+
+		assert!(parse("someday", Side::Start, &now).is_err());
+		assert!(parse("in 3 fortnights", Side::Start, &now).is_err());
+		assert!(parse("in 3 days 17:00", Side::Start, &now).is_err());
+		assert!(parse("", Side::Start, &now).is_err());
+		assert_eq!(
+			parse("in 4000000000 days", Side::Start, &now),
+			Err(WhenError::Unrecognized("in 4000000000 days".into()))
+		);
+		assert!(parse("in 4000000000 weeks", Side::End, &now).is_err());
+		assert!(parse("in 4000000000 months", Side::End, &now).is_err());
+	}
+}